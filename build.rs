@@ -1,3 +0,0 @@
-fn main() {
-    println!("Welcome to develop czsc crates!");
-}
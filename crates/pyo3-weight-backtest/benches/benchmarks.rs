@@ -1,5 +1,4 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use flame;
 use polars::prelude::*;
 use std::hint::black_box;
 use weight_backtest_pyo3::config::BacktestConfig;
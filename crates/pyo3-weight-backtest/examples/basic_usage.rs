@@ -1,21 +1,5 @@
 use chrono::NaiveDate;
 use polars::prelude::*;
-use std::fmt::Debug;
-
-fn sort_dataframe(df: &DataFrame) -> PolarsResult<DataFrame> {
-    Ok(df
-        .clone()
-        .lazy()
-        .sort(
-            ["date"],
-            SortMultipleOptions {
-                descending: vec![false],
-                nulls_last: vec![true],
-                ..Default::default()
-            },
-        )
-        .collect()?)
-}
 
 fn main() {
     let df = df![
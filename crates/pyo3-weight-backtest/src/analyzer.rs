@@ -1,11 +1,23 @@
-use crate::config::{BacktestConfig, WeightType};
-use crate::errors::{CzscError, CzscResult};
-use crate::portfolio_builder::PortfolioMetricsBuilder;
-use crate::types::{DailyMetric, SymbolResult};
+use crate::config::{BacktestConfig, Locale, WeightType};
+use crate::errors::CzscResult;
+use crate::portfolio_builder::{PortfolioMetricsBuilder, PositionCounts};
+use crate::stats::{daily_performance, evaluate_pairs, DAILY_PERFORMANCE_KEYS};
+use crate::types::{
+    BacktestWarning, DailyMetric, Direction, PortfolioMetrics, SymbolResult, TradeEvaluation,
+    TradePair,
+};
+use log::debug;
 use polars::prelude::*;
 use polars_ops::pivot::pivot;
+use rayon::prelude::*;
 use std::collections::HashMap;
 
+/// `gen_daily_summary_df` 的列
+pub(crate) const DAILY_SUMMARY_COLUMNS: [&str; 11] = [
+    "date", "total", "strategy", "benchmark", "gross", "net", "long", "short", "long_count",
+    "short_count", "turnover",
+];
+
 enum AggType {
     Mean,
     Sum,
@@ -13,31 +25,29 @@ enum AggType {
 
 #[derive(Debug, Clone)]
 pub struct PortfolioAnalyzer<'a> {
-    config            : BacktestConfig,
-    symbol_results    : &'a HashMap<String, SymbolResult>,
-    df                : &'a DataFrame,
-    daily_df          : &'a DataFrame,
-    daily_ew_return_df: &'a DataFrame,
+    config         : BacktestConfig,
+    symbol_results : &'a HashMap<String, SymbolResult>,
+    position_counts: PositionCounts,
+    daily_summary  : &'a DataFrame,
 }
 
 impl<'a> PortfolioAnalyzer<'a> {
+    /// `daily_summary` 为 `gen_daily_summary_df` 格式的每日组合汇总
     pub fn new(
         config: BacktestConfig,
         symbol_results: &'a HashMap<String, SymbolResult>,
-        df: &'a DataFrame,
-        daily_df: &'a DataFrame,
-        daily_ew_return_df: &'a DataFrame,
+        position_counts: PositionCounts,
+        daily_summary: &'a DataFrame,
     ) -> Self {
         PortfolioAnalyzer {
             config,
             symbol_results,
-            df,
-            daily_df: &daily_df,
-            daily_ew_return_df: &daily_ew_return_df,
+            position_counts,
+            daily_summary,
         }
     }
 
-    fn to_daily_dateframe(metrics: &[&DailyMetric]) -> CzscResult<DataFrame> {
+    pub fn to_daily_dateframe(metrics: &[&DailyMetric]) -> CzscResult<DataFrame> {
         let mut dates = Vec::with_capacity(metrics.len());
         let mut symbols = Vec::with_capacity(metrics.len());
         let mut edges = Vec::with_capacity(metrics.len());
@@ -53,6 +63,12 @@ impl<'a> PortfolioAnalyzer<'a> {
         let mut short_costs = Vec::with_capacity(metrics.len());
         let mut short_returns = Vec::with_capacity(metrics.len());
         let mut short_turnovers = Vec::with_capacity(metrics.len());
+        let mut weights = Vec::with_capacity(metrics.len());
+        let mut long_exposures = Vec::with_capacity(metrics.len());
+        let mut short_exposures = Vec::with_capacity(metrics.len());
+        let mut long_bars = Vec::with_capacity(metrics.len());
+        let mut short_bars = Vec::with_capacity(metrics.len());
+        let mut vol_scales = Vec::with_capacity(metrics.len());
 
         for metric in metrics {
             dates.push(metric.date.clone());
@@ -70,6 +86,12 @@ impl<'a> PortfolioAnalyzer<'a> {
             short_costs.push(metric.short_cost);
             short_returns.push(metric.short_return);
             short_turnovers.push(metric.short_turnover);
+            weights.push(metric.weight);
+            long_exposures.push(metric.long_exposure);
+            short_exposures.push(metric.short_exposure);
+            long_bars.push(metric.long_bars);
+            short_bars.push(metric.short_bars);
+            vol_scales.push(metric.vol_scale);
         }
 
         Ok(df![
@@ -88,8 +110,136 @@ impl<'a> PortfolioAnalyzer<'a> {
             "short_cost" => short_costs,
             "short_return" => short_returns,
             "short_turnover" => short_turnovers,
+            "weight" => weights,
+            "long_exposure" => long_exposures,
+            "short_exposure" => short_exposures,
+            "long_bars" => long_bars,
+            "short_bars" => short_bars,
+            "vol_scale" => vol_scales,
+        ]?)
+    }
+    pub fn to_trade_pair_dataframe(pairs: &[&TradePair]) -> CzscResult<DataFrame> {
+        Ok(df![
+            "symbol" => pairs.iter().map(|p| p.symbol.as_str()).collect::<Vec<_>>(),
+            "direction" => pairs.iter().map(|p| p.direction.to_string()).collect::<Vec<_>>(),
+            "open_dt" => pairs.iter().map(|p| p.open_dt.as_str()).collect::<Vec<_>>(),
+            "close_dt" => pairs.iter().map(|p| p.close_dt.as_str()).collect::<Vec<_>>(),
+            "open_price" => pairs.iter().map(|p| p.open_price).collect::<Vec<_>>(),
+            "close_price" => pairs.iter().map(|p| p.close_price).collect::<Vec<_>>(),
+            "bar_count" => pairs.iter().map(|p| p.bar_count as u64).collect::<Vec<_>>(),
+            "event_sequence" => pairs.iter().map(|p| p.event_sequence.as_str()).collect::<Vec<_>>(),
+            "holding_days" => pairs.iter().map(|p| p.holding_days).collect::<Vec<_>>(),
+            "profit_ratio" => pairs.iter().map(|p| p.profit_ratio).collect::<Vec<_>>(),
         ]?)
     }
+
+    /// 每个品种一行的绩效表，按品种名排列：日收益绩效指标，以及全部、多头、空头交易对的统计
+    pub fn symbol_performance_df(
+        symbol_results: &HashMap<String, SymbolResult>,
+        yearly_days: usize,
+        locale: Locale,
+    ) -> CzscResult<DataFrame> {
+        const DIRECTIONS: [Direction; 3] = [Direction::LongShort, Direction::Long, Direction::Short];
+
+        let mut symbols: Vec<&String> = symbol_results.keys().collect();
+        symbols.sort();
+        let rows = symbols
+            .par_iter()
+            .map(|&symbol| {
+                let sr = &symbol_results[symbol];
+                let returns: Vec<f64> = sr.daily_metrics.iter().map(|m| m.return_val).collect();
+                performance_row(&returns, &sr.trade_pairs, &DIRECTIONS, yearly_days)
+            })
+            .collect::<CzscResult<Vec<Vec<f64>>>>()?;
+
+        let labels = symbols.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+        performance_table("symbol", labels, &DIRECTIONS, rows, locale)
+    }
+
+    /// 多空组合及多头、空头两条腿的日收益，按日期排列；汇总方式与 `daily_ew_return_df` 的 total 一致
+    pub fn gen_leg_return_df(
+        weight_type: WeightType,
+        symbol_count: usize,
+        daily_df: &DataFrame,
+    ) -> CzscResult<DataFrame> {
+        let scale = weight_type.portfolio_scale(symbol_count);
+        let leg = |name: &str, alias: &str| {
+            (col(name).sum() * lit(scale)).round(4, RoundMode::HalfAwayFromZero).alias(alias)
+        };
+
+        Ok(daily_df
+            .clone()
+            .lazy()
+            .group_by([col("date")])
+            .agg([
+                leg("return", "total"),
+                leg("long_return", "long"),
+                leg("short_return", "short"),
+            ])
+            .sort(["date"], SortMultipleOptions::default())
+            .collect()?)
+    }
+
+    /// 每日组合敞口与换手，按日期排列：总敞口、净敞口、多头敞口、空头敞口 (负数)、
+    /// 多空持仓品种数与换手；敞口与持仓品种数取交易日内全部 K 线的平均值，
+    /// 敞口与换手的汇总方式与组合收益一致
+    pub fn gen_exposure_df(
+        weight_type: WeightType,
+        symbol_count: usize,
+        daily_df: &DataFrame,
+    ) -> CzscResult<DataFrame> {
+        let scale = lit(weight_type.portfolio_scale(symbol_count));
+        let (long, short) = (|| col("long_exposure"), || col("short_exposure"));
+
+        Ok(daily_df
+            .clone()
+            .lazy()
+            .group_by([col("date")])
+            .agg([
+                ((long() - short()).sum() * scale.clone()).alias("gross"),
+                ((long() + short()).sum() * scale.clone()).alias("net"),
+                (long().sum() * scale.clone()).alias("long"),
+                (short().sum() * scale.clone()).alias("short"),
+                col("long_bars").sum().alias("long_count"),
+                col("short_bars").sum().alias("short_count"),
+                (col("turnover").sum() * scale).alias("turnover"),
+            ])
+            .sort(["date"], SortMultipleOptions::default())
+            .collect()?)
+    }
+
+    /// 多空、多头、空头三行的绩效表：各自日收益的绩效指标，以及对应方向交易对的统计
+    pub fn leg_performance_df(
+        leg_return_df: &DataFrame,
+        symbol_results: &HashMap<String, SymbolResult>,
+        yearly_days: usize,
+        locale: Locale,
+    ) -> CzscResult<DataFrame> {
+        const LEGS: [(Direction, &str, &str, &str); 3] = [
+            (Direction::LongShort, "total", "多空", "long_short"),
+            (Direction::Long,      "long",  "多头", "long"),
+            (Direction::Short,     "short", "空头", "short"),
+        ];
+
+        let pairs: Vec<TradePair> =
+            symbol_results.values().flat_map(|sr| sr.trade_pairs.iter().cloned()).collect();
+        let mut rows = Vec::with_capacity(LEGS.len());
+        for (direction, column, _, _) in LEGS {
+            let returns: Vec<f64> =
+                leg_return_df.column(column)?.f64()?.into_iter().flatten().collect();
+            rows.push(performance_row(&returns, &pairs, &[direction], yearly_days)?);
+        }
+
+        let labels = LEGS
+            .iter()
+            .map(|(_, _, zh, en)| match locale {
+                Locale::Zh => *zh,
+                Locale::En => *en,
+            })
+            .collect::<Vec<_>>();
+        performance_table("leg", labels, &[Direction::LongShort], rows, locale)
+    }
+
     pub fn gen_daily_metric_df(symbol_results: &HashMap<String, SymbolResult>) -> DataFrame {
         let all_daily_metrics: Vec<&DailyMetric> =
             symbol_results.values().flat_map(|r| &r.daily_metrics).collect();
@@ -123,7 +273,7 @@ impl<'a> PortfolioAnalyzer<'a> {
         )?
         .fill_null(FillNullStrategy::Zero)?;
 
-        println!("finish pivot");
+        debug!("daily return pivot finished: rows={}, columns={}", dret_df.height(), dret_df.width());
 
         let symbols = symbol_results.keys().map(|s| s.as_str()).collect::<Vec<&str>>();
 
@@ -134,10 +284,9 @@ impl<'a> PortfolioAnalyzer<'a> {
             WeightType::CrossSection => {
                 Self::add_agg_column(dret_df.lazy(), &symbols, AggType::Sum)?
             }
-            _ => return Err(CzscError::InvalidWeightType(weight_type).into()),
         };
 
-        println!("finish dret_lf");
+        debug!("daily return aggregate column added: weight_type={:?}", weight_type);
         /*let r = dret_lf
             .select(&[
                 col("date"),
@@ -155,7 +304,6 @@ impl<'a> PortfolioAnalyzer<'a> {
         // 分批处理列 (每批 50 列)
         let mut current_lf = dret_lf.clone();
         for chunk in non_date_cols.chunks(500) { // 每批处理50列
-            // println!(" Processing chunk: {:?}", chunk);
             let exprs: Vec<Expr> = chunk.iter()
                 .map(|col_name|
                     col(col_name.to_string()).round(4, RoundMode::HalfAwayFromZero).alias(col_name.to_string())
@@ -165,24 +313,55 @@ impl<'a> PortfolioAnalyzer<'a> {
             current_lf = current_lf.with_columns(&exprs);
         }
 
-        println!("finish chunk processing");
+        debug!("daily return rounding planned: columns={}", non_date_cols.len());
 
         // 准备选择所有需要的列
+        // 透视表按日期首次出现的顺序排列，某品种缺少的日期会排到后面，编号前按日期排序
         current_lf = current_lf
             .with_column(col("date"))
+            .sort(["date"], SortMultipleOptions::default())
             .with_row_index("idx", Some(0));
-        println!("finish row idx processing");
 
         let r = current_lf.collect()?;
-        println!("finish dret_r");
+        debug!("daily return frame collected: rows={}", r.height());
         Ok(r)
     }
-    pub fn analyze_portfolio_metrics(&self) -> CzscResult<HashMap<String, f64>> {
+
+    /// 每日组合汇总，按日期排列：组合收益 total、品种平均收益 strategy 与平均基准收益 benchmark，
+    /// 以及 `gen_exposure_df` 的敞口与换手列；组合指标只依赖该汇总与交易对
+    pub fn gen_daily_summary_df(
+        weight_type: WeightType,
+        symbol_count: usize,
+        daily_df: &DataFrame,
+        daily_ew_return_df: &DataFrame,
+    ) -> CzscResult<DataFrame> {
+        let means = daily_df
+            .clone()
+            .lazy()
+            .group_by([col("date")])
+            .agg([
+                col("return").mean().alias("strategy"),
+                col("n1b").mean().alias("benchmark"),
+            ]);
+        let total = daily_ew_return_df.clone().lazy().select([col("date"), col("total")]);
+        let exposure = Self::gen_exposure_df(weight_type, symbol_count, daily_df)?;
+
+        Ok(exposure
+            .lazy()
+            .join(means, [col("date")], [col("date")], JoinArgs::new(JoinType::Inner))
+            .join(total, [col("date")], [col("date")], JoinArgs::new(JoinType::Inner))
+            .select(DAILY_SUMMARY_COLUMNS.map(col))
+            .sort(["date"], SortMultipleOptions::default())
+            .collect()?)
+    }
+
+    pub fn analyze_portfolio_metrics(
+        &self,
+    ) -> CzscResult<(HashMap<String, f64>, PortfolioMetrics, Vec<BacktestWarning>)> {
         let metrics = PortfolioMetricsBuilder::new(
             &self.config,
-            &self.df,
-            &self.daily_df,
-            &self.daily_ew_return_df,
+            self.position_counts,
+            self.daily_summary,
             self.symbol_results,
         )
         .add_basic_metrics()?
@@ -190,7 +369,57 @@ impl<'a> PortfolioAnalyzer<'a> {
         .add_long_short_metrics()?
         .add_benchmark_correlations()?
         .add_portfolio_return_metrics()?
+        .add_turnover_metrics()?
+        .add_exposure_metrics()?
         .build();
         Ok(metrics)
     }
 }
+
+/// 一行绩效数据：日收益的绩效指标，之后依次为各方向交易对的统计
+pub(crate) fn performance_row(
+    returns: &[f64],
+    pairs: &[TradePair],
+    directions: &[Direction],
+    yearly_days: usize,
+) -> CzscResult<Vec<f64>> {
+    let (perf, _) = daily_performance(returns, Some(yearly_days as f64));
+    let mut row: Vec<f64> = DAILY_PERFORMANCE_KEYS.iter().map(|k| perf[k]).collect();
+    for &direction in directions {
+        let stats = evaluate_pairs(pairs, direction)?;
+        row.extend(stats.columns().iter().map(|(_, value)| *value));
+    }
+    Ok(row)
+}
+
+/// 由 `performance_row` 生成的各行组成绩效表，首列为行标签；多头、空头交易统计的列名带方向前缀
+pub(crate) fn performance_table(
+    label: &str,
+    labels: Vec<&str>,
+    directions: &[Direction],
+    rows: Vec<Vec<f64>>,
+    locale: Locale,
+) -> CzscResult<DataFrame> {
+    let mut names: Vec<String> =
+        DAILY_PERFORMANCE_KEYS.iter().map(|k| k.label(locale).to_string()).collect();
+    for direction in directions {
+        let (zh_prefix, en_prefix) = match direction {
+            Direction::LongShort => ("", ""),
+            Direction::Long => ("多头", "long_"),
+            Direction::Short => ("空头", "short_"),
+        };
+        for (key, _) in TradeEvaluation::default().columns() {
+            names.push(match locale {
+                Locale::Zh => format!("{}{}", zh_prefix, key.as_str()),
+                Locale::En => format!("{}{}", en_prefix, key.as_en_str()),
+            });
+        }
+    }
+
+    let mut columns = vec![Column::new(label.into(), labels)];
+    for (i, name) in names.iter().enumerate() {
+        let values: Vec<f64> = rows.iter().map(|row| row[i]).collect();
+        columns.push(Column::new(name.into(), values));
+    }
+    Ok(DataFrame::new(columns)?)
+}
@@ -0,0 +1,139 @@
+use crate::errors::{CzscError, CzscResult};
+use polars::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+
+/// 参与归因的日指标列；成本取净收益与毛收益之差（负数），即实际计入净收益的成本，
+/// 使 edge + cost = return（品种最后一根 K 线没有下一期价格，其成本不计入收益）
+pub const COMPONENTS: [&str; 9] = [
+    "edge",
+    "cost",
+    "return",
+    "long_edge",
+    "long_cost",
+    "long_return",
+    "short_edge",
+    "short_cost",
+    "short_return",
+];
+
+/// 分期归因的周期
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Year,
+    Month,
+}
+
+impl Period {
+    /// `date` 列 ("%Y-%m-%d") 中代表该周期的前缀长度
+    fn prefix_len(&self) -> u64 {
+        match self {
+            Period::Year => 4,
+            Period::Month => 7,
+        }
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Period::Year => write!(f, "year"),
+            Period::Month => write!(f, "month"),
+        }
+    }
+}
+
+impl FromStr for Period {
+    type Err = CzscError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "year" => Ok(Period::Year),
+            "month" => Ok(Period::Month),
+            _ => Err(CzscError::Validation(format!(
+                "Invalid period {:?}, must be 'year' or 'month'",
+                s
+            ))),
+        }
+    }
+}
+
+/// 各归因项的组合贡献之和
+fn contribution_exprs(scale: f64) -> Vec<Expr> {
+    COMPONENTS
+        .iter()
+        .map(|&name| {
+            let sum = match name.strip_suffix("cost") {
+                Some(leg) => {
+                    col(format!("{}return", leg)).sum() - col(format!("{}edge", leg)).sum()
+                }
+                None => col(name).sum(),
+            };
+            (sum * lit(scale)).alias(name)
+        })
+        .collect()
+}
+
+/// 组合总收益按毛收益/成本、多头/空头拆分，每项一行 (component, contribution, share)；
+/// share 为占净收益的比例
+pub fn attribution_summary(daily_df: &DataFrame, scale: f64) -> CzscResult<DataFrame> {
+    let totals = daily_df.clone().lazy().select(contribution_exprs(scale)).collect()?;
+
+    let mut contributions = Vec::with_capacity(COMPONENTS.len());
+    for name in COMPONENTS {
+        contributions.push(totals.column(name)?.f64()?.get(0).unwrap_or(0.0));
+    }
+    let total = totals.column("return")?.f64()?.get(0).unwrap_or(0.0);
+    let shares: Vec<f64> = contributions
+        .iter()
+        .map(|c| if total != 0.0 { c / total } else { f64::NAN })
+        .collect();
+
+    Ok(df![
+        "component" => COMPONENTS,
+        "contribution" => contributions,
+        "share" => shares,
+    ]?)
+}
+
+/// 各品种对组合收益的贡献，按净收益贡献从高到低排列；
+/// 指定 `top_n` 时只保留贡献最高与最低的各 `top_n` 个品种
+pub fn symbol_attribution(
+    daily_df: &DataFrame,
+    scale: f64,
+    top_n: Option<usize>,
+) -> CzscResult<DataFrame> {
+    let total: f64 = daily_df.column("return")?.f64()?.sum().unwrap_or(0.0) * scale;
+    let df = daily_df
+        .clone()
+        .lazy()
+        .group_by([col("symbol")])
+        .agg(contribution_exprs(scale))
+        .with_column(
+            (col("return") / lit(if total != 0.0 { total } else { f64::NAN })).alias("share"),
+        )
+        .sort_by_exprs(
+            [col("return"), col("symbol")],
+            SortMultipleOptions::default().with_order_descending_multi([true, false]),
+        )
+        .collect()?;
+
+    match top_n {
+        Some(n) if 2 * n < df.height() => {
+            Ok(df.head(Some(n)).vstack(&df.tail(Some(n)))?)
+        }
+        _ => Ok(df),
+    }
+}
+
+/// 按年或按月汇总的组合收益贡献，period 列形如 "2024" 或 "2024-01"，按时间排列
+pub fn period_attribution(daily_df: &DataFrame, scale: f64, period: Period) -> CzscResult<DataFrame> {
+    Ok(daily_df
+        .clone()
+        .lazy()
+        .with_column(col("date").str().slice(lit(0), lit(period.prefix_len())).alias("period"))
+        .group_by([col("period")])
+        .agg(contribution_exprs(scale))
+        .sort(["period"], SortMultipleOptions::default())
+        .collect()?)
+}
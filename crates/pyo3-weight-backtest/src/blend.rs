@@ -0,0 +1,334 @@
+use crate::config::Locale;
+use crate::engine::BacktestResult;
+use crate::errors::CzscError::Validation;
+use crate::errors::CzscResult;
+use crate::stats::daily_performance;
+use crate::utils::standard_deviation;
+use polars::prelude::*;
+use pyo3::pyclass;
+use pyo3_polars::PyDataFrame;
+use std::collections::HashMap;
+
+/// 等风险权重迭代次数
+const RISK_PARITY_ITERATIONS: usize = 200;
+
+/// 多策略组合的权重构建方式；滚动方式在第 t 天只使用此前 `window` 天的收益，
+/// 数据不足一个窗口时使用等权
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlendMethod {
+    /// 固定权重，按策略顺序给出
+    Fixed(Vec<f64>),
+    /// 波动率倒数加权
+    InverseVolatility { window: usize },
+    /// 等风险贡献，考虑策略间的相关性
+    EqualRisk { window: usize },
+    /// 滚动最大夏普：权重与 Σ⁻¹μ 成正比，只做多，负权重置零
+    MaxSharpe { window: usize },
+}
+
+impl BlendMethod {
+    /// 由方法名与参数构建，方法名为 "fixed"、"inverse_vol"、"equal_risk" 或 "max_sharpe"
+    pub fn from_parts(method: &str, weights: Option<Vec<f64>>, window: usize) -> CzscResult<Self> {
+        let method = match method.to_lowercase().as_str() {
+            "fixed" => BlendMethod::Fixed(weights.ok_or_else(|| {
+                Validation("fixed blend requires weights".to_string())
+            })?),
+            "inverse_vol" => BlendMethod::InverseVolatility { window },
+            "equal_risk" => BlendMethod::EqualRisk { window },
+            "max_sharpe" => BlendMethod::MaxSharpe { window },
+            _ => {
+                return Err(Validation(format!(
+                    "Invalid blend method {:?}, must be 'fixed', 'inverse_vol', 'equal_risk' or 'max_sharpe'",
+                    method
+                )))
+            }
+        };
+        Ok(method)
+    }
+
+    fn validate(&self, n: usize) -> CzscResult<()> {
+        match self {
+            BlendMethod::Fixed(weights) => {
+                if weights.len() != n {
+                    return Err(Validation(format!(
+                        "fixed blend needs {} weights, got {}",
+                        n,
+                        weights.len()
+                    )));
+                }
+                if weights.iter().any(|w| !w.is_finite()) {
+                    return Err(Validation("fixed blend weights must be finite".to_string()));
+                }
+            }
+            BlendMethod::InverseVolatility { window }
+            | BlendMethod::EqualRisk { window }
+            | BlendMethod::MaxSharpe { window } => {
+                if *window < 2 {
+                    return Err(Validation(format!("blend window must be at least 2, got {}", window)));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 多策略组合结果
+#[pyclass]
+#[derive(Clone)]
+pub struct BlendResult {
+    /// 各策略与组合的日收益 (date, 各策略, blend)
+    #[pyo3(get)] pub returns_df    : PyDataFrame,
+    /// 每日各策略的权重 (date, 各策略)
+    #[pyo3(get)] pub weights_df    : PyDataFrame,
+    /// 策略日收益的相关系数矩阵，首列为 strategy
+    #[pyo3(get)] pub correlation_df: PyDataFrame,
+    /// 组合日收益的绩效指标，键语言由 locale 决定
+    #[pyo3(get)] pub metrics       : HashMap<String, f64>,
+}
+
+/// 以各回测结果的组合日收益 (`daily_ew_return_df` 的 total 列) 构建多策略组合
+pub fn blend_results(
+    results: &[(&str, &BacktestResult)],
+    method: &BlendMethod,
+) -> CzscResult<BlendResult> {
+    let (_, first) = results
+        .first()
+        .ok_or_else(|| Validation("at least one strategy is required".to_string()))?;
+    let series = results
+        .iter()
+        .map(|(name, r)| Ok((name.to_string(), r.daily_ew_return_df.0.select(["date", "total"])?)))
+        .collect::<CzscResult<Vec<_>>>()?;
+    blend_returns(&series, method, first.config.yearly_days, first.config.locale)
+}
+
+/// 按日期对齐各策略的日收益 (date, total)，只保留全部策略都有收益的日期，按 `method` 构建组合
+pub fn blend_returns(
+    series: &[(String, DataFrame)],
+    method: &BlendMethod,
+    yearly_days: usize,
+    locale: Locale,
+) -> CzscResult<BlendResult> {
+    if series.is_empty() {
+        return Err(Validation("at least one strategy is required".to_string()));
+    }
+    method.validate(series.len())?;
+    let names: Vec<&str> = series.iter().map(|(name, _)| name.as_str()).collect();
+    let (dates, returns) = align_returns(series)?;
+
+    // 逐日计算权重与组合收益，returns[i][t] 为第 i 个策略第 t 天的收益
+    let n = returns.len();
+    let days = dates.len();
+    let mut weights = vec![Vec::with_capacity(days); n];
+    let mut blended = Vec::with_capacity(days);
+    for t in 0..days {
+        let w = blend_weights(method, &returns, t);
+        blended.push((0..n).map(|i| w[i] * returns[i][t]).sum::<f64>());
+        for i in 0..n {
+            weights[i].push(w[i]);
+        }
+    }
+
+    let mut return_columns = vec![Column::new("date".into(), dates.clone())];
+    let mut weight_columns = vec![Column::new("date".into(), dates)];
+    for i in 0..n {
+        return_columns.push(Column::new(names[i].into(), returns[i].clone()));
+        weight_columns.push(Column::new(names[i].into(), weights[i].clone()));
+    }
+    return_columns.push(Column::new("blend".into(), blended.clone()));
+
+    let (perf, _) = daily_performance(&blended, Some(yearly_days as f64));
+    let metrics = perf.into_iter().map(|(k, v)| (k.label(locale).to_string(), v)).collect();
+
+    Ok(BlendResult {
+        returns_df    : PyDataFrame(DataFrame::new(return_columns)?),
+        weights_df    : PyDataFrame(DataFrame::new(weight_columns)?),
+        correlation_df: PyDataFrame(correlation_matrix(&names, &returns)?),
+        metrics,
+    })
+}
+
+/// 按日期内连接各策略收益，返回排序后的共同日期与每个策略的收益序列
+fn align_returns(series: &[(String, DataFrame)]) -> CzscResult<(Vec<String>, Vec<Vec<f64>>)> {
+    let mut aligned: Option<LazyFrame> = None;
+    for (i, (_, df)) in series.iter().enumerate() {
+        let lf = df
+            .clone()
+            .lazy()
+            .select([col("date"), col("total").cast(DataType::Float64).alias(format!("s{}", i))]);
+        aligned = Some(match aligned {
+            None => lf,
+            Some(acc) => acc.join(
+                lf,
+                [col("date")],
+                [col("date")],
+                JoinArgs::new(JoinType::Inner),
+            ),
+        });
+    }
+    let df = aligned
+        .unwrap()
+        .sort(["date"], SortMultipleOptions::default())
+        .collect()?;
+    if df.is_empty() {
+        return Err(Validation("strategies share no common dates".to_string()));
+    }
+
+    let dates = df.column("date")?.str()?.into_no_null_iter().map(|d| d.to_string()).collect();
+    let returns = (0..series.len())
+        .map(|i| {
+            Ok(df
+                .column(&format!("s{}", i))?
+                .f64()?
+                .into_iter()
+                .map(|v| v.filter(|x| x.is_finite()).unwrap_or(0.0))
+                .collect())
+        })
+        .collect::<CzscResult<Vec<Vec<f64>>>>()?;
+    Ok((dates, returns))
+}
+
+/// 第 t 天的策略权重，滚动方式只使用 [t - window, t) 的收益
+fn blend_weights(method: &BlendMethod, returns: &[Vec<f64>], t: usize) -> Vec<f64> {
+    let n = returns.len();
+    let equal = vec![1.0 / n as f64; n];
+    let window = match method {
+        BlendMethod::Fixed(weights) => return weights.clone(),
+        BlendMethod::InverseVolatility { window }
+        | BlendMethod::EqualRisk { window }
+        | BlendMethod::MaxSharpe { window } => *window,
+    };
+    if t < window {
+        return equal;
+    }
+    let history: Vec<&[f64]> = returns.iter().map(|r| &r[t - window..t]).collect();
+
+    let weights = match method {
+        BlendMethod::InverseVolatility { .. } => Some(inverse_volatility(&history)),
+        BlendMethod::EqualRisk { .. } => equal_risk(&history),
+        BlendMethod::MaxSharpe { .. } => max_sharpe(&history),
+        BlendMethod::Fixed(_) => unreachable!(),
+    };
+    weights.and_then(normalize).unwrap_or(equal)
+}
+
+/// 权重归一化为和为 1，无法归一化时返回 None
+fn normalize(weights: Vec<f64>) -> Option<Vec<f64>> {
+    let total: f64 = weights.iter().sum();
+    if total > 0.0 && total.is_finite() {
+        Some(weights.into_iter().map(|w| w / total).collect())
+    } else {
+        None
+    }
+}
+
+/// 波动率倒数，无波动的策略权重为 0
+fn inverse_volatility(history: &[&[f64]]) -> Vec<f64> {
+    history
+        .iter()
+        .map(|r| {
+            let std = standard_deviation(r);
+            if std > 0.0 { 1.0 / std } else { 0.0 }
+        })
+        .collect()
+}
+
+/// 等风险贡献：从波动率倒数出发，按 w_i ← w_i · sqrt(平均风险贡献 / 风险贡献_i) 迭代
+fn equal_risk(history: &[&[f64]]) -> Option<Vec<f64>> {
+    let cov = covariance(history);
+    let mut w = normalize(inverse_volatility(history))?;
+    for _ in 0..RISK_PARITY_ITERATIONS {
+        let marginal: Vec<f64> =
+            (0..w.len()).map(|i| (0..w.len()).map(|j| cov[i][j] * w[j]).sum()).collect();
+        let contributions: Vec<f64> = w.iter().zip(&marginal).map(|(w, m)| w * m).collect();
+        let target = contributions.iter().sum::<f64>() / w.len() as f64;
+        for i in 0..w.len() {
+            if w[i] > 0.0 {
+                if contributions[i] <= 0.0 {
+                    return None;
+                }
+                w[i] *= (target / contributions[i]).sqrt();
+            }
+        }
+        w = normalize(w)?;
+    }
+    Some(w)
+}
+
+/// 最大夏普：解 (Σ + λI) x = μ，负权重置零
+fn max_sharpe(history: &[&[f64]]) -> Option<Vec<f64>> {
+    let n = history.len();
+    let mut cov = covariance(history);
+    let ridge = (0..n).map(|i| cov[i][i]).sum::<f64>() / n as f64 * 1e-6;
+    for (i, row) in cov.iter_mut().enumerate() {
+        row[i] += ridge;
+    }
+    let mean: Vec<f64> =
+        history.iter().map(|r| r.iter().sum::<f64>() / r.len() as f64).collect();
+    let x = solve(cov, mean)?;
+    Some(x.into_iter().map(|v| v.max(0.0)).collect())
+}
+
+/// 样本协方差矩阵 (总体口径，与 `standard_deviation` 一致)
+fn covariance(history: &[&[f64]]) -> Vec<Vec<f64>> {
+    let n = history.len();
+    let len = history[0].len() as f64;
+    let means: Vec<f64> = history.iter().map(|r| r.iter().sum::<f64>() / len).collect();
+    let mut cov = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in i..n {
+            let c = history[i]
+                .iter()
+                .zip(history[j])
+                .map(|(a, b)| (a - means[i]) * (b - means[j]))
+                .sum::<f64>()
+                / len;
+            cov[i][j] = c;
+            cov[j][i] = c;
+        }
+    }
+    cov
+}
+
+/// 列主元高斯消元解线性方程组，矩阵奇异时返回 None
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for k in 0..n {
+        let pivot = (k..n).max_by(|&i, &j| a[i][k].abs().total_cmp(&a[j][k].abs()))?;
+        if a[pivot][k].abs() < 1e-18 {
+            return None;
+        }
+        a.swap(k, pivot);
+        b.swap(k, pivot);
+        let pivot_row = a[k].clone();
+        for i in k + 1..n {
+            let factor = a[i][k] / pivot_row[k];
+            for (x, p) in a[i][k..].iter_mut().zip(&pivot_row[k..]) {
+                *x -= factor * p;
+            }
+            b[i] -= factor * b[k];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for k in (0..n).rev() {
+        let tail: f64 = (k + 1..n).map(|j| a[k][j] * x[j]).sum();
+        x[k] = (b[k] - tail) / a[k][k];
+    }
+    Some(x)
+}
+
+/// 策略日收益的皮尔逊相关系数矩阵，无波动的策略相关系数为 NaN
+fn correlation_matrix(names: &[&str], returns: &[Vec<f64>]) -> CzscResult<DataFrame> {
+    let history: Vec<&[f64]> = returns.iter().map(|r| r.as_slice()).collect();
+    let cov = covariance(&history);
+    let mut columns = vec![Column::new("strategy".into(), names.to_vec())];
+    for j in 0..names.len() {
+        let values: Vec<f64> = (0..names.len())
+            .map(|i| {
+                let denom = (cov[i][i] * cov[j][j]).sqrt();
+                if denom > 0.0 { cov[i][j] / denom } else { f64::NAN }
+            })
+            .collect();
+        columns.push(Column::new(names[j].into(), values));
+    }
+    Ok(DataFrame::new(columns)?)
+}
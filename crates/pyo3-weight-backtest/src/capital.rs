@@ -0,0 +1,298 @@
+use crate::config::{Locale, WeightType};
+use crate::errors::CzscError::Validation;
+use crate::errors::CzscResult;
+use crate::stats::daily_performance;
+use polars::prelude::*;
+use pyo3::{pyclass, pymethods};
+use pyo3_polars::PyDataFrame;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// 资金模拟参数：以初始资金按整手买卖，跟踪现金、持仓、净值与手续费
+#[pyclass]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CapitalConfig {
+    /// 初始资金
+    #[pyo3(get, set)] pub initial_capital: f64,
+    /// 默认每手股数
+    #[pyo3(get, set)] pub lot_size       : u64,
+    /// 按品种指定的每手股数，如 A 股为 100
+    #[pyo3(get, set)] pub lot_sizes      : HashMap<String, u64>,
+}
+
+impl Default for CapitalConfig {
+    fn default() -> Self {
+        CapitalConfig {
+            initial_capital: 1_000_000.0,
+            lot_size       : 1,
+            lot_sizes      : HashMap::new(),
+        }
+    }
+}
+
+#[pymethods]
+impl CapitalConfig {
+    #[new]
+    #[pyo3(signature = (initial_capital=1_000_000.0, lot_size=1, lot_sizes=None))]
+    pub fn py_new(
+        initial_capital: f64,
+        lot_size: u64,
+        lot_sizes: Option<HashMap<String, u64>>,
+    ) -> Self {
+        CapitalConfig { initial_capital, lot_size, lot_sizes: lot_sizes.unwrap_or_default() }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+impl CapitalConfig {
+    /// 检查各参数的取值范围
+    pub fn validate(&self) -> CzscResult<()> {
+        if !self.initial_capital.is_finite() || self.initial_capital <= 0.0 {
+            return Err(Validation(format!(
+                "capital.initial_capital must be a positive number, got {}",
+                self.initial_capital
+            )));
+        }
+        if self.lot_size == 0 || self.lot_sizes.values().any(|&lot| lot == 0) {
+            return Err(Validation("capital lot sizes must be positive".to_string()));
+        }
+        Ok(())
+    }
+
+    fn lot(&self, symbol: &str) -> i64 {
+        self.lot_sizes.get(symbol).copied().unwrap_or(self.lot_size) as i64
+    }
+}
+
+/// 资金模拟结果
+#[pyclass]
+#[derive(Clone)]
+pub struct CapitalResult {
+    /// 每个交易日收盘后的资金状况 (date, nav, cash, market_value, fees, cum_fees,
+    /// weight_nav, tracking, cash_ratio, rounding_gap)
+    #[pyo3(get)] pub nav_df : PyDataFrame,
+    /// 资金净值日收益的绩效指标，键语言由 locale 决定
+    #[pyo3(get)] pub metrics: HashMap<String, f64>,
+}
+
+impl CapitalResult {
+    /// 由净值表计算绩效指标，首日收益相对初始资金计算
+    pub(crate) fn from_nav_df(
+        nav_df: DataFrame,
+        initial_capital: f64,
+        yearly_days: usize,
+        locale: Locale,
+    ) -> CzscResult<Self> {
+        let nav: Vec<f64> = nav_df.column("nav")?.f64()?.into_no_null_iter().collect();
+        let returns: Vec<f64> = std::iter::once(initial_capital)
+            .chain(nav.iter().copied())
+            .collect::<Vec<_>>()
+            .windows(2)
+            .map(|w| w[1] / w[0] - 1.0)
+            .collect();
+        let (perf, _) = daily_performance(&returns, Some(yearly_days as f64));
+        let metrics = perf.into_iter().map(|(k, v)| (k.label(locale).to_string(), v)).collect();
+        Ok(CapitalResult { nav_df: PyDataFrame(nav_df), metrics })
+    }
+}
+
+/// 单个品种按时间排序的 K 线，只保留资金模拟需要的字段
+pub(crate) struct CapitalBars {
+    symbol    : String,
+    dt        : Vec<i64>,
+    weight    : Vec<f64>,
+    price     : Vec<f64>,
+    trade_date: Vec<i32>,
+}
+
+impl CapitalBars {
+    /// 由处理后的品种 K 线 (dt, weight, price, trade_date) 构建
+    pub(crate) fn from_df(symbol: &str, df: &DataFrame) -> CzscResult<Self> {
+        Ok(CapitalBars {
+            symbol    : symbol.to_string(),
+            dt        : df.column("dt")?.datetime()?.physical().into_no_null_iter().collect(),
+            weight    : df.column("weight")?.f64()?.into_iter().map(|w| w.unwrap_or(0.0)).collect(),
+            price     : df.column("price")?.f64()?.into_iter().map(|p| p.unwrap_or(f64::NAN)).collect(),
+            trade_date: df.column("trade_date")?.date()?.physical().into_no_null_iter().collect(),
+        })
+    }
+}
+
+/// 单个品种的持仓
+#[derive(Default)]
+struct Holding {
+    shares: i64,
+    price : f64,
+    /// 最近一次的目标权重（已按组合口径缩放）
+    target: f64,
+}
+
+/// 按时间顺序逐时刻归并回放各品种的 K 线：每根 K 线以当时净值计算目标市值，
+/// 按收盘价换算为整手持仓并调仓，手续费按成交金额计；时序策略每个品种分得 1 / 品种数 的净值
+///
+/// `daily_returns` 为权重口径的组合日收益 (date, total)，用于对比：权重口径净值使用截至前一交易日的
+/// 组合收益，与按收盘价盯市的资金净值对齐（日线数据时完全对应）
+pub(crate) fn simulate_capital(
+    mut bars: Vec<CapitalBars>,
+    config: &CapitalConfig,
+    weight_type: WeightType,
+    fee_rate: f64,
+    daily_returns: &DataFrame,
+) -> CzscResult<DataFrame> {
+    if bars.is_empty() {
+        return Err(Validation("No bars for capital simulation".to_string()));
+    }
+    let scale = weight_type.portfolio_scale(bars.len());
+    // 同一时刻按品种名依次调仓
+    bars.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+    // 各品种的下一根 K 线，按 (dt, 品种序号) 取最早者
+    let mut cursors: BinaryHeap<Reverse<(i64, usize)>> = bars
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| !b.dt.is_empty())
+        .map(|(i, b)| Reverse((b.dt[0], i)))
+        .collect();
+    let mut positions = vec![0usize; bars.len()];
+    let mut holdings: Vec<Holding> = bars.iter().map(|_| Holding::default()).collect();
+    let mut cash = config.initial_capital;
+    let mut day_fees = 0.0;
+    let mut rows = NavRows::default();
+    let mut batch = Vec::new();
+
+    while let Some(&Reverse((current, _))) = cursors.peek() {
+        // 取出同一时刻的全部 K 线，先按最新价格盯市，再依次调仓
+        batch.clear();
+        while let Some(&Reverse((dt, i))) = cursors.peek() {
+            if dt != current {
+                break;
+            }
+            cursors.pop();
+            let pos = positions[i];
+            batch.push((i, pos));
+            positions[i] += 1;
+            if let Some(&next) = bars[i].dt.get(pos + 1) {
+                cursors.push(Reverse((next, i)));
+            }
+        }
+        for &(i, pos) in &batch {
+            let price = bars[i].price[pos];
+            if !price.is_nan() {
+                holdings[i].price = price;
+            }
+        }
+        let nav = cash + market_value(&holdings);
+        for &(i, pos) in &batch {
+            let lot = config.lot(&bars[i].symbol);
+            let holding = &mut holdings[i];
+            holding.target = bars[i].weight[pos] * scale;
+            let target_shares = if nav > 0.0 && holding.price > 0.0 {
+                (holding.target * nav / (holding.price * lot as f64)).trunc() as i64 * lot
+            } else {
+                0
+            };
+            let traded = target_shares - holding.shares;
+            if traded != 0 {
+                let fee = traded.abs() as f64 * holding.price * fee_rate;
+                cash -= traded as f64 * holding.price + fee;
+                day_fees += fee;
+                holding.shares = target_shares;
+            }
+        }
+
+        // 交易日最后一个时刻收盘后记录资金状况
+        let (i, pos) = batch[0];
+        let date = bars[i].trade_date[pos];
+        let day_end = match cursors.peek() {
+            Some(&Reverse((_, j))) => bars[j].trade_date[positions[j]] != date,
+            None => true,
+        };
+        if day_end {
+            let value = market_value(&holdings);
+            let nav = cash + value;
+            let rounding_gap: f64 = holdings
+                .iter()
+                .map(|h| (h.target - h.shares as f64 * h.price / nav).abs())
+                .sum();
+            rows.push(date, nav, cash, value, day_fees, rounding_gap);
+            day_fees = 0.0;
+        }
+    }
+
+    rows.into_df(config.initial_capital, daily_returns)
+}
+
+/// 持仓净市值，空头为负
+fn market_value(holdings: &[Holding]) -> f64 {
+    holdings.iter().map(|h| h.shares as f64 * h.price).sum()
+}
+
+/// 按交易日累积的资金状况
+#[derive(Default)]
+struct NavRows {
+    dates       : Vec<i32>,
+    nav         : Vec<f64>,
+    cash        : Vec<f64>,
+    market_value: Vec<f64>,
+    fees        : Vec<f64>,
+    rounding_gap: Vec<f64>,
+}
+
+impl NavRows {
+    fn push(&mut self, date: i32, nav: f64, cash: f64, value: f64, fees: f64, rounding_gap: f64) {
+        self.dates.push(date);
+        self.nav.push(nav);
+        self.cash.push(cash);
+        self.market_value.push(value);
+        self.fees.push(fees);
+        self.rounding_gap.push(rounding_gap);
+    }
+
+    fn into_df(self, initial_capital: f64, daily_returns: &DataFrame) -> CzscResult<DataFrame> {
+        let totals: HashMap<&str, f64> = daily_returns
+            .column("date")?
+            .str()?
+            .into_no_null_iter()
+            .zip(daily_returns.column("total")?.f64()?)
+            .map(|(date, total)| (date, total.unwrap_or(0.0)))
+            .collect();
+
+        let dates = Series::new("date".into(), self.dates)
+            .cast(&DataType::Date)?
+            .cast(&DataType::String)?;
+        let mut weight_nav = Vec::with_capacity(dates.len());
+        let mut value = initial_capital;
+        for date in dates.str()?.into_no_null_iter() {
+            weight_nav.push(value);
+            value *= 1.0 + totals.get(date).copied().unwrap_or(0.0);
+        }
+        let tracking: Vec<f64> = self.nav.iter().zip(&weight_nav).map(|(n, w)| n / w - 1.0).collect();
+        let cash_ratio: Vec<f64> = self.cash.iter().zip(&self.nav).map(|(c, n)| c / n).collect();
+        let cum_fees: Vec<f64> = self
+            .fees
+            .iter()
+            .scan(0.0, |acc, fee| {
+                *acc += fee;
+                Some(*acc)
+            })
+            .collect();
+
+        Ok(df![
+            "date" => dates,
+            "nav" => self.nav,
+            "cash" => self.cash,
+            "market_value" => self.market_value,
+            "fees" => self.fees,
+            "cum_fees" => cum_fees,
+            "weight_nav" => weight_nav,
+            "tracking" => tracking,
+            "cash_ratio" => cash_ratio,
+            "rounding_gap" => self.rounding_gap,
+        ]?)
+    }
+}
@@ -1,19 +1,132 @@
+use crate::capital::CapitalConfig;
+use crate::errors::CzscError::Validation;
 use crate::errors::CzscResult;
+use crate::exits::ExitRules;
+use crate::progress::ProgressMode;
+use crate::validation::{CheckAction, ValidationConfig};
+use crate::vol_target::VolTarget;
 use anyhow::anyhow;
+use polars::prelude::TimeZone;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// 权重乘以 10^digits 后需放入 i32 手数，10^9 是 i32 能容纳的最大 10 的幂
+const MAX_DIGITS: usize = 9;
+
+/// 默认交易所时区
+const DEFAULT_TIMEZONE: &str = "Asia/Shanghai";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum WeightType {
+    #[serde(rename = "ts")]
     TimeSeries,
+    #[serde(rename = "cs")]
     CrossSection,
 }
 
-#[derive(Debug, Clone)]
+impl FromStr for WeightType {
+    type Err = crate::errors::CzscError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ts" => Ok(WeightType::TimeSeries),
+            "cs" => Ok(WeightType::CrossSection),
+            _ => Err(anyhow!("Invalid weight_type {:?}, must be 'ts' or 'cs'", s).into()),
+        }
+    }
+}
+
+impl WeightType {
+    /// 单个品种对组合收益的贡献比例：时序策略组合收益为各品种等权平均，截面策略为直接加总
+    pub fn portfolio_scale(self, symbol_count: usize) -> f64 {
+        match self {
+            WeightType::TimeSeries => 1.0 / symbol_count.max(1) as f64,
+            WeightType::CrossSection => 1.0,
+        }
+    }
+}
+
+/// 组合指标字典的键名语言
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    Zh,
+    En,
+}
+
+impl FromStr for Locale {
+    type Err = crate::errors::CzscError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "zh" => Ok(Locale::Zh),
+            "en" => Ok(Locale::En),
+            _ => Err(anyhow!("Invalid locale {:?}, must be 'zh' or 'en'", s).into()),
+        }
+    }
+}
+
+/// 交易日划分规则，决定每根 K 线计入哪一个交易日
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "rule", rename_all = "lowercase", deny_unknown_fields)]
+pub enum TradingDay {
+    /// 按自然日划分
+    #[default]
+    Calendar,
+    /// `hour` 点及之后的 K 线计入下一交易日，落在周末的交易日顺延到周一；
+    /// 适用于夜盘（如 21:00–02:30）属于下一交易日的期货品种
+    Cutoff { hour: u32 },
+    /// 使用数据中的交易日列（Date 或 ISO 日期字符串）
+    Column { name: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct BacktestConfig {
     pub digits     : usize,
     pub fee_rate   : f32,
     pub weight_type: WeightType,
     pub yearly_days: usize,
     pub n_jobs     : usize,
+    pub locale     : Locale,
+    pub progress   : ProgressMode,
+    /// 交易所时区，带时区的 dt 会转换为该时区的本地时间
+    pub timezone   : String,
+    /// 交易日划分规则
+    pub trading_day: TradingDay,
+    /// 输入数据检查
+    pub validation : ValidationConfig,
+    /// 波动率目标，未设置时不缩放权重
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vol_target : Option<VolTarget>,
+    /// 止损、止盈、移动止损与最大持仓 K 线数，未设置时持仓完全跟随权重
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_rules : Option<ExitRules>,
+    /// 资金模拟，设置后按整手与现金额外记账，输出资金净值
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capital    : Option<CapitalConfig>,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        BacktestConfig {
+            digits     : 2,
+            fee_rate   : 0.0002,
+            weight_type: WeightType::TimeSeries,
+            yearly_days: 252,
+            n_jobs     : 1,
+            locale     : Locale::Zh,
+            progress   : ProgressMode::Bar,
+            timezone   : DEFAULT_TIMEZONE.to_string(),
+            trading_day: TradingDay::Calendar,
+            validation : ValidationConfig::default(),
+            vol_target : None,
+            exit_rules : None,
+            capital    : None,
+        }
+    }
 }
 
 impl BacktestConfig {
@@ -24,24 +137,112 @@ impl BacktestConfig {
         yearly_days: usize,
         n_jobs: usize,
     ) -> CzscResult<Self> {
-        let weight_type_enum = match weight_type.to_lowercase().as_str() {
-            "ts" => WeightType::TimeSeries,
-            "cs" => WeightType::CrossSection,
-            _ => {
-                return Err(anyhow!(
-                    "Invalid weight_type {:?}, must be 'ts' or 'cs'",
-                    weight_type
-                )
-                .into())
-            }
-        };
-
-        Ok(BacktestConfig {
+        let config = BacktestConfig {
             digits,
             fee_rate,
-            weight_type: weight_type_enum,
+            weight_type: weight_type.parse()?,
             yearly_days,
             n_jobs,
-        })
+            ..BacktestConfig::default()
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 检查各参数的取值范围
+    pub fn validate(&self) -> CzscResult<()> {
+        if self.digits > MAX_DIGITS {
+            return Err(Validation(format!(
+                "digits must be at most {} so that volume fits in i32, got {}",
+                MAX_DIGITS, self.digits
+            )));
+        }
+        // 权重有确定上界时提前检查手数是否溢出，否则由计算手数时的严格转换报错
+        if let Some(bound) = self.max_abs_weight() {
+            if bound * 10f64.powi(self.digits as i32) > i32::MAX as f64 {
+                return Err(Validation(format!(
+                    "weights up to {} are too large for digits {}, volume must fit in i32",
+                    bound, self.digits
+                )));
+            }
+        }
+        if !self.fee_rate.is_finite() || self.fee_rate < 0.0 {
+            return Err(Validation(format!(
+                "fee_rate must be a non-negative number, got {}",
+                self.fee_rate
+            )));
+        }
+        if self.yearly_days == 0 {
+            return Err(Validation("yearly_days must be positive".to_string()));
+        }
+        if let TradingDay::Cutoff { hour } = self.trading_day {
+            if hour == 0 || hour > 23 {
+                return Err(Validation(format!(
+                    "trading day cutoff hour must be between 1 and 23, got {}",
+                    hour
+                )));
+            }
+        }
+        if TimeZone::opt_try_new(Some(self.timezone.as_str())).is_err() {
+            return Err(Validation(format!("Invalid timezone: {:?}", self.timezone)));
+        }
+        if let Some(vol_target) = &self.vol_target {
+            vol_target.validate()?;
+        }
+        if let Some(exit_rules) = &self.exit_rules {
+            exit_rules.validate()?;
+        }
+        if let Some(capital) = &self.capital {
+            capital.validate()?;
+        }
+        self.validation.validate()
+    }
+
+    /// 回测中权重绝对值的上界：`max_abs_weight` 截断或拒绝越界权重时才有上界，
+    /// 波动率目标的缩放系数不超过 max(1, max_leverage)
+    fn max_abs_weight(&self) -> Option<f64> {
+        let bound = match self.validation.weight_bound {
+            CheckAction::Warn => None,
+            CheckAction::Error | CheckAction::Fix => self.validation.max_abs_weight,
+        }?;
+        match &self.vol_target {
+            Some(vol_target) => Some(bound * vol_target.max_leverage.max(1.0)),
+            None => Some(bound),
+        }
+    }
+
+    /// 从 TOML 文本读取配置，未给出的参数使用默认值
+    pub fn from_toml_str(text: &str) -> CzscResult<Self> {
+        let config: BacktestConfig = toml::from_str(text)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 从 JSON 文本读取配置，未给出的参数使用默认值
+    pub fn from_json_str(text: &str) -> CzscResult<Self> {
+        let config: BacktestConfig = serde_json::from_str(text)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 读取配置文件，按扩展名区分 `.toml` 与 `.json`
+    pub fn from_file(path: &Path) -> CzscResult<Self> {
+        let text = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml_str(&text),
+            Some("json") => Self::from_json_str(&text),
+            _ => Err(Validation(format!(
+                "Unsupported config file: {}, expected .toml or .json",
+                path.display()
+            ))),
+        }
+    }
+
+    pub fn to_toml_string(&self) -> CzscResult<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    pub fn to_json_string(&self) -> CzscResult<String> {
+        Ok(serde_json::to_string_pretty(self)?)
     }
 }
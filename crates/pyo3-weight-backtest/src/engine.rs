@@ -1,196 +1,842 @@
 use crate::analyzer::PortfolioAnalyzer;
-use crate::config::BacktestConfig;
-use crate::errors::CzscResult;
-use crate::processor::MetricProcessor;
-use crate::types::SymbolResult;
-use crate::utils::validate_dataframe;
-use anyhow::Context;
-use indicatif::{ProgressBar, ProgressStyle};
+use crate::attribution::{self, Period};
+use crate::capital::{simulate_capital, CapitalBars, CapitalResult};
+use crate::config::{BacktestConfig, Locale, WeightType};
+use crate::errors::CzscError::{BacktestLogic, Validation};
+use crate::errors::{check_cancelled, CzscResult, ErrorContext};
+use crate::exits::apply_exit_rules;
+use crate::portfolio_builder::PositionCounts;
+use crate::processor::{mask_nan_returns, MetricProcessor};
+use crate::progress::ProgressSink;
+use crate::source::scan_files;
+use crate::types::{
+    BacktestWarning, DailyMetric, MetricKey, PortfolioMetrics, SymbolResult, TradePair,
+};
+use crate::utils::{
+    normalize_dt, normalize_dt_lazy, trade_date_expr, validate_lazyframe, validate_trading_day,
+};
+use crate::validation::{validate_bars, validate_schema, validate_symbol_bars, validate_weights};
+use crate::vol_target::apply_vol_target;
 use polars::prelude::RoundMode::HalfAwayFromZero;
 use polars::prelude::*;
-use pyo3::pyclass;
+use pyo3::{pyclass, pymethods, PyResult};
 use pyo3_polars::PyDataFrame;
 use rayon::iter::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
-use std::collections::HashMap;
-use std::sync::Mutex;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// 回测数据来源
+#[derive(Clone)]
+enum BarSource {
+    /// 已载入内存并完成预处理的数据
+    Memory(DataFrame),
+    /// 惰性数据，回测时按品种逐个载入；`bar_index` 用于给检查后的 K 线编号
+    Lazy {
+        lf       : Box<LazyFrame>,
+        bar_index: BarIndex,
+    },
+    /// 多策略共享的按品种拆分的数据，已完成价格检查并算好 n1b 与 bar_id，
+    /// 回测时取 `weight` 列作为权重
+    Shared {
+        parts : Arc<HashMap<String, DataFrame>>,
+        weight: String,
+    },
+}
+
+impl std::fmt::Debug for BarSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BarSource::Memory(df) => f.debug_tuple("Memory").field(df).finish(),
+            BarSource::Lazy { bar_index, .. } => f
+                .debug_struct("Lazy")
+                .field("timestamps", &bar_index.dts.len())
+                .finish(),
+            BarSource::Shared { parts, weight } => f
+                .debug_struct("Shared")
+                .field("symbols", &parts.len())
+                .field("weight", weight)
+                .finish(),
+        }
+    }
+}
+
+/// 惰性数据检查后全部 K 线的编号索引，编号与全量数据按 (dt, symbol) 排序后的行号一致。
+/// 只按时间戳保存此前的行数与各品种是否有 K 线，不保存逐行的 (dt, symbol)
+#[derive(Debug, Clone)]
+struct BarIndex {
+    /// 升序排列的全部时间戳
+    dts    : Vec<i64>,
+    /// 早于各时间戳的 K 线行数
+    offsets: Vec<IdxSize>,
+    /// 每个时间戳 `words` 个字的位图，第 i 位表示按名称排序的第 i 个品种在该时间有 K 线
+    members: Vec<u64>,
+    words  : usize,
+    ranks  : HashMap<String, usize>,
+}
+
+impl BarIndex {
+    /// 按品种逐个读取检查后的时间戳建立索引
+    fn build(
+        symbols: &[String],
+        mut symbol_dts: impl FnMut(&str) -> CzscResult<Vec<i64>>,
+    ) -> CzscResult<Self> {
+        let mut sorted = symbols.to_vec();
+        sorted.sort();
+        let words = sorted.len().div_ceil(64);
+
+        // 每个时间戳的行数与品种位图
+        let mut slots: BTreeMap<i64, (IdxSize, Vec<u64>)> = BTreeMap::new();
+        for (rank, symbol) in sorted.iter().enumerate() {
+            for dt in symbol_dts(symbol)? {
+                let (rows, bits) = slots.entry(dt).or_insert_with(|| (0, vec![0; words]));
+                *rows += 1;
+                bits[rank / 64] |= 1 << (rank % 64);
+            }
+        }
+
+        let mut index = BarIndex {
+            dts    : Vec::with_capacity(slots.len()),
+            offsets: Vec::with_capacity(slots.len()),
+            members: Vec::with_capacity(slots.len() * words),
+            words,
+            ranks  : sorted.into_iter().enumerate().map(|(rank, s)| (s, rank)).collect(),
+        };
+        let mut total: IdxSize = 0;
+        for (dt, (rows, bits)) in slots {
+            index.dts.push(dt);
+            index.offsets.push(total);
+            index.members.extend(bits);
+            total += rows;
+        }
+        Ok(index)
+    }
+
+    /// 品种各 K 线的编号：早于该时间的行数加上同一时间排在前面的品种数
+    fn bar_ids(&self, symbol: &str, dts: &[i64]) -> CzscResult<Vec<IdxSize>> {
+        let rank = self.ranks[symbol];
+        dts.iter()
+            .map(|dt| {
+                let k = self.dts.binary_search(dt).map_err(|_| {
+                    BacktestLogic(format!("{} has a bar missing from the bar index", symbol))
+                })?;
+                let bits = &self.members[k * self.words..(k + 1) * self.words];
+                let before = bits[..rank / 64].iter().map(|w| w.count_ones()).sum::<u32>()
+                    + (bits[rank / 64] & ((1u64 << (rank % 64)) - 1)).count_ones();
+                Ok(self.offsets[k] + before as IdxSize)
+            })
+            .collect()
+    }
+}
+
+/// 检查后 dt 列的时间戳
+fn dt_values(df: &DataFrame) -> CzscResult<Vec<i64>> {
+    df.column("dt")?
+        .datetime()?
+        .physical()
+        .into_iter()
+        .map(|dt| dt.ok_or_else(|| Validation("column dt contains null values".to_string())))
+        .collect()
+}
 
 #[derive(Debug, Clone)]
 pub struct BacktestEngine {
-    config   : BacktestConfig,
-    df       : DataFrame,
-    symbols  : Vec<String>,
-    processor: MetricProcessor,
+    config             : BacktestConfig,
+    source             : BarSource,
+    symbols            : Vec<String>,
+    processor          : MetricProcessor,
+    /// 创建引擎时数据检查产生的警告
+    validation_warnings: Vec<BacktestWarning>,
 }
 
 #[pyclass]
+#[derive(Clone)]
 pub struct BacktestResult {
     #[pyo3(get)] pub symbol_results    : HashMap<String, SymbolResult>,
     #[pyo3(get)] pub portfolio_metrics : HashMap<String, f64>,
     #[pyo3(get)] pub daily_ew_return_df: PyDataFrame,
+    /// 结构化的组合指标，`portfolio_metrics` 的键语言由配置的 locale 决定
+    #[pyo3(get)] pub metrics           : PortfolioMetrics,
+    /// 回测过程中发现的数据异常
+    #[pyo3(get)] pub warnings          : Vec<BacktestWarning>,
+    /// 按整手与现金记账的资金模拟结果，配置了 capital 时才有
+    #[pyo3(get)] pub capital           : Option<CapitalResult>,
+    /// 产生该结果的回测配置
+    pub config: BacktestConfig,
+}
+
+#[pymethods]
+impl BacktestResult {
+    /// 以 JSON 格式返回产生该结果的回测配置
+    pub fn config_json(&self) -> PyResult<String> {
+        Ok(self.config.to_json_string()?)
+    }
+
+    /// 以指定语言 ("zh" / "en") 的键返回组合指标字典
+    #[pyo3(name = "localized_metrics")]
+    pub fn py_localized_metrics(&self, locale: &str) -> PyResult<HashMap<String, f64>> {
+        Ok(self.localized_metrics(locale.parse()?))
+    }
+
+    /// 每日指标 DataFrame，可按品种筛选
+    #[pyo3(name = "daily_metrics_df", signature = (symbol=None))]
+    pub fn py_daily_metrics_df(&self, symbol: Option<&str>) -> PyResult<PyDataFrame> {
+        Ok(PyDataFrame(self.daily_metrics_df(symbol)?))
+    }
+
+    /// 交易对 DataFrame，可按品种筛选
+    #[pyo3(name = "trade_pairs_df", signature = (symbol=None))]
+    pub fn py_trade_pairs_df(&self, symbol: Option<&str>) -> PyResult<PyDataFrame> {
+        Ok(PyDataFrame(self.trade_pairs_df(symbol)?))
+    }
+
+    /// 每个品种一行的绩效表，用于按夏普、回撤、胜率等筛选品种
+    #[pyo3(name = "symbol_performance_df")]
+    pub fn py_symbol_performance_df(&self) -> PyResult<PyDataFrame> {
+        Ok(PyDataFrame(self.symbol_performance_df()?))
+    }
+
+    /// 多空组合及多头、空头两条腿的日收益 (date, total, long, short)
+    #[pyo3(name = "leg_returns_df")]
+    pub fn py_leg_returns_df(&self) -> PyResult<PyDataFrame> {
+        Ok(PyDataFrame(self.leg_returns_df()?))
+    }
+
+    /// 多空、多头、空头三行的绩效表，用于判断收益是否只来自单边
+    #[pyo3(name = "leg_performance_df")]
+    pub fn py_leg_performance_df(&self) -> PyResult<PyDataFrame> {
+        Ok(PyDataFrame(self.leg_performance_df()?))
+    }
+
+    /// 每日组合敞口与换手 (date, gross, net, long, short, long_count, short_count, turnover)
+    #[pyo3(name = "exposure_df")]
+    pub fn py_exposure_df(&self) -> PyResult<PyDataFrame> {
+        Ok(PyDataFrame(self.exposure_df()?))
+    }
+
+    /// 组合收益按毛收益/成本、多头/空头拆分，每项一行
+    #[pyo3(name = "attribution_summary")]
+    pub fn py_attribution_summary(&self) -> PyResult<PyDataFrame> {
+        Ok(PyDataFrame(self.attribution_summary()?))
+    }
+
+    /// 各品种的收益贡献，按贡献从高到低排列；`top_n` 只保留贡献最高与最低的各 N 个品种
+    #[pyo3(name = "symbol_attribution_df", signature = (top_n=None))]
+    pub fn py_symbol_attribution_df(&self, top_n: Option<usize>) -> PyResult<PyDataFrame> {
+        Ok(PyDataFrame(self.symbol_attribution_df(top_n)?))
+    }
+
+    /// 按 "year" 或 "month" 汇总的收益贡献
+    #[pyo3(name = "period_attribution_df", signature = (period="year"))]
+    pub fn py_period_attribution_df(&self, period: &str) -> PyResult<PyDataFrame> {
+        Ok(PyDataFrame(self.period_attribution_df(period.parse()?)?))
+    }
+
+    /// 将完整结果保存到目录 (Parquet + JSON 清单)
+    #[pyo3(name = "save")]
+    pub fn py_save(&self, dir: PathBuf) -> PyResult<()> {
+        Ok(self.save(&dir)?)
+    }
+
+    /// 生成离线 HTML 报告
+    #[pyo3(name = "to_html")]
+    pub fn py_to_html(&self) -> PyResult<String> {
+        Ok(self.to_html()?)
+    }
+
+    /// 将离线 HTML 报告写入文件
+    #[pyo3(name = "save_html")]
+    pub fn py_save_html(&self, path: PathBuf) -> PyResult<()> {
+        Ok(self.save_html(&path)?)
+    }
+
+    /// 从 `save` 写出的目录读取结果
+    #[staticmethod]
+    #[pyo3(name = "load")]
+    pub fn py_load(dir: PathBuf) -> PyResult<Self> {
+        Ok(Self::load(&dir)?)
+    }
 }
 
 impl BacktestEngine {
     pub fn new(df: DataFrame, config: BacktestConfig) -> CzscResult<Self> {
-        // 数据检验
-        validate_dataframe(&df).context("DataFrame validation")?;
+        // 配置与数据检验
+        config.validate()?;
+        validate_trading_day(df.clone().lazy(), df.schema(), &config.trading_day)
+            .context("DataFrame validation")?;
+        let df = normalize_dt(df, &config.timezone).context("DataFrame validation")?;
+        let (df, validation_warnings) =
+            validate_bars(df, &config.validation).context("DataFrame validation")?;
+        if df.height() == 0 {
+            return Err(Validation("DataFrame is empty after validation".to_string()));
+        }
 
         // 获取 symbols
-        let symbols = df
-            .column("symbol")?
-            .str()?
-            .unique()?
-            .into_iter()
-            .flatten()
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>();
+        let symbols = collect_symbols(&df)?;
 
-        // println!("Symbols found: {:?}", symbols);
+        // 波动率目标在权重取整之前缩放权重
+        let df = match &config.vol_target {
+            Some(vol_target) => apply_vol_target(
+                df,
+                vol_target,
+                config.weight_type,
+                &config.trading_day,
+                config.yearly_days,
+            )?,
+            None => df,
+        };
 
         // 预处理 dataframe
-        let prepared_df = df
-            .lazy()
-            .with_columns([col("weight")
-                .cast(DataType::Float64)
-                .round(config.digits as u32, HalfAwayFromZero)
-                .alias("weight")])
-            .with_column(
-                (col("weight") * lit(10f64.powi(config.digits as i32)))
-                    .cast(DataType::Int32)
-                    .alias("volume"),
-            )
-            .sort(["dt"], SortMultipleOptions::default())
-            .with_row_index("bar_id", Some(0))
-            .collect()?;
+        let prepared_df = prepare_dataframe(df, &config, 0)?;
 
         let processor = MetricProcessor::new(config.clone());
 
         Ok(Self {
             config,
-            df: prepared_df,
+            source: BarSource::Memory(prepared_df),
             symbols,
             processor,
+            validation_warnings,
         })
     }
 
-    pub fn run_backtest(&self) -> CzscResult<(BacktestResult)> {
-        let symbol_results = if self.config.n_jobs > 1 {
+    /// 基于惰性数据创建引擎，回测时每次只载入一个品种的数据
+    pub fn from_lazy(lf: LazyFrame, config: BacktestConfig) -> CzscResult<Self> {
+        // 配置与数据检验
+        config.validate()?;
+        validate_lazyframe(&lf).context("LazyFrame validation")?;
+        check_vol_target(&config)?;
+        // 列类型在此检查，行级检查在回测时逐品种进行
+        let schema = lf.clone().collect_schema()?;
+        validate_trading_day(lf.clone(), &schema, &config.trading_day)
+            .context("LazyFrame validation")?;
+        let lf = normalize_dt_lazy(lf, &schema, &config.timezone).context("LazyFrame validation")?;
+        let (casts, validation_warnings) =
+            validate_schema(&schema, &config.validation).context("LazyFrame validation")?;
+        let lf = if casts.is_empty() { lf } else { lf.with_columns(casts) };
+
+        // 获取 symbols
+        let symbols_df = lf.clone().select([col("symbol").unique()]).collect()?;
+        let symbols = collect_symbols(&symbols_df)?;
+
+        // 逐品种完成行级检查后建立编号索引，与全量数据检查、排序后的 bar_id 一致；
+        // 检查产生的警告在回测时重新给出
+        let bar_index = BarIndex::build(&symbols, |symbol| {
+            let raw_df = lf.clone().filter(col("symbol").eq(lit(symbol))).collect()?;
+            let (raw_df, _) = validate_symbol_bars(raw_df, &config.validation)
+                .context(&format!("{} validation", symbol))?;
+            dt_values(&raw_df)
+        })?;
+
+        let processor = MetricProcessor::new(config.clone());
+
+        Ok(Self {
+            config,
+            source: BarSource::Lazy { lf: Box::new(lf), bar_index },
+            symbols,
+            processor,
+            validation_warnings,
+        })
+    }
+
+    /// 基于多策略共享的按品种拆分数据创建引擎，`weight` 为该策略的权重列
+    pub(crate) fn from_shared(
+        parts: Arc<HashMap<String, DataFrame>>,
+        weight: &str,
+        config: BacktestConfig,
+        validation_warnings: Vec<BacktestWarning>,
+    ) -> Self {
+        let mut symbols: Vec<String> = parts.keys().cloned().collect();
+        symbols.sort();
+        let processor = MetricProcessor::new(config.clone());
+
+        Self {
+            config,
+            source: BarSource::Shared { parts, weight: weight.to_string() },
+            symbols,
+            processor,
+            validation_warnings,
+        }
+    }
+
+    /// 从 Parquet/IPC/CSV 文件创建引擎，文件格式由扩展名推断
+    pub fn from_files(
+        paths: &[PathBuf],
+        hive_partitioned: bool,
+        config: BacktestConfig,
+    ) -> CzscResult<Self> {
+        let lf = scan_files(paths, hive_partitioned)?;
+        Self::from_lazy(lf, config)
+    }
+
+    /// 待回测的品种
+    pub fn symbols(&self) -> &[String] {
+        &self.symbols
+    }
+
+    pub fn config(&self) -> &BacktestConfig {
+        &self.config
+    }
+
+    /// 创建引擎时数据检查产生的警告；惰性数据只包含列类型检查，行级警告在回测结果中给出
+    pub fn validation_warnings(&self) -> &[BacktestWarning] {
+        &self.validation_warnings
+    }
+
+    pub fn run_backtest(&self) -> CzscResult<BacktestResult> {
+        self.run_backtest_cancellable(&AtomicBool::new(false))
+    }
+
+    /// 可中断的回测，品种之间及品种内部逐 K 线、逐日检查 `cancel`，
+    /// 置位后返回 `CzscError::Interrupted`
+    pub fn run_backtest_cancellable(&self, cancel: &AtomicBool) -> CzscResult<BacktestResult> {
+        let progress = self.config.progress.sink(self.symbols.len());
+        self.run_backtest_with(progress.as_ref(), cancel)
+    }
+
+    /// 使用指定的进度接收器运行回测
+    pub fn run_backtest_with(
+        &self,
+        progress: &dyn ProgressSink,
+        cancel: &AtomicBool,
+    ) -> CzscResult<BacktestResult> {
+        let outputs = if self.config.n_jobs > 1 {
             // 多线程处理
-            self.run_parallel()?
+            self.run_parallel(progress, cancel)?
         } else {
             // 单线程处理
-            self.run_sequential()?
+            self.run_sequential(progress, cancel)?
         };
+        progress.finish();
 
-        let daily_df = PortfolioAnalyzer::gen_daily_metric_df(&symbol_results);
-        let daily_ew_return_df = PortfolioAnalyzer::gen_daily_ew_return_df(
-            self.config.weight_type,
-            &symbol_results,
-            &daily_df,
-        )?;
+        let mut symbol_results = HashMap::with_capacity(outputs.len());
+        let mut position_counts = PositionCounts::default();
+        let mut bars = Vec::new();
+        for (symbol, output) in outputs {
+            position_counts += output.counts;
+            bars.extend(output.bars);
+            symbol_results.insert(symbol, output.result);
+        }
 
-        // 计算组合指标
-        let analyzer = PortfolioAnalyzer::new(
-            self.config.clone(),
-            &symbol_results,
-            &self.df,
-            &daily_df,
-            &daily_ew_return_df,
-        );
+        // 数据级警告与检查时被丢弃、或没有剩余 K 线的品种的警告放在结果最前面
+        let mut data_warnings: Vec<BacktestWarning> = self
+            .validation_warnings
+            .iter()
+            .filter(|w| !self.symbols.contains(&w.symbol))
+            .cloned()
+            .collect();
+        let mut empty_symbols: Vec<String> = symbol_results
+            .iter()
+            .filter(|(_, sr)| sr.daily_metrics.is_empty())
+            .map(|(symbol, _)| symbol.clone())
+            .collect();
+        empty_symbols.sort();
+        for symbol in empty_symbols {
+            data_warnings.extend(symbol_results.remove(&symbol).unwrap().warnings);
+        }
+        if symbol_results.is_empty() {
+            return Err(Validation("No bars left after validation".to_string()));
+        }
 
-        let metrics = analyzer.analyze_portfolio_metrics()?;
+        let mut result =
+            BacktestResult::from_symbol_results(&self.config, symbol_results, position_counts)?;
+        result.warnings.splice(0..0, data_warnings);
 
-        Ok(BacktestResult {
-            symbol_results,
-            portfolio_metrics: metrics,
-            daily_ew_return_df: PyDataFrame(daily_ew_return_df),
-        })
+        if let Some(capital) = &self.config.capital {
+            let nav_df = simulate_capital(
+                bars,
+                capital,
+                self.config.weight_type,
+                self.config.fee_rate as f64,
+                &result.daily_ew_return_df.0,
+            )?;
+            result.capital = Some(CapitalResult::from_nav_df(
+                nav_df,
+                capital.initial_capital,
+                self.config.yearly_days,
+                self.config.locale,
+            )?);
+        }
+        Ok(result)
     }
 
-    fn run_sequential(&self) -> CzscResult<HashMap<String, SymbolResult>> {
-        let pb = ProgressBar::new(self.symbols.len() as u64);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
-                .unwrap()
-                .progress_chars("##-"),
-        );
-
+    fn run_sequential(
+        &self,
+        progress: &dyn ProgressSink,
+        cancel: &AtomicBool,
+    ) -> CzscResult<HashMap<String, SymbolOutput>> {
+        let total = self.symbols.len();
         let mut results = HashMap::new();
 
-        for symbol in &self.symbols {
-            let sr = self.process_symbol(symbol)?;
+        for (i, symbol) in self.symbols.iter().enumerate() {
+            check_cancelled(cancel)?;
+            let sr = self.process_symbol(symbol, cancel)?;
             results.insert(symbol.to_string(), sr);
-            pb.inc(1); // 更新进度条
-            pb.set_message(symbol.to_string());
+            progress.update(i + 1, total, symbol);
         }
 
-        pb.finish_with_message("完成");
         Ok(results)
     }
 
-    fn run_parallel(&self) -> CzscResult<HashMap<String, SymbolResult>> {
+    fn run_parallel(
+        &self,
+        progress: &dyn ProgressSink,
+        cancel: &AtomicBool,
+    ) -> CzscResult<HashMap<String, SymbolOutput>> {
         let pool = rayon::ThreadPoolBuilder::new()
             .num_threads(self.config.n_jobs)
             .stack_size(256 * 1024 * 1024) // 256MB 栈大小
             .build()?;
 
-        // 创建进度条
-        let pb = ProgressBar::new(self.symbols.len() as u64);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
-                .unwrap()
-                .progress_chars("##-"),
-        );
-
-        // 使用 Mutex 包装进度条以便在并行环境中安全更新
-        let pb_mutex = Mutex::new(pb);
+        let total = self.symbols.len();
+        let done = AtomicUsize::new(0);
 
         pool.install(|| {
-            let results: Vec<CzscResult<(String, SymbolResult)>> = self
+            let results: Vec<CzscResult<(String, SymbolOutput)>> = self
                 .symbols
                 .par_iter()
                 .map(|symbol| {
+                    // 已中断时跳过剩余品种
+                    check_cancelled(cancel)?;
+
                     // 处理当前 symbol
-                    let result = self.process_symbol(symbol).map(|sr| (symbol.clone(), sr));
+                    let result = self.process_symbol(symbol, cancel).map(|sr| (symbol.clone(), sr));
 
-                    // 更新进度条（互斥访问）
-                    if let Ok(ref mut pb) = pb_mutex.lock() {
-                        pb.inc(1);
-                    }
+                    let finished = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    progress.update(finished, total, symbol);
 
                     result
                 })
                 .collect();
 
-            // 完成进度条
-            if let Ok(pb) = pb_mutex.lock() {
-                pb.finish();
-            }
-
             // 处理结果
-            let pairs: CzscResult<Vec<(String, SymbolResult)>> = results.into_iter().collect();
+            let pairs: CzscResult<Vec<(String, SymbolOutput)>> = results.into_iter().collect();
             pairs.map(|pairs| pairs.into_iter().collect())
         })
     }
 
-    fn process_symbol(&self, symbol: &String) -> CzscResult<SymbolResult> {
+    /// 按品种载入的数据逐品种应用波动率目标，只支持时序策略
+    fn apply_symbol_vol_target(&self, df: DataFrame) -> CzscResult<DataFrame> {
+        match &self.config.vol_target {
+            Some(vol_target) => apply_vol_target(
+                df,
+                vol_target,
+                self.config.weight_type,
+                &self.config.trading_day,
+                self.config.yearly_days,
+            ),
+            None => Ok(df),
+        }
+    }
+
+    /// 处理单个品种；启用资金模拟时同时返回出场规则生效后的 K 线，供资金模拟按时间回放。
+    /// 出场规则、每日指标与交易对的循环中检查 `cancel`，单个大品种也能及时中断
+    fn process_symbol(&self, symbol: &str, cancel: &AtomicBool) -> CzscResult<SymbolOutput> {
+        let mut warnings: Vec<BacktestWarning> = self
+            .validation_warnings
+            .iter()
+            .filter(|w| w.symbol == symbol)
+            .cloned()
+            .collect();
+
         // 过滤出当前 symbol 的数据
-        let symbol_df =
-            self.df.clone().lazy().filter(col("symbol").eq(lit(symbol.clone()))).collect()?;
+        let symbol_df = match &self.source {
+            BarSource::Memory(df) => {
+                df.clone().lazy().filter(col("symbol").eq(lit(symbol))).collect()?
+            }
+            BarSource::Lazy { lf, bar_index } => {
+                let raw_df = (**lf).clone().filter(col("symbol").eq(lit(symbol))).collect()?;
+                let (raw_df, symbol_warnings) =
+                    validate_symbol_bars(raw_df, &self.config.validation)
+                        .context(&format!("{} validation", symbol))?;
+                warnings.extend(symbol_warnings);
+                let raw_df = self.apply_symbol_vol_target(raw_df)?;
 
-        // let column_names = symbol_df.get_column_names();
-        // println!("Processing symbol: {}, columns: {:?}", symbol, column_names);
+                let mut symbol_df = prepare_lazy(raw_df.lazy(), &self.config).collect()?;
+                let bar_ids = bar_index.bar_ids(symbol, &dt_values(&symbol_df)?)?;
+                symbol_df.with_column(Column::new("bar_id".into(), bar_ids))?;
+                symbol_df
+            }
+            BarSource::Shared { parts, weight } => {
+                let raw_df = parts[symbol]
+                    .clone()
+                    .lazy()
+                    .with_column(col(weight.as_str()).alias("weight"))
+                    .collect()?;
+                let raw_height = raw_df.height();
+                let (raw_df, symbol_warnings) = validate_weights(raw_df, &self.config.validation)
+                    .context(&format!("{} validation", symbol))?;
+                warnings.extend(symbol_warnings);
+                // 丢弃行后下一期价格改变，由处理器重新计算 n1b
+                let raw_df =
+                    if raw_df.height() < raw_height { raw_df.drop("n1b")? } else { raw_df };
+                let raw_df = self.apply_symbol_vol_target(raw_df)?;
+                prepare_lazy(raw_df.lazy(), &self.config).collect()?
+            }
+        };
+
+        // 丢弃异常行并记录警告
+        let symbol_df = self.processor.clean_bars(symbol, symbol_df, &mut warnings)?;
+        if symbol_df.is_empty() {
+            return Ok(SymbolOutput {
+                result: SymbolResult { daily_metrics: vec![], trade_pairs: vec![], warnings },
+                counts: PositionCounts::default(),
+                bars  : None,
+            });
+        }
+        // 出场规则在权重取整之后逐 K 线执行
+        let symbol_df = match &self.config.exit_rules {
+            Some(rules) => apply_exit_rules(symbol_df, rules, cancel)?,
+            None => symbol_df,
+        };
+        // 多空占比按检查、取整并执行出场规则后的 K 线统计，各数据来源口径一致
+        let counts = PositionCounts::from_df(&symbol_df)?;
 
         // 生成每日结果
-        let daily_metrics = self.processor.process_daily_metrics(symbol, &symbol_df)?;
+        let mut daily_metrics = self.processor.process_daily_metrics(symbol, &symbol_df, cancel)?;
+        warnings.extend(mask_nan_returns(symbol, &mut daily_metrics));
         // 生成交易对
-        let trade_pairs = self.processor.generate_trade_pairs(symbol, &symbol_df)?;
+        let trade_pairs =
+            self.processor.generate_trade_pairs(symbol, &symbol_df, &mut warnings, cancel)?;
+
+        let bars = match self.config.capital {
+            Some(_) => Some(CapitalBars::from_df(symbol, &symbol_df)?),
+            None => None,
+        };
+
+        Ok(SymbolOutput {
+            result: SymbolResult {
+                daily_metrics,
+                trade_pairs,
+                warnings,
+            },
+            counts,
+            bars,
+        })
+    }
+}
+
+impl BacktestResult {
+    /// 将组合指标字典的键转换为指定语言，未登记的键保持不变
+    pub fn localized_metrics(&self, locale: Locale) -> HashMap<String, f64> {
+        self.portfolio_metrics
+            .iter()
+            .map(|(key, value)| {
+                let key = MetricKey::from_label(key)
+                    .map(|k| k.label(locale).to_string())
+                    .unwrap_or_else(|| key.clone());
+                (key, *value)
+            })
+            .collect()
+    }
+
+    /// 每日指标长表，`symbol` 为空时返回全部品种，按品种、日期排列
+    pub fn daily_metrics_df(&self, symbol: Option<&str>) -> CzscResult<DataFrame> {
+        let metrics: Vec<&DailyMetric> = self
+            .selected_results(symbol)?
+            .into_iter()
+            .flat_map(|sr| &sr.daily_metrics)
+            .collect();
+        PortfolioAnalyzer::to_daily_dateframe(&metrics)
+    }
+
+    /// 交易对表，`symbol` 为空时返回全部品种，按品种、开仓时间排列
+    pub fn trade_pairs_df(&self, symbol: Option<&str>) -> CzscResult<DataFrame> {
+        let pairs: Vec<&TradePair> = self
+            .selected_results(symbol)?
+            .into_iter()
+            .flat_map(|sr| &sr.trade_pairs)
+            .collect();
+        PortfolioAnalyzer::to_trade_pair_dataframe(&pairs)
+    }
+
+    /// 每个品种一行的绩效表，列名语言由配置的 locale 决定
+    pub fn symbol_performance_df(&self) -> CzscResult<DataFrame> {
+        PortfolioAnalyzer::symbol_performance_df(
+            &self.symbol_results,
+            self.config.yearly_days,
+            self.config.locale,
+        )
+    }
+
+    /// 多空组合及多头、空头两条腿的日收益，按日期排列
+    pub fn leg_returns_df(&self) -> CzscResult<DataFrame> {
+        let daily_df = PortfolioAnalyzer::gen_daily_metric_df(&self.symbol_results);
+        PortfolioAnalyzer::gen_leg_return_df(
+            self.config.weight_type,
+            self.symbol_results.len(),
+            &daily_df,
+        )
+    }
+
+    /// 多空、多头、空头三行的绩效表，列名语言由配置的 locale 决定
+    pub fn leg_performance_df(&self) -> CzscResult<DataFrame> {
+        PortfolioAnalyzer::leg_performance_df(
+            &self.leg_returns_df()?,
+            &self.symbol_results,
+            self.config.yearly_days,
+            self.config.locale,
+        )
+    }
+
+    /// 每日组合敞口与换手，按日期排列
+    pub fn exposure_df(&self) -> CzscResult<DataFrame> {
+        let daily_df = PortfolioAnalyzer::gen_daily_metric_df(&self.symbol_results);
+        PortfolioAnalyzer::gen_exposure_df(
+            self.config.weight_type,
+            self.symbol_results.len(),
+            &daily_df,
+        )
+    }
+
+    /// 组合收益按毛收益/成本、多头/空头拆分，成本以负数计入
+    pub fn attribution_summary(&self) -> CzscResult<DataFrame> {
+        let daily_df = PortfolioAnalyzer::gen_daily_metric_df(&self.symbol_results);
+        attribution::attribution_summary(&daily_df, self.portfolio_scale())
+    }
+
+    /// 各品种对组合收益的贡献，按净收益贡献从高到低排列
+    pub fn symbol_attribution_df(&self, top_n: Option<usize>) -> CzscResult<DataFrame> {
+        let daily_df = PortfolioAnalyzer::gen_daily_metric_df(&self.symbol_results);
+        attribution::symbol_attribution(&daily_df, self.portfolio_scale(), top_n)
+    }
+
+    /// 按年或按月汇总的组合收益贡献
+    pub fn period_attribution_df(&self, period: Period) -> CzscResult<DataFrame> {
+        let daily_df = PortfolioAnalyzer::gen_daily_metric_df(&self.symbol_results);
+        attribution::period_attribution(&daily_df, self.portfolio_scale(), period)
+    }
+
+    /// 单个品种收益计入组合收益的比例
+    fn portfolio_scale(&self) -> f64 {
+        self.config.weight_type.portfolio_scale(self.symbol_results.len())
+    }
 
-        Ok(SymbolResult {
-            daily_metrics,
-            trade_pairs,
+    /// 按品种名排序后的品种结果，指定 `symbol` 时只取该品种
+    fn selected_results(&self, symbol: Option<&str>) -> CzscResult<Vec<&SymbolResult>> {
+        match symbol {
+            Some(symbol) => self
+                .symbol_results
+                .get(symbol)
+                .map(|sr| vec![sr])
+                .ok_or_else(|| Validation(format!("Symbol {} not found in result", symbol))),
+            None => {
+                let mut symbols: Vec<&String> = self.symbol_results.keys().collect();
+                symbols.sort();
+                Ok(symbols.into_iter().map(|s| &self.symbol_results[s]).collect())
+            }
+        }
+    }
+
+    /// 由各品种的回测结果汇总出组合层面的结果
+    pub(crate) fn from_symbol_results(
+        config: &BacktestConfig,
+        symbol_results: HashMap<String, SymbolResult>,
+        position_counts: PositionCounts,
+    ) -> CzscResult<Self> {
+        let daily_df = PortfolioAnalyzer::gen_daily_metric_df(&symbol_results);
+        let daily_ew_return_df = PortfolioAnalyzer::gen_daily_ew_return_df(
+            config.weight_type,
+            &symbol_results,
+            &daily_df,
+        )?;
+
+        let daily_summary = PortfolioAnalyzer::gen_daily_summary_df(
+            config.weight_type,
+            symbol_results.len(),
+            &daily_df,
+            &daily_ew_return_df,
+        )?;
+
+        // 计算组合指标
+        let analyzer = PortfolioAnalyzer::new(
+            config.clone(),
+            &symbol_results,
+            position_counts,
+            &daily_summary,
+        );
+
+        let (portfolio_metrics, metrics, portfolio_warnings) =
+            analyzer.analyze_portfolio_metrics()?;
+
+        // 品种级警告按品种名排列，组合级警告放在最后
+        let mut symbols: Vec<&String> = symbol_results.keys().collect();
+        symbols.sort();
+        let mut warnings: Vec<BacktestWarning> = symbols
+            .into_iter()
+            .flat_map(|s| symbol_results[s].warnings.iter().cloned())
+            .collect();
+        warnings.extend(portfolio_warnings);
+
+        Ok(BacktestResult {
+            symbol_results,
+            portfolio_metrics,
+            metrics,
+            warnings,
+            daily_ew_return_df: PyDataFrame(daily_ew_return_df),
+            capital: None,
+            config: config.clone(),
         })
     }
 }
+
+/// 单个品种的回测结果
+struct SymbolOutput {
+    result: SymbolResult,
+    /// 多空持仓 K 线计数
+    counts: PositionCounts,
+    /// 资金模拟需要的 K 线，未启用资金模拟时为空
+    bars  : Option<CapitalBars>,
+}
+
+/// 截面策略的波动率目标需要全部品种的数据，按品种载入时只支持时序策略
+pub(crate) fn check_vol_target(config: &BacktestConfig) -> CzscResult<()> {
+    if config.vol_target.is_some() && config.weight_type == WeightType::CrossSection {
+        return Err(Validation(
+            "vol_target with cs weights requires an in-memory DataFrame".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// 获取数据中出现的全部品种
+pub(crate) fn collect_symbols(df: &DataFrame) -> CzscResult<Vec<String>> {
+    Ok(df
+        .column("symbol")?
+        .str()?
+        .unique()?
+        .into_iter()
+        .flatten()
+        .map(|s| s.to_string())
+        .collect::<Vec<String>>())
+}
+
+/// 预处理数据：权重取整、计算手数、按时间排序并从 `bar_offset` 开始编号
+pub(crate) fn prepare_dataframe(
+    df: DataFrame,
+    config: &BacktestConfig,
+    bar_offset: IdxSize,
+) -> CzscResult<DataFrame> {
+    Ok(prepare_lazy(df.lazy(), config)
+        .with_row_index("bar_id", Some(bar_offset))
+        .collect()?)
+}
+
+/// 权重取整、计算手数与交易日并按时间、品种排序
+fn prepare_lazy(lf: LazyFrame, config: &BacktestConfig) -> LazyFrame {
+    lf.with_columns([col("weight")
+        .cast(DataType::Float64)
+        .round(config.digits as u32, HalfAwayFromZero)
+        .alias("weight")])
+        .with_columns([
+            // 非有限权重留给处理器丢弃，其余手数溢出 i32 时报错
+            (when(col("weight").is_finite()).then(col("weight")).otherwise(lit(NULL))
+                * lit(10f64.powi(config.digits as i32)))
+            .strict_cast(DataType::Int32)
+            .alias("volume"),
+            trade_date_expr(&config.trading_day).alias("trade_date"),
+        ])
+        .sort(["dt", "symbol"], SortMultipleOptions::default())
+}
@@ -4,6 +4,7 @@ use polars::error::PolarsError;
 use pyo3::PyErr;
 use rayon::ThreadPoolBuildError;
 use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -56,6 +57,10 @@ pub enum CzscError {
     #[error("Parse Float Error: {0}")]
     ParseFloatError(#[from] std::num::ParseFloatError),
 
+    /// 回测被用户中断
+    #[error("回测已中断")]
+    Interrupted,
+
     #[error("未知错误: {0}")]
     Unknown(String),
 }
@@ -66,6 +71,18 @@ impl From<serde_json::Error> for CzscError {
     }
 }
 
+impl From<toml::de::Error> for CzscError {
+    fn from(err: toml::de::Error) -> Self {
+        CzscError::Serialization(err.to_string())
+    }
+}
+
+impl From<toml::ser::Error> for CzscError {
+    fn from(err: toml::ser::Error) -> Self {
+        CzscError::Serialization(err.to_string())
+    }
+}
+
 impl From<String> for CzscError {
     fn from(err: String) -> Self {
         CzscError::Unknown(err)
@@ -77,6 +94,7 @@ impl From<CzscError> for PyErr {
     fn from(err: CzscError) -> PyErr {
         match err {
             CzscError::Validation(msg) => pyo3::exceptions::PyValueError::new_err(msg),
+            CzscError::Interrupted => pyo3::exceptions::PyKeyboardInterrupt::new_err(err.to_string()),
             _ => pyo3::exceptions::PyRuntimeError::new_err(err.to_string()),
         }
     }
@@ -85,6 +103,14 @@ impl From<CzscError> for PyErr {
 // 扩展 Result 类型
 pub type CzscResult<T> = Result<T, CzscError>;
 
+/// 取消标志已置位时返回 `CzscError::Interrupted`，供逐 K 线、逐日的循环检查
+pub fn check_cancelled(cancel: &AtomicBool) -> CzscResult<()> {
+    if cancel.load(Ordering::Relaxed) {
+        return Err(CzscError::Interrupted);
+    }
+    Ok(())
+}
+
 /// 为错误添加上下文
 pub trait ErrorContext<T, E> {
     fn context(self, context: &str) -> CzscResult<T>;
@@ -129,8 +155,9 @@ where
                 CzscError::ColumnNotFound(col) => {
                     CzscError::ColumnNotFound(format!("{}: {}", context, col))
                 }
+                CzscError::Interrupted => CzscError::Interrupted,
                 _ => {
-                    CzscError::Unknown(format!("{}: {}", context, base_err.to_string()))
+                    CzscError::Unknown(format!("{}: {}", context, base_err))
                 }
             }
         })
@@ -0,0 +1,192 @@
+use crate::errors::CzscError::Validation;
+use crate::errors::{check_cancelled, CzscResult};
+use anyhow::anyhow;
+use polars::prelude::*;
+use pyo3::{pyclass, pymethods};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::atomic::AtomicBool;
+
+/// 强制平仓的原因，写入交易对的 event_sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitReason {
+    StopLoss,
+    TakeProfit,
+    TrailingStop,
+    MaxHoldingBars,
+}
+
+impl fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitReason::StopLoss => write!(f, "止损"),
+            ExitReason::TakeProfit => write!(f, "止盈"),
+            ExitReason::TrailingStop => write!(f, "移动止损"),
+            ExitReason::MaxHoldingBars => write!(f, "超时平仓"),
+        }
+    }
+}
+
+/// 出场规则：触发后按当根 K 线价格平仓，并保持空仓直到权重换向或归零
+///
+/// 盈亏以收盘价相对持仓首次开仓价计算，单位为 BP；移动止损以持仓期间最有利的价格为基准
+#[pyclass]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExitRules {
+    /// 亏损达到该 BP 数时止损
+    #[pyo3(get, set)] pub stop_loss       : Option<f64>,
+    /// 盈利达到该 BP 数时止盈
+    #[pyo3(get, set)] pub take_profit     : Option<f64>,
+    /// 从持仓期间最有利价格回撤该 BP 数时平仓
+    #[pyo3(get, set)] pub trailing_stop   : Option<f64>,
+    /// 持仓达到该 K 线数时平仓
+    #[pyo3(get, set)] pub max_holding_bars: Option<usize>,
+}
+
+#[pymethods]
+impl ExitRules {
+    #[new]
+    #[pyo3(signature = (stop_loss=None, take_profit=None, trailing_stop=None, max_holding_bars=None))]
+    pub fn py_new(
+        stop_loss: Option<f64>,
+        take_profit: Option<f64>,
+        trailing_stop: Option<f64>,
+        max_holding_bars: Option<usize>,
+    ) -> Self {
+        ExitRules { stop_loss, take_profit, trailing_stop, max_holding_bars }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+impl ExitRules {
+    /// 检查各参数的取值范围
+    pub fn validate(&self) -> CzscResult<()> {
+        for (name, value) in [
+            ("stop_loss", self.stop_loss),
+            ("take_profit", self.take_profit),
+            ("trailing_stop", self.trailing_stop),
+        ] {
+            if let Some(bps) = value {
+                if !bps.is_finite() || bps <= 0.0 {
+                    return Err(Validation(format!(
+                        "exit_rules.{} must be a positive number of bps, got {}",
+                        name, bps
+                    )));
+                }
+            }
+        }
+        if self.max_holding_bars == Some(0) {
+            return Err(Validation("exit_rules.max_holding_bars must be positive".to_string()));
+        }
+        Ok(())
+    }
+
+    /// 持仓在当前 K 线是否触发出场，依次检查止损、止盈、移动止损与持仓 K 线数
+    fn check(&self, position: &OpenPosition, price: f64) -> Option<ExitReason> {
+        let bps = |from: f64| position.sign * (price / from - 1.0) * 10000.0;
+        let pnl = bps(position.entry_price);
+        if self.stop_loss.is_some_and(|limit| pnl <= -limit) {
+            return Some(ExitReason::StopLoss);
+        }
+        if self.take_profit.is_some_and(|limit| pnl >= limit) {
+            return Some(ExitReason::TakeProfit);
+        }
+        if self.trailing_stop.is_some_and(|limit| bps(position.best_price) <= -limit) {
+            return Some(ExitReason::TrailingStop);
+        }
+        if self.max_holding_bars.is_some_and(|limit| position.bars >= limit) {
+            return Some(ExitReason::MaxHoldingBars);
+        }
+        None
+    }
+}
+
+/// 当前持仓的开仓价、最有利价格与已持有的 K 线数
+struct OpenPosition {
+    sign       : f64,
+    entry_price: f64,
+    best_price : f64,
+    bars       : usize,
+}
+
+/// 对单个品种按时间排列、已取整的数据应用出场规则：触发的 K 线及之后被强制空仓的 K 线
+/// 权重与手数置 0，触发的 K 线在 exit 列记录原因；`cancel` 置位后返回 `CzscError::Interrupted`
+pub fn apply_exit_rules(
+    df: DataFrame,
+    rules: &ExitRules,
+    cancel: &AtomicBool,
+) -> CzscResult<DataFrame> {
+    let weights = df.column("weight")?.f64()?;
+    let volumes = df.column("volume")?.i32()?;
+    let prices = df.column("price")?.f64()?;
+
+    let mut new_weights = Vec::with_capacity(df.height());
+    let mut new_volumes = Vec::with_capacity(df.height());
+    let mut exits: Vec<Option<String>> = Vec::with_capacity(df.height());
+    let mut position: Option<OpenPosition> = None;
+    // 被强制平仓的方向，权重保持该方向时维持空仓
+    let mut blocked: Option<f64> = None;
+
+    for i in 0..df.height() {
+        check_cancelled(cancel)?;
+        let (weight, volume, price) = match (weights.get(i), volumes.get(i), prices.get(i)) {
+            (Some(w), Some(v), Some(p)) => (w, v, p),
+            _ => return Err(anyhow!("DataFrame contains null values in required columns").into()),
+        };
+        let sign = if volume > 0 { 1.0 } else if volume < 0 { -1.0 } else { 0.0 };
+
+        if blocked.is_some_and(|s| s == sign) {
+            new_weights.push(0.0);
+            new_volumes.push(0);
+            exits.push(None);
+            continue;
+        }
+        blocked = None;
+
+        let mut exit = None;
+        match position.as_mut() {
+            Some(open) if open.sign == sign => {
+                open.bars += 1;
+                open.best_price = if sign > 0.0 {
+                    open.best_price.max(price)
+                } else {
+                    open.best_price.min(price)
+                };
+                exit = rules.check(open, price);
+            }
+            _ => {
+                position = (sign != 0.0).then_some(OpenPosition {
+                    sign,
+                    entry_price: price,
+                    best_price: price,
+                    bars: 0,
+                });
+            }
+        }
+
+        match exit {
+            Some(reason) => {
+                new_weights.push(0.0);
+                new_volumes.push(0);
+                exits.push(Some(reason.to_string()));
+                blocked = Some(sign);
+                position = None;
+            }
+            None => {
+                new_weights.push(weight);
+                new_volumes.push(volume);
+                exits.push(None);
+            }
+        }
+    }
+
+    let mut df = df;
+    df.with_column(Column::new("weight".into(), new_weights))?;
+    df.with_column(Column::new("volume".into(), new_volumes))?;
+    df.with_column(Column::new("exit".into(), exits))?;
+    Ok(df)
+}
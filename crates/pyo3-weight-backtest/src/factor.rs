@@ -0,0 +1,294 @@
+use crate::analyzer::{performance_row, performance_table};
+use crate::config::{BacktestConfig, TradingDay, WeightType};
+use crate::errors::CzscError::Validation;
+use crate::errors::{CzscResult, ErrorContext};
+use crate::multi::MultiBacktest;
+use crate::progress::{NoProgress, ProgressMode};
+use crate::types::{BacktestWarning, Direction};
+use crate::utils::{normalize_dt, standard_deviation};
+use polars::prelude::*;
+use pyo3::pyclass;
+use pyo3_polars::PyDataFrame;
+use std::sync::atomic::AtomicBool;
+
+/// 因子评估参数
+#[derive(Debug, Clone)]
+pub struct FactorConfig {
+    /// 分组数
+    pub quantiles: usize,
+    /// IC 衰减的预测期，单位为 K 线数
+    pub horizons : Vec<usize>,
+    /// 分组组合的回测配置，weight_type 固定为截面；组内等权的权重会按 digits 取整
+    pub backtest : BacktestConfig,
+}
+
+impl Default for FactorConfig {
+    fn default() -> Self {
+        FactorConfig {
+            quantiles: 5,
+            horizons : vec![1, 5, 10, 20],
+            backtest : BacktestConfig { digits: 4, ..BacktestConfig::default() },
+        }
+    }
+}
+
+impl FactorConfig {
+    pub fn new(quantiles: usize, horizons: Vec<usize>, backtest: BacktestConfig) -> CzscResult<Self> {
+        let config = FactorConfig { quantiles, horizons, backtest };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 检查各参数的取值范围
+    pub fn validate(&self) -> CzscResult<()> {
+        if self.quantiles < 2 {
+            return Err(Validation(format!("quantiles must be at least 2, got {}", self.quantiles)));
+        }
+        if self.horizons.is_empty() || self.horizons.contains(&0) {
+            return Err(Validation(format!(
+                "horizons must be a non-empty list of positive bar counts, got {:?}",
+                self.horizons
+            )));
+        }
+        self.backtest.validate()
+    }
+}
+
+/// 因子评估结果
+#[pyclass]
+pub struct FactorResult {
+    /// 每期 IC 与秩 IC (dt, ic, rank_ic)，预测期为 1 根 K 线，与 n1b 一致
+    #[pyo3(get)] pub ic_df                  : PyDataFrame,
+    /// 各预测期 IC 的均值、标准差与 IR
+    #[pyo3(get)] pub ic_decay_df            : PyDataFrame,
+    /// 各分组组合及多空组合的日收益 (date, q1..qN, long_short)
+    #[pyo3(get)] pub quantile_returns_df    : PyDataFrame,
+    /// 各分组组合及多空组合的绩效表
+    #[pyo3(get)] pub quantile_performance_df: PyDataFrame,
+    /// 分组组合回测中发现的异常：各组合共有的警告（如价格异常）只保留一条，
+    /// 其余警告的 message 以组合名开头
+    #[pyo3(get)] pub warnings               : Vec<BacktestWarning>,
+}
+
+/// 评估 (dt, symbol, factor, price) 数据中的因子：IC、秩 IC、IC 衰减与分组组合回测
+pub fn evaluate_factor(df: DataFrame, config: &FactorConfig) -> CzscResult<FactorResult> {
+    evaluate_factor_cancellable(df, config, &AtomicBool::new(false))
+}
+
+/// 可中断的因子评估，分组组合回测时逐品种检查 `cancel`
+pub fn evaluate_factor_cancellable(
+    df: DataFrame,
+    config: &FactorConfig,
+    cancel: &AtomicBool,
+) -> CzscResult<FactorResult> {
+    config.validate()?;
+    let df = prepare_factor_df(df, &config.backtest).context("Factor validation")?;
+
+    let ic_df = ic_series(&df, 1)?;
+    let ic_decay_df = ic_decay(&df, &config.horizons)?;
+    let (quantile_returns_df, quantile_performance_df, warnings) =
+        quantile_portfolios(&df, config, cancel)?;
+
+    Ok(FactorResult {
+        ic_df                  : PyDataFrame(ic_df),
+        ic_decay_df            : PyDataFrame(ic_decay_df),
+        quantile_returns_df    : PyDataFrame(quantile_returns_df),
+        quantile_performance_df: PyDataFrame(quantile_performance_df),
+        warnings,
+    })
+}
+
+/// 检查必需列，统一 dt 类型，非有限的因子值视为缺失，按品种、时间排列；
+/// 交易日规则为 Column 时保留交易日列
+fn prepare_factor_df(df: DataFrame, config: &BacktestConfig) -> CzscResult<DataFrame> {
+    for name in ["dt", "symbol", "factor", "price"] {
+        if df.column(name).is_err() {
+            return Err(Validation(format!("DataFrame is missing required column: {}", name)));
+        }
+    }
+    if df.height() == 0 {
+        return Err(Validation("DataFrame is empty".to_string()));
+    }
+
+    let df = normalize_dt(df, &config.timezone)?;
+    let factor = col("factor").cast(DataType::Float64);
+    let mut columns = vec![
+        col("dt"),
+        col("symbol").cast(DataType::String),
+        when(factor.clone().is_finite()).then(factor).otherwise(lit(NULL)).alias("factor"),
+        col("price").cast(DataType::Float64),
+    ];
+    if let TradingDay::Column { name } = &config.trading_day {
+        columns.push(col(name.as_str()));
+    }
+    Ok(df
+        .lazy()
+        .select(columns)
+        .sort(["symbol", "dt"], SortMultipleOptions::default())
+        .collect()?)
+}
+
+/// 品种内 `horizon` 根 K 线后的收益，与 n1b 的计算方式相同
+fn forward_return(horizon: usize) -> Expr {
+    (col("price").shift(Expr::from(-(horizon as i64))) / col("price") - lit(1.0))
+        .over([col("symbol")])
+}
+
+/// 每期因子与前向收益的截面相关系数，无法计算（品种不足或无波动）的期数被跳过
+fn ic_series(df: &DataFrame, horizon: usize) -> CzscResult<DataFrame> {
+    Ok(df
+        .clone()
+        .lazy()
+        .with_column(forward_return(horizon).alias("forward_return"))
+        .filter(col("factor").is_not_null().and(col("forward_return").is_finite()))
+        .group_by([col("dt")])
+        .agg([
+            pearson_corr(col("factor"), col("forward_return")).alias("ic"),
+            spearman_rank_corr(col("factor"), col("forward_return"), false).alias("rank_ic"),
+        ])
+        .filter(col("ic").is_finite().and(col("rank_ic").is_finite()))
+        .sort(["dt"], SortMultipleOptions::default())
+        .collect()?)
+}
+
+/// 各预测期 IC 与秩 IC 的均值、标准差与 IR (均值 / 标准差)
+fn ic_decay(df: &DataFrame, horizons: &[usize]) -> CzscResult<DataFrame> {
+    let mut columns: Vec<(&str, Vec<f64>)> = [
+        "ic_mean", "ic_std", "icir", "rank_ic_mean", "rank_ic_std", "rank_icir",
+    ]
+    .into_iter()
+    .map(|name| (name, Vec::with_capacity(horizons.len())))
+    .collect();
+    let mut periods = Vec::with_capacity(horizons.len());
+
+    for &horizon in horizons {
+        let ic = ic_series(df, horizon)?;
+        periods.push(ic.height() as u32);
+        for (i, name) in ["ic", "rank_ic"].into_iter().enumerate() {
+            let values: Vec<f64> = ic.column(name)?.f64()?.into_no_null_iter().collect();
+            let mean = if values.is_empty() {
+                f64::NAN
+            } else {
+                values.iter().sum::<f64>() / values.len() as f64
+            };
+            let std = standard_deviation(&values);
+            columns[3 * i].1.push(mean);
+            columns[3 * i + 1].1.push(std);
+            columns[3 * i + 2].1.push(if std > 0.0 { mean / std } else { f64::NAN });
+        }
+    }
+
+    let mut result = vec![
+        Column::new("horizon".into(), horizons.iter().map(|&h| h as u32).collect::<Vec<_>>()),
+        Column::new("periods".into(), periods),
+    ];
+    result.extend(columns.into_iter().map(|(name, values)| Column::new(name.into(), values)));
+    Ok(DataFrame::new(result)?)
+}
+
+/// 每期按因子值从小到大分为 N 组，组内等权构成截面组合，在同一价格面板上一并回测；
+/// 多空组合做多因子值最大的一组、做空最小的一组
+fn quantile_portfolios(
+    df: &DataFrame,
+    config: &FactorConfig,
+    cancel: &AtomicBool,
+) -> CzscResult<(DataFrame, DataFrame, Vec<BacktestWarning>)> {
+    let n = config.quantiles;
+    let rank = RankOptions { method: RankMethod::Average, descending: false };
+    let ranked = df
+        .clone()
+        .lazy()
+        .with_columns([
+            col("factor").rank(rank, None).over([col("dt")]).alias("rank"),
+            col("factor").count().over([col("dt")]).alias("count"),
+        ])
+        .with_column(
+            (((col("rank") - lit(1.0)) * lit(n as f64) / col("count").cast(DataType::Float64))
+                .floor()
+                + lit(1.0))
+            .cast(DataType::Int32)
+            .alias("quantile"),
+        )
+        .with_column(
+            (lit(1.0) / len().over([col("dt"), col("quantile")]).cast(DataType::Float64))
+                .alias("quantile_weight"),
+        );
+
+    let member = |q: usize| {
+        when(col("quantile").eq(lit(q as i32)))
+            .then(col("quantile_weight"))
+            .otherwise(lit(0.0))
+    };
+    let mut portfolios: Vec<(String, Expr)> =
+        (1..=n).map(|q| (format!("q{}", q), member(q))).collect();
+    portfolios.push(("long_short".to_string(), member(n) - member(1)));
+
+    // 各组合作为权重列共享同一价格面板，价格检查与按品种拆分只做一次
+    let names: Vec<String> = portfolios.iter().map(|(name, _)| name.clone()).collect();
+    let weighted = ranked
+        .with_columns(portfolios.into_iter().map(|(name, weight)| weight.alias(name)).collect::<Vec<_>>())
+        .drop([col("factor"), col("rank"), col("count"), col("quantile"), col("quantile_weight")])
+        .collect()?;
+
+    let mut backtest = config.backtest.clone();
+    backtest.weight_type = WeightType::CrossSection;
+    backtest.progress = ProgressMode::None;
+    let results = MultiBacktest::new(weighted, &names, backtest.clone())
+        .context("Quantile portfolios")?
+        .run_backtest_with(&NoProgress, cancel)?
+        .results;
+
+    let mut returns_df: Option<DataFrame> = None;
+    let mut rows = Vec::with_capacity(results.len());
+    let mut warnings: Vec<BacktestWarning> = Vec::new();
+    for (name, result) in &results {
+        for warning in &result.warnings {
+            if results.iter().all(|(_, other)| other.warnings.contains(warning)) {
+                if !warnings.contains(warning) {
+                    warnings.push(warning.clone());
+                }
+            } else {
+                let mut warning = warning.clone();
+                warning.message = format!("{}: {}", name, warning.message);
+                warnings.push(warning);
+            }
+        }
+
+        let legs = result.leg_returns_df()?.select(["date", "total"])?;
+        let returns: Vec<f64> = legs.column("total")?.f64()?.into_no_null_iter().collect();
+        let pairs: Vec<_> =
+            result.symbol_results.values().flat_map(|sr| sr.trade_pairs.iter().cloned()).collect();
+        rows.push(performance_row(
+            &returns,
+            &pairs,
+            &[Direction::LongShort],
+            backtest.yearly_days,
+        )?);
+
+        let mut legs = legs;
+        legs.rename("total", name.as_str().into())?;
+        returns_df = Some(match returns_df {
+            None => legs,
+            Some(df) => df
+                .lazy()
+                .join(
+                    legs.lazy(),
+                    [col("date")],
+                    [col("date")],
+                    JoinArgs::new(JoinType::Full).with_coalesce(JoinCoalesce::CoalesceColumns),
+                )
+                .sort(["date"], SortMultipleOptions::default())
+                .collect()?,
+        });
+    }
+
+    let labels = names.iter().map(|name| name.as_str()).collect::<Vec<_>>();
+    let performance_df = performance_table(
+        "portfolio",
+        labels,
+        &[Direction::LongShort],
+        rows,
+        backtest.locale,
+    )?;
+    Ok((returns_df.unwrap_or_default(), performance_df, warnings))
+}
@@ -0,0 +1,316 @@
+use crate::analyzer::PortfolioAnalyzer;
+use crate::config::BacktestConfig;
+use crate::engine::{collect_symbols, prepare_dataframe, BacktestResult};
+use crate::errors::CzscError::Validation;
+use crate::errors::{CzscResult, ErrorContext};
+use crate::portfolio_builder::PositionCounts;
+use crate::processor::{mask_nan_returns, MetricProcessor, PositionTracker};
+use crate::types::{BacktestWarning, DailyMetric, SymbolResult, TradePair};
+use crate::utils::{normalize_dt, validate_trading_day, RoundTo};
+use crate::validation::validate_batch;
+use polars::prelude::*;
+use pyo3::pyclass;
+use pyo3_polars::PyDataFrame;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::AtomicBool;
+
+/// 增量计算时保留的列
+const CARRY_COLUMNS: [&str; 7] =
+    ["dt", "symbol", "weight", "price", "volume", "trade_date", "bar_id"];
+
+/// 单个品种在两次增量更新之间需要保留的状态
+#[derive(Debug, Clone)]
+struct SymbolState {
+    /// 持仓状态与未平仓队列
+    tracker  : PositionTracker,
+    /// 最后一个交易日的全部 K 线及其前一根 K 线；
+    /// 最后一根 K 线的 n1b 需要等到下一根 K 线到来才能确定，该交易日的指标需要重算
+    tail_bars: DataFrame,
+    /// 最后一根 K 线的时间戳
+    last_dt  : i64,
+}
+
+/// 单个交易日全部品种每日指标的累计值；品种最后一个交易日的指标被修正时先减去旧值再计入新值
+#[derive(Debug, Clone, Copy, Default)]
+struct DayTotals {
+    return_sum : f64,
+    n1b_sum    : f64,
+    count      : i64,
+    gross      : f64,
+    net        : f64,
+    long       : f64,
+    short      : f64,
+    long_count : f64,
+    short_count: f64,
+    turnover   : f64,
+}
+
+impl DayTotals {
+    /// `sign` 为 1 时计入该指标，为 -1 时扣除
+    fn apply(&mut self, m: &DailyMetric, sign: i64) {
+        let k = sign as f64;
+        self.return_sum += k * m.return_val;
+        self.n1b_sum += k * m.n1b;
+        self.count += sign;
+        self.gross += k * (m.long_exposure - m.short_exposure);
+        self.net += k * (m.long_exposure + m.short_exposure);
+        self.long += k * m.long_exposure;
+        self.short += k * m.short_exposure;
+        self.long_count += k * m.long_bars;
+        self.short_count += k * m.short_bars;
+        self.turnover += k * m.turnover;
+    }
+}
+
+/// 一次增量更新的输出
+#[pyclass]
+pub struct IncrementalUpdate {
+    /// 本次新增或被修正的每日指标（上一批最后一个交易日会被修正）
+    #[pyo3(get)] pub daily_metrics     : Vec<DailyMetric>,
+    /// 本次新完成的交易对
+    #[pyo3(get)] pub trade_pairs       : Vec<TradePair>,
+    /// 本次数据中发现的异常
+    #[pyo3(get)] pub warnings          : Vec<BacktestWarning>,
+    #[pyo3(get)] pub portfolio_metrics : HashMap<String, f64>,
+    /// 全部交易日的组合日收益 (date, total)
+    #[pyo3(get)] pub daily_return_df   : PyDataFrame,
+}
+
+/// 增量回测引擎
+///
+/// 每次调用 `update` 只处理新追加的 K 线，历史 K 线不会重新计算；
+/// 组合指标基于按交易日累计的汇总刷新，不再重建全部品种的每日指标。
+#[derive(Debug, Clone)]
+pub struct IncrementalEngine {
+    config         : BacktestConfig,
+    processor      : MetricProcessor,
+    states         : HashMap<String, SymbolState>,
+    symbol_results : HashMap<String, SymbolResult>,
+    position_counts: PositionCounts,
+    days           : BTreeMap<String, DayTotals>,
+    next_bar_id    : IdxSize,
+}
+
+impl IncrementalEngine {
+    pub fn new(config: BacktestConfig) -> CzscResult<Self> {
+        config.validate()?;
+        check_supported(&config)?;
+        let processor = MetricProcessor::new(config.clone());
+        Ok(Self {
+            config,
+            processor,
+            states: HashMap::new(),
+            symbol_results: HashMap::new(),
+            position_counts: PositionCounts::default(),
+            days: BTreeMap::new(),
+            next_bar_id: 0,
+        })
+    }
+
+    /// 追加新的 K 线数据，每个品种的新数据必须晚于该品种已处理的最后一根 K 线
+    pub fn update(&mut self, df: DataFrame) -> CzscResult<IncrementalUpdate> {
+        validate_trading_day(df.clone().lazy(), df.schema(), &self.config.trading_day)
+            .context("DataFrame validation")?;
+        let df = normalize_dt(df, &self.config.timezone).context("DataFrame validation")?;
+        let (df, validation_warnings) =
+            validate_batch(df, &self.config.validation).context("DataFrame validation")?;
+        if df.height() == 0 {
+            return Err(Validation("DataFrame is empty after validation".to_string()));
+        }
+
+        let height = df.height() as IdxSize;
+        let prepared_df = prepare_dataframe(df, &self.config, self.next_bar_id)?
+            .select(CARRY_COLUMNS)?;
+
+        // 先检查全部品种，避免部分品种更新后报错导致状态不一致
+        let mut batches = Vec::new();
+        for symbol_df in prepared_df.partition_by_stable(["symbol"], true)? {
+            let symbol = collect_symbols(&symbol_df)?.remove(0);
+            let (first_dt, last_dt) = dt_range(&symbol_df)?;
+            if let Some(state) = self.states.get(&symbol) {
+                if first_dt <= state.last_dt {
+                    return Err(Validation(format!(
+                        "New bars of {} must be later than the last processed bar",
+                        symbol
+                    )));
+                }
+            }
+            batches.push((symbol, symbol_df, last_dt));
+        }
+
+        let mut daily_metrics = Vec::new();
+        let mut trade_pairs = Vec::new();
+        // 数据级警告（如列类型）放在最前面，品种级检查警告交给对应品种
+        let mut warnings: Vec<BacktestWarning> =
+            validation_warnings.iter().filter(|w| w.symbol.is_empty()).cloned().collect();
+        for (symbol, symbol_df, last_dt) in batches {
+            let checked = validation_warnings.iter().filter(|w| w.symbol == symbol).cloned().collect();
+            let (updated, pairs, symbol_warnings) =
+                self.process_symbol(&symbol, symbol_df, last_dt, checked)?;
+            daily_metrics.extend(updated);
+            trade_pairs.extend(pairs);
+            warnings.extend(symbol_warnings);
+        }
+
+        self.next_bar_id += height;
+
+        let daily_summary = self.daily_summary_df()?;
+        let (portfolio_metrics, _, _) = PortfolioAnalyzer::new(
+            self.config.clone(),
+            &self.symbol_results,
+            self.position_counts,
+            &daily_summary,
+        )
+        .analyze_portfolio_metrics()?;
+        Ok(IncrementalUpdate {
+            daily_metrics,
+            trade_pairs,
+            warnings,
+            portfolio_metrics,
+            daily_return_df: PyDataFrame(daily_summary.select(["date", "total"])?),
+        })
+    }
+
+    /// 由按交易日累计的汇总生成 `PortfolioAnalyzer::gen_daily_summary_df` 格式的每日组合汇总
+    fn daily_summary_df(&self) -> CzscResult<DataFrame> {
+        let symbol_count =
+            self.symbol_results.values().filter(|sr| !sr.daily_metrics.is_empty()).count();
+        let scale = self.config.weight_type.portfolio_scale(symbol_count);
+        let days: Vec<(&String, &DayTotals)> = self.days.iter().filter(|(_, t)| t.count > 0).collect();
+        let column = |f: fn(&DayTotals) -> f64| -> Vec<f64> { days.iter().map(|(_, t)| f(t)).collect() };
+        let scaled = |f: fn(&DayTotals) -> f64| -> Vec<f64> {
+            days.iter().map(|(_, t)| f(t) * scale).collect()
+        };
+
+        Ok(df![
+            "date"        => days.iter().map(|(date, _)| date.as_str()).collect::<Vec<_>>(),
+            "total"       => scaled(|t| t.return_sum).into_iter().map(|v| v.round_to(4)).collect::<Vec<_>>(),
+            "strategy"    => column(|t| t.return_sum / t.count as f64),
+            "benchmark"   => column(|t| t.n1b_sum / t.count as f64),
+            "gross"       => scaled(|t| t.gross),
+            "net"         => scaled(|t| t.net),
+            "long"        => scaled(|t| t.long),
+            "short"       => scaled(|t| t.short),
+            "long_count"  => column(|t| t.long_count),
+            "short_count" => column(|t| t.short_count),
+            "turnover"    => scaled(|t| t.turnover),
+        ]?)
+    }
+
+    /// 生成当前累计数据的完整回测结果
+    pub fn result(&self) -> CzscResult<BacktestResult> {
+        BacktestResult::from_symbol_results(
+            &self.config,
+            self.symbol_results.clone(),
+            self.position_counts,
+        )
+    }
+
+    fn process_symbol(
+        &mut self,
+        symbol: &str,
+        symbol_df: DataFrame,
+        last_dt: i64,
+        mut warnings: Vec<BacktestWarning>,
+    ) -> CzscResult<(Vec<DailyMetric>, Vec<TradePair>, Vec<BacktestWarning>)> {
+        let symbol_df = self.processor.clean_bars(symbol, symbol_df, &mut warnings)?;
+        // 多空占比按丢弃异常行后的 K 线统计，与全量回测一致
+        self.position_counts += PositionCounts::from_df(&symbol_df)?;
+        let symbol_result = self
+            .symbol_results
+            .entry(symbol.to_string())
+            .or_insert_with(|| SymbolResult {
+                daily_metrics: vec![],
+                trade_pairs: vec![],
+                warnings: vec![],
+            });
+        if symbol_df.is_empty() {
+            symbol_result.warnings.extend(warnings.iter().cloned());
+            return Ok((vec![], vec![], warnings));
+        }
+
+        let state = self.states.remove(symbol);
+        // 增量更新按批处理，不提供中断
+        let never = AtomicBool::new(false);
+
+        // 上一批保留的尾部 K 线与新数据拼接，重算最后一个交易日的指标；
+        // 首行只用于计算换手，其所在交易日的指标已经确定，不再更新
+        let (metric_df, mut tracker) = match state {
+            Some(state) => (state.tail_bars.vstack(&symbol_df)?, state.tracker),
+            None => (symbol_df.clone(), PositionTracker::default()),
+        };
+        let mut new_metrics = self.processor.process_daily_metrics(symbol, &metric_df, &never)?;
+        if let Some(last) = symbol_result.daily_metrics.last() {
+            new_metrics.retain(|metric| metric.date >= last.date);
+        }
+        warnings.extend(mask_nan_returns(symbol, &mut new_metrics));
+        let trade_pairs = self.processor.track_trade_pairs(
+            symbol,
+            &symbol_df,
+            &mut tracker,
+            &mut warnings,
+            &never,
+        )?;
+
+        let mut updated = Vec::with_capacity(new_metrics.len());
+        for metric in new_metrics {
+            let day = self.days.entry(metric.date.clone()).or_default();
+            match symbol_result.daily_metrics.last_mut() {
+                Some(last) if metric.date == last.date => {
+                    day.apply(last, -1);
+                    *last = metric.clone();
+                }
+                _ => symbol_result.daily_metrics.push(metric.clone()),
+            }
+            day.apply(&metric, 1);
+            updated.push(metric);
+        }
+        symbol_result.trade_pairs.extend(trade_pairs.iter().cloned());
+        symbol_result.warnings.extend(warnings.iter().cloned());
+
+        self.states.insert(
+            symbol.to_string(),
+            SymbolState {
+                tracker,
+                tail_bars: last_day_bars(&metric_df)?,
+                last_dt,
+            },
+        );
+
+        Ok((updated, trade_pairs, warnings))
+    }
+}
+
+/// 波动率目标、出场规则与资金模拟需要回看或回放全部历史，增量回测不支持
+fn check_supported(config: &BacktestConfig) -> CzscResult<()> {
+    let unsupported = [
+        ("vol_target", config.vol_target.is_some()),
+        ("exit_rules", config.exit_rules.is_some()),
+        ("capital", config.capital.is_some()),
+    ];
+    match unsupported.iter().find(|(_, enabled)| *enabled) {
+        Some((name, _)) => {
+            Err(Validation(format!("{} is not supported by incremental backtests", name)))
+        }
+        None => Ok(()),
+    }
+}
+
+/// 截取最后一个交易日的全部 K 线，并带上其前一根 K 线
+fn last_day_bars(df: &DataFrame) -> CzscResult<DataFrame> {
+    let dates = df.column("trade_date")?.date()?.physical();
+    let height = df.height();
+    let last_date = dates.get(height - 1);
+    let last_day_count = (0..height).rev().take_while(|&i| dates.get(i) == last_date).count();
+    let start = (height - last_day_count).saturating_sub(1);
+    Ok(df.slice(start as i64, height - start))
+}
+
+/// 获取 dt 列的起止时间戳
+fn dt_range(df: &DataFrame) -> CzscResult<(i64, i64)> {
+    let dt = df.column("dt")?.datetime()?.physical();
+    match (dt.min(), dt.max()) {
+        (Some(first), Some(last)) => Ok((first, last)),
+        _ => Err(Validation("dt column contains null values".to_string())),
+    }
+}
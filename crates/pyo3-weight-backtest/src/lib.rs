@@ -1,29 +1,58 @@
 mod analyzer;
+pub mod attribution;
+pub mod blend;
+pub mod capital;
 pub mod config;
 pub mod engine;
-mod errors;
+pub mod errors;
+pub mod exits;
+pub mod factor;
+pub mod incremental;
+pub mod multi;
 mod portfolio_builder;
+mod persistence;
 mod processor;
+pub mod progress;
+mod report;
+pub mod source;
 mod stats;
 mod trade_position;
-mod types;
+pub mod types;
 pub mod utils;
+pub mod validation;
+pub mod vol_target;
 
+use crate::blend::{blend_results, BlendMethod, BlendResult};
+use crate::capital::{CapitalConfig, CapitalResult};
 use crate::config::BacktestConfig;
 use crate::engine::{BacktestEngine, BacktestResult};
-use crate::types::{DailyMetric, Direction, SymbolResult, TradePair};
-use polars::prelude::*;
+use crate::errors::{CzscError, CzscResult};
+use crate::exits::ExitRules;
+use crate::factor::{evaluate_factor_cancellable, FactorConfig, FactorResult};
+use crate::incremental::{IncrementalEngine, IncrementalUpdate};
+use crate::multi::{MultiBacktest, MultiBacktestResult};
+use crate::progress::{ProgressMode, ProgressSink};
+use crate::types::{
+    BacktestWarning, DailyMetric, Direction, PortfolioMetrics, SymbolResult, TradePair, WarningKind,
+};
+use crate::vol_target::VolTarget;
 use pyo3::prelude::*;
+use pyo3::types::PyDict;
 use pyo3_polars::PyDataFrame;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
 
 #[pyclass]
 pub struct WeightBacktest {
     engine: BacktestEngine,
 }
 
-#[pymethods]
 impl WeightBacktest {
-    #[new]
+    /// 使用默认选项创建回测
     pub fn new(
         py_df: PyDataFrame,
         digits: usize,
@@ -44,13 +73,406 @@ impl WeightBacktest {
             engine: BacktestEngine::new(py_df.into(), config)?
         })
     }
+}
+
+#[pymethods]
+impl WeightBacktest {
+    /// 可选参数只能以关键字传入；vol_lookback/max_leverage 需配合 vol_target 使用，
+    /// timezone 与 trading_day 的写法与 `evaluate_factor` 相同
+    #[new]
+    #[pyo3(signature = (py_df, digits, weight_type, fee_rate, yearly_days, n_jobs, *, locale="zh",
+                        vol_target=None, vol_lookback=None, max_leverage=None, exit_rules=None,
+                        capital=None, timezone=None, trading_day=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        py_df: PyDataFrame,
+        digits: usize,
+        weight_type: &str,
+        fee_rate: f32,
+        yearly_days: usize,
+        n_jobs: usize,
+        locale: &str,
+        vol_target: Option<f64>,
+        vol_lookback: Option<usize>,
+        max_leverage: Option<f64>,
+        exit_rules: Option<ExitRules>,
+        capital: Option<CapitalConfig>,
+        timezone: Option<String>,
+        trading_day: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        let mut config = BacktestConfig::new(
+            digits,
+            fee_rate,
+            weight_type.to_string(),
+            yearly_days,
+            n_jobs,
+        )?;
+        config.locale = locale.parse()?;
+        config.vol_target = match vol_target {
+            Some(target) => {
+                let defaults = VolTarget::default();
+                Some(VolTarget {
+                    target,
+                    lookback    : vol_lookback.unwrap_or(defaults.lookback),
+                    max_leverage: max_leverage.unwrap_or(defaults.max_leverage),
+                })
+            }
+            None if vol_lookback.is_some() || max_leverage.is_some() => {
+                return Err(CzscError::Validation(
+                    "vol_lookback/max_leverage 需要同时设置 vol_target".to_string(),
+                ).into());
+            }
+            None => None,
+        };
+        config.exit_rules = exit_rules;
+        config.capital = capital;
+        set_trading_calendar(&mut config, timezone, trading_day)?;
+        config.validate()?;
+
+        Ok(WeightBacktest {
+            engine: BacktestEngine::new(py_df.into(), config)?
+        })
+    }
+
+    /// 使用 TOML/JSON 配置文件创建回测
+    #[staticmethod]
+    pub fn from_config_file(py_df: PyDataFrame, config_path: PathBuf) -> PyResult<Self> {
+        let config = BacktestConfig::from_file(&config_path)?;
+
+        Ok(WeightBacktest {
+            engine: BacktestEngine::new(py_df.into(), config)?
+        })
+    }
+
+    /// 从 Parquet/IPC/CSV 文件惰性加载数据，回测时按品种逐个载入
+    #[staticmethod]
+    #[pyo3(signature = (paths, digits, weight_type, fee_rate, yearly_days, n_jobs, hive_partitioned=false,
+                        locale="zh", *, timezone=None, trading_day=None))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_files(
+        paths: Vec<PathBuf>,
+        digits: usize,
+        weight_type: &str,
+        fee_rate: f32,
+        yearly_days: usize,
+        n_jobs: usize,
+        hive_partitioned: bool,
+        locale: &str,
+        timezone: Option<String>,
+        trading_day: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        let mut config = BacktestConfig::new(
+            digits,
+            fee_rate,
+            weight_type.to_string(),
+            yearly_days,
+            n_jobs,
+        )?;
+        config.locale = locale.parse()?;
+        set_trading_calendar(&mut config, timezone, trading_day)?;
+
+        Ok(WeightBacktest {
+            engine: BacktestEngine::from_files(&paths, hive_partitioned, config)?
+        })
+    }
+
+    /// 创建时数据检查产生的警告
+    #[getter]
+    pub fn validation_warnings(&self) -> Vec<BacktestWarning> {
+        self.engine.validation_warnings().to_vec()
+    }
+
+    /// 回测期间释放 GIL，Ctrl-C 会中断回测并抛出 KeyboardInterrupt
+    ///
+    /// `progress` 可为 None（使用配置）、"none"、"bar"，或接收 (done, total, symbol) 的可调用对象
+    #[pyo3(signature = (progress=None))]
+    pub fn run_backtest(
+        &self,
+        py: Python<'_>,
+        progress: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<BacktestResult> {
+        let total = self.engine.symbols().len();
+        run_with_progress(py, progress, self.engine.config().progress, total, |sink, cancel| {
+            self.engine.run_backtest_with(sink, cancel)
+        })
+    }
+}
+
+/// 同一价格面板上多个权重列的回测，价格相关的处理只做一次
+#[pyclass]
+pub struct MultiWeightBacktest {
+    engine: MultiBacktest,
+}
+
+#[pymethods]
+impl MultiWeightBacktest {
+    #[new]
+    #[pyo3(signature = (py_df, weight_columns, digits, weight_type, fee_rate, yearly_days, n_jobs,
+                        locale="zh", *, timezone=None, trading_day=None))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        py_df: PyDataFrame,
+        weight_columns: Vec<String>,
+        digits: usize,
+        weight_type: &str,
+        fee_rate: f32,
+        yearly_days: usize,
+        n_jobs: usize,
+        locale: &str,
+        timezone: Option<String>,
+        trading_day: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        let mut config = BacktestConfig::new(
+            digits,
+            fee_rate,
+            weight_type.to_string(),
+            yearly_days,
+            n_jobs,
+        )?;
+        config.locale = locale.parse()?;
+        set_trading_calendar(&mut config, timezone, trading_day)?;
+
+        Ok(MultiWeightBacktest {
+            engine: MultiBacktest::new(py_df.into(), &weight_columns, config)?
+        })
+    }
+
+    /// 策略名，即权重列名
+    #[getter]
+    pub fn strategies(&self) -> Vec<String> {
+        self.engine.strategies().into_iter().map(|s| s.to_string()).collect()
+    }
 
-    pub fn run_backtest(&self) -> PyResult<BacktestResult> {
-        let result = self.engine.run_backtest()?;
+    /// 依次回测各策略，期间释放 GIL，Ctrl-C 可中断；`progress` 的含义与 `WeightBacktest` 相同
+    #[pyo3(signature = (progress=None))]
+    pub fn run_backtest(
+        &self,
+        py: Python<'_>,
+        progress: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<MultiBacktestResult> {
+        let total = self.engine.symbols().len() * self.engine.strategies().len();
+        run_with_progress(py, progress, self.engine.config().progress, total, |sink, cancel| {
+            self.engine.run_backtest_with(sink, cancel)
+        })
+    }
+}
+
+#[pyclass]
+pub struct IncrementalBacktest {
+    engine: IncrementalEngine,
+}
+
+#[pymethods]
+impl IncrementalBacktest {
+    #[new]
+    #[pyo3(signature = (digits, weight_type, fee_rate, yearly_days, locale="zh", *, timezone=None,
+                        trading_day=None))]
+    pub fn new(
+        digits: usize,
+        weight_type: &str,
+        fee_rate: f32,
+        yearly_days: usize,
+        locale: &str,
+        timezone: Option<String>,
+        trading_day: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        let mut config = BacktestConfig::new(
+            digits,
+            fee_rate,
+            weight_type.to_string(),
+            yearly_days,
+            1,
+        )?;
+        config.locale = locale.parse()?;
+        set_trading_calendar(&mut config, timezone, trading_day)?;
+
+        Ok(IncrementalBacktest {
+            engine: IncrementalEngine::new(config)?,
+        })
+    }
+
+    pub fn update(&mut self, py_df: PyDataFrame) -> PyResult<IncrementalUpdate> {
+        let update = self.engine.update(py_df.into())?;
+        Ok(update)
+    }
+
+    pub fn result(&self) -> PyResult<BacktestResult> {
+        let result = self.engine.result()?;
         Ok(result)
     }
 }
 
+/// 按关键字参数设置交易所时区与交易日规则；trading_day 与配置文件写法相同，
+/// 如 {"rule": "cutoff", "hour": 18}
+fn set_trading_calendar(
+    config: &mut BacktestConfig,
+    timezone: Option<String>,
+    trading_day: Option<&Bound<'_, PyDict>>,
+) -> PyResult<()> {
+    if let Some(timezone) = timezone {
+        config.timezone = timezone;
+    }
+    if let Some(trading_day) = trading_day {
+        let json: String =
+            trading_day.py().import("json")?.call_method1("dumps", (trading_day,))?.extract()?;
+        config.trading_day = serde_json::from_str(&json).map_err(CzscError::from)?;
+    }
+    Ok(())
+}
+
+/// 评估 (dt, symbol, factor, price) 数据中的因子：IC、秩 IC、IC 衰减与分组组合回测，
+/// 评估期间释放 GIL，Ctrl-C 可中断；trading_day 与配置文件写法相同，如 {"rule": "cutoff", "hour": 18}
+#[pyfunction]
+#[pyo3(signature = (
+    py_df, quantiles=5, horizons=vec![1, 5, 10, 20], digits=4, fee_rate=0.0002,
+    yearly_days=252, n_jobs=1, locale="zh", timezone=None, trading_day=None
+))]
+#[allow(clippy::too_many_arguments)]
+fn evaluate_factor(
+    py: Python<'_>,
+    py_df: PyDataFrame,
+    quantiles: usize,
+    horizons: Vec<usize>,
+    digits: usize,
+    fee_rate: f32,
+    yearly_days: usize,
+    n_jobs: usize,
+    locale: &str,
+    timezone: Option<String>,
+    trading_day: Option<&Bound<'_, PyDict>>,
+) -> PyResult<FactorResult> {
+    let mut backtest = BacktestConfig::new(digits, fee_rate, "cs".to_string(), yearly_days, n_jobs)?;
+    backtest.locale = locale.parse()?;
+    set_trading_calendar(&mut backtest, timezone, trading_day)?;
+    let config = FactorConfig::new(quantiles, horizons, backtest)?;
+    let df = py_df.into();
+    run_interruptible(py, |cancel| evaluate_factor_cancellable(df, &config, cancel))
+}
+
+/// 由多个回测结果构建多策略组合，`results` 为 {策略名: BacktestResult} 字典，按插入顺序排列；
+/// method 为 "fixed"（需给出 weights）、"inverse_vol"、"equal_risk" 或 "max_sharpe"
+#[pyfunction]
+#[pyo3(name = "blend_results", signature = (results, method="equal_risk", weights=None, window=60))]
+fn py_blend_results(
+    results: &Bound<'_, PyDict>,
+    method: &str,
+    weights: Option<Vec<f64>>,
+    window: usize,
+) -> PyResult<BlendResult> {
+    let results = results
+        .iter()
+        .map(|(name, result)| Ok((name.extract::<String>()?, result.extract::<PyRef<BacktestResult>>()?)))
+        .collect::<PyResult<Vec<_>>>()?;
+    let results: Vec<(&str, &BacktestResult)> =
+        results.iter().map(|(name, result)| (name.as_str(), &**result)).collect();
+    Ok(blend_results(&results, &BlendMethod::from_parts(method, weights, window)?)?)
+}
+
+/// 将回测进度转发给 Python 回调 `callback(done, total, symbol)`；
+/// 回调抛出异常时保存异常并置位取消标志，不再调用回调
+struct PyProgress<'a> {
+    callback: PyObject,
+    cancel  : &'a AtomicBool,
+    error   : &'a Mutex<Option<PyErr>>,
+}
+
+impl ProgressSink for PyProgress<'_> {
+    fn update(&self, done: usize, total: usize, symbol: &str) {
+        if self.cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        Python::with_gil(|py| {
+            if let Err(err) = self.callback.call1(py, (done, total, symbol)) {
+                self.error.lock().unwrap().get_or_insert(err);
+                self.cancel.store(true, Ordering::Relaxed);
+            }
+        });
+    }
+}
+
+/// 按 `progress` 参数选择进度输出并通过 `run_interruptible` 执行 `job`，
+/// 回调抛出的异常（包括 KeyboardInterrupt）在回测结束后重新抛出
+fn run_with_progress<T, F>(
+    py: Python<'_>,
+    progress: Option<Bound<'_, PyAny>>,
+    default: ProgressMode,
+    total: usize,
+    job: F,
+) -> PyResult<T>
+where
+    T: Send,
+    F: FnOnce(&dyn ProgressSink, &AtomicBool) -> CzscResult<T> + Send,
+{
+    let (callback, mode) = match progress {
+        None => (None, default),
+        Some(obj) if obj.is_callable() => (Some(obj.unbind()), ProgressMode::None),
+        Some(obj) => (None, obj.extract::<String>()?.parse::<ProgressMode>()?),
+    };
+    let error = Mutex::new(None);
+    let result = run_interruptible(py, |cancel| {
+        let sink: Box<dyn ProgressSink + '_> = match callback {
+            Some(callback) => Box::new(PyProgress { callback, cancel, error: &error }),
+            None => mode.sink(total),
+        };
+        job(sink.as_ref(), cancel)
+    });
+    match error.into_inner().unwrap() {
+        Some(err) => Err(err),
+        None => result,
+    }
+}
+
+/// 信号检查间隔
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// 回测线程栈大小，与并行线程池保持一致
+const WORKER_STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// 在释放 GIL 的后台线程中执行 `job`，当前线程定期检查 Python 信号，
+/// 收到中断时置位取消标志，由 `job` 在下一次检查时返回 `Interrupted`
+fn run_interruptible<T, F>(py: Python<'_>, job: F) -> PyResult<T>
+where
+    T: Send,
+    F: FnOnce(&AtomicBool) -> CzscResult<T> + Send,
+{
+    let cancel = AtomicBool::new(false);
+    let mut signal_err = None;
+
+    let result = py.allow_threads(|| {
+        thread::scope(|scope| {
+            let (tx, rx) = mpsc::channel();
+            let cancel = &cancel;
+            thread::Builder::new()
+                .stack_size(WORKER_STACK_SIZE)
+                .spawn_scoped(scope, move || {
+                    let _ = tx.send(job(cancel));
+                })?;
+
+            loop {
+                match rx.recv_timeout(SIGNAL_POLL_INTERVAL) {
+                    Ok(result) => break result,
+                    Err(RecvTimeoutError::Timeout) => {
+                        if signal_err.is_none() {
+                            if let Err(err) = Python::with_gil(|py| py.check_signals()) {
+                                signal_err = Some(err);
+                                cancel.store(true, Ordering::Relaxed);
+                            }
+                        }
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        break Err(CzscError::Unknown("backtest thread panicked".to_string()))
+                    }
+                }
+            }
+        })
+    });
+
+    match (result, signal_err) {
+        // 优先抛出信号处理函数产生的异常（通常为 KeyboardInterrupt）
+        (Err(CzscError::Interrupted), Some(err)) => Err(err),
+        (result, _) => Ok(result?),
+    }
+}
+
 #[pymodule]
 fn weight_backtest_pyo3(_py: Python, m: &Bound<PyModule>) -> PyResult<()> {
     m.add_class::<WeightBacktest>()?;
@@ -58,6 +480,20 @@ fn weight_backtest_pyo3(_py: Python, m: &Bound<PyModule>) -> PyResult<()> {
     m.add_class::<DailyMetric>()?;
     m.add_class::<TradePair>()?;
     m.add_class::<SymbolResult>()?;
+    m.add_class::<WarningKind>()?;
+    m.add_class::<BacktestWarning>()?;
+    m.add_class::<PortfolioMetrics>()?;
     m.add_class::<BacktestResult>()?;
+    m.add_class::<IncrementalBacktest>()?;
+    m.add_class::<IncrementalUpdate>()?;
+    m.add_class::<FactorResult>()?;
+    m.add_class::<MultiWeightBacktest>()?;
+    m.add_class::<MultiBacktestResult>()?;
+    m.add_class::<BlendResult>()?;
+    m.add_class::<ExitRules>()?;
+    m.add_class::<CapitalConfig>()?;
+    m.add_class::<CapitalResult>()?;
+    m.add_function(wrap_pyfunction!(py_blend_results, m)?)?;
+    m.add_function(wrap_pyfunction!(evaluate_factor, m)?)?;
     Ok(())
 }
@@ -0,0 +1,263 @@
+use crate::blend::{blend_results, BlendMethod, BlendResult};
+use crate::config::BacktestConfig;
+use crate::engine::{check_vol_target, BacktestEngine, BacktestResult};
+use crate::errors::CzscError::Validation;
+use crate::errors::{CzscResult, ErrorContext};
+use crate::processor::n1b_expr;
+use crate::progress::ProgressSink;
+use crate::types::{BacktestWarning, MetricKey, WarningKind};
+use crate::utils::{normalize_dt, validate_trading_day};
+use crate::validation::{validate_bars, CheckAction};
+use polars::prelude::*;
+use pyo3::{pyclass, pymethods, PyResult};
+use pyo3_polars::PyDataFrame;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// 引擎内部使用的列名，不能作为策略权重列
+const RESERVED_COLUMNS: [&str; 8] =
+    ["dt", "symbol", "price", "weight", "n1b", "bar_id", "volume", "trade_date"];
+
+/// 同一价格面板上多个权重列（策略）的回测：价格检查、排序、n1b 与按品种拆分只做一次，
+/// 各策略只检查自己的权重列
+#[derive(Debug, Clone)]
+pub struct MultiBacktest {
+    engines: Vec<(String, BacktestEngine)>,
+}
+
+/// 多策略回测结果，按权重列的顺序排列
+#[pyclass]
+#[derive(Clone)]
+pub struct MultiBacktestResult {
+    pub results: Vec<(String, BacktestResult)>,
+    pub config : BacktestConfig,
+}
+
+impl MultiBacktest {
+    pub fn new(df: DataFrame, weight_columns: &[String], config: BacktestConfig) -> CzscResult<Self> {
+        config.validate()?;
+        check_vol_target(&config)?;
+        check_weight_columns(&df, weight_columns).context("DataFrame validation")?;
+        validate_trading_day(df.clone().lazy(), df.schema(), &config.trading_day)
+            .context("DataFrame validation")?;
+        let df = normalize_dt(df, &config.timezone).context("DataFrame validation")?;
+
+        // 各策略的权重列类型
+        let mut casts = Vec::new();
+        let mut weight_warnings: HashMap<&str, Vec<BacktestWarning>> = HashMap::new();
+        for name in weight_columns {
+            let dtype = df.column(name)?.dtype();
+            if *dtype == DataType::Float64 {
+                continue;
+            }
+            let message = format!("column {} has dtype {}, expected f64", name, dtype);
+            let message = match config.validation.dtypes {
+                CheckAction::Error => return Err(Validation(message)),
+                CheckAction::Warn => message,
+                CheckAction::Fix => {
+                    casts.push(col(name.as_str()).strict_cast(DataType::Float64));
+                    format!("{}, cast to f64", message)
+                }
+            };
+            weight_warnings.entry(name.as_str()).or_default().push(BacktestWarning::new(
+                "",
+                "",
+                WarningKind::DtypeMismatch,
+                message,
+            ));
+        }
+        let df = if casts.is_empty() { df } else { df.lazy().with_columns(casts).collect()? };
+
+        // 价格检查只做一次，权重列以 0 占位，各策略的权重在回测时逐品种检查
+        let df = df.lazy().with_column(lit(0.0).alias("weight")).collect()?;
+        let (df, price_warnings) =
+            validate_bars(df, &config.validation).context("DataFrame validation")?;
+        if df.height() == 0 {
+            return Err(Validation("DataFrame is empty after validation".to_string()));
+        }
+
+        // 与单策略回测相同：按时间、品种排序后统一编号，再按品种计算 n1b
+        let df = df
+            .drop("weight")?
+            .sort(["dt", "symbol"], SortMultipleOptions::default().with_maintain_order(true))?
+            .with_row_index("bar_id".into(), None)?
+            .lazy()
+            .with_column(n1b_expr().over([col("symbol")]))
+            .collect()?;
+        let parts: HashMap<String, DataFrame> = df
+            .partition_by_stable(["symbol"], true)?
+            .into_iter()
+            .map(|part| {
+                let symbol = part.column("symbol")?.str()?.get(0).unwrap_or_default().to_string();
+                Ok((symbol, part))
+            })
+            .collect::<CzscResult<_>>()?;
+        let parts = Arc::new(parts);
+
+        let engines = weight_columns
+            .iter()
+            .map(|name| {
+                let mut warnings = price_warnings.clone();
+                warnings.extend(weight_warnings.remove(name.as_str()).unwrap_or_default());
+                let engine = BacktestEngine::from_shared(parts.clone(), name, config.clone(), warnings);
+                (name.clone(), engine)
+            })
+            .collect();
+        Ok(Self { engines })
+    }
+
+    /// 策略名，即权重列名
+    pub fn strategies(&self) -> Vec<&str> {
+        self.engines.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// 待回测的品种
+    pub fn symbols(&self) -> &[String] {
+        self.engines[0].1.symbols()
+    }
+
+    pub fn config(&self) -> &BacktestConfig {
+        self.engines[0].1.config()
+    }
+
+    pub fn run_backtest(&self) -> CzscResult<MultiBacktestResult> {
+        let progress = self.config().progress.sink(self.symbols().len() * self.engines.len());
+        self.run_backtest_with(progress.as_ref(), &AtomicBool::new(false))
+    }
+
+    /// 依次回测各策略，进度按全部策略的品种总数累计
+    pub fn run_backtest_with(
+        &self,
+        progress: &dyn ProgressSink,
+        cancel: &AtomicBool,
+    ) -> CzscResult<MultiBacktestResult> {
+        let per_strategy = self.symbols().len();
+        let total = per_strategy * self.engines.len();
+
+        let mut results = Vec::with_capacity(self.engines.len());
+        for (i, (name, engine)) in self.engines.iter().enumerate() {
+            let offset = OffsetProgress { inner: progress, offset: i * per_strategy, total };
+            let result = engine.run_backtest_with(&offset, cancel)?;
+            results.push((name.clone(), result));
+        }
+        progress.finish();
+
+        Ok(MultiBacktestResult { results, config: self.config().clone() })
+    }
+}
+
+#[pymethods]
+impl MultiBacktestResult {
+    /// 策略名，按权重列的顺序
+    #[getter(strategies)]
+    pub fn py_strategies(&self) -> Vec<String> {
+        self.strategies().into_iter().map(|s| s.to_string()).collect()
+    }
+
+    /// 单个策略的完整回测结果
+    #[pyo3(name = "result")]
+    pub fn py_result(&self, strategy: &str) -> PyResult<BacktestResult> {
+        Ok(self
+            .get(strategy)
+            .cloned()
+            .ok_or_else(|| Validation(format!("Strategy {} not found in result", strategy)))?)
+    }
+
+    /// 各策略组合指标的对比表
+    #[pyo3(name = "comparison_df")]
+    pub fn py_comparison_df(&self) -> PyResult<PyDataFrame> {
+        Ok(PyDataFrame(self.comparison_df()?))
+    }
+
+    /// 构建多策略组合，method 为 "fixed"（需给出 weights）、"inverse_vol"、"equal_risk" 或 "max_sharpe"
+    #[pyo3(name = "blend", signature = (method="equal_risk", weights=None, window=60))]
+    pub fn py_blend(
+        &self,
+        method: &str,
+        weights: Option<Vec<f64>>,
+        window: usize,
+    ) -> PyResult<BlendResult> {
+        Ok(self.blend(&BlendMethod::from_parts(method, weights, window)?)?)
+    }
+}
+
+impl MultiBacktestResult {
+    /// 策略名，按权重列的顺序
+    pub fn strategies(&self) -> Vec<&str> {
+        self.results.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    pub fn get(&self, strategy: &str) -> Option<&BacktestResult> {
+        self.results.iter().find(|(name, _)| name == strategy).map(|(_, r)| r)
+    }
+
+    /// 以各策略的组合日收益构建多策略组合
+    pub fn blend(&self, method: &BlendMethod) -> CzscResult<BlendResult> {
+        let results: Vec<(&str, &BacktestResult)> =
+            self.results.iter().map(|(name, r)| (name.as_str(), r)).collect();
+        blend_results(&results, method)
+    }
+
+    /// 各策略组合指标的对比表，每个策略一行，列为组合指标字典中的指标，键语言由配置决定
+    pub fn comparison_df(&self) -> CzscResult<DataFrame> {
+        let locale = self.config.locale;
+        let names: Vec<&str> = self.strategies();
+        let mut columns = vec![Column::new("strategy".into(), names)];
+        for key in MetricKey::ALL {
+            let label = key.label(locale);
+            let values: Option<Vec<f64>> = self
+                .results
+                .iter()
+                .map(|(_, r)| r.portfolio_metrics.get(label).copied())
+                .collect();
+            if let Some(values) = values {
+                columns.push(Column::new(label.into(), values));
+            }
+        }
+        Ok(DataFrame::new(columns)?)
+    }
+}
+
+/// 检查权重列存在、不重名且不与内部列冲突
+fn check_weight_columns(df: &DataFrame, weight_columns: &[String]) -> CzscResult<()> {
+    if weight_columns.is_empty() {
+        return Err(Validation("at least one weight column is required".to_string()));
+    }
+    for name in ["dt", "symbol", "price"] {
+        if df.column(name).is_err() {
+            return Err(Validation(format!("DataFrame is missing required column: {}", name)));
+        }
+    }
+    let mut seen = HashSet::new();
+    for name in weight_columns {
+        if RESERVED_COLUMNS.contains(&name.as_str()) {
+            return Err(Validation(format!("{} is reserved and cannot be a weight column", name)));
+        }
+        if !seen.insert(name) {
+            return Err(Validation(format!("weight column {} is listed twice", name)));
+        }
+        if df.column(name).is_err() {
+            return Err(Validation(format!("DataFrame is missing weight column: {}", name)));
+        }
+    }
+    if df.height() == 0 {
+        return Err(Validation("DataFrame is empty".to_string()));
+    }
+    Ok(())
+}
+
+/// 把单个策略的进度换算为全部策略的累计进度，结束由外层统一通知
+struct OffsetProgress<'a> {
+    inner : &'a dyn ProgressSink,
+    offset: usize,
+    total : usize,
+}
+
+impl ProgressSink for OffsetProgress<'_> {
+    fn update(&self, done: usize, _total: usize, symbol: &str) {
+        self.inner.update(self.offset + done, self.total, symbol);
+    }
+
+    fn finish(&self) {}
+}
@@ -0,0 +1,244 @@
+use crate::capital::CapitalResult;
+use crate::config::BacktestConfig;
+use crate::engine::BacktestResult;
+use crate::errors::CzscError::Validation;
+use crate::errors::CzscResult;
+use crate::types::{
+    BacktestWarning, DailyMetric, JsonF64, PortfolioMetrics, SymbolResult, TradePair,
+};
+use polars::prelude::*;
+use pyo3_polars::PyDataFrame;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+/// 结果目录格式版本
+const MANIFEST_VERSION: u32 = 1;
+const MANIFEST_FILE: &str = "manifest.json";
+const DAILY_EW_RETURN_FILE: &str = "daily_ew_return.parquet";
+const DAILY_METRICS_FILE: &str = "daily_metrics.parquet";
+const TRADE_PAIRS_FILE: &str = "trade_pairs.parquet";
+const CAPITAL_NAV_FILE: &str = "capital_nav.parquet";
+
+/// 结果目录的清单文件
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    version          : u32,
+    config           : BacktestConfig,
+    symbols          : Vec<String>,
+    /// 非有限值按 `JsonF64` 的规则保存，读回后与原值一致
+    portfolio_metrics: HashMap<String, JsonF64>,
+    metrics          : PortfolioMetrics,
+    warnings         : Vec<BacktestWarning>,
+}
+
+impl BacktestResult {
+    /// 将完整回测结果保存到目录：三张 Parquet 表加一个 JSON 清单，有资金模拟时另存资金净值表
+    pub fn save(&self, dir: &Path) -> CzscResult<()> {
+        std::fs::create_dir_all(dir)?;
+
+        write_parquet(&mut self.daily_ew_return_df.0.clone(), &dir.join(DAILY_EW_RETURN_FILE))?;
+        write_parquet(&mut self.daily_metrics_df(None)?, &dir.join(DAILY_METRICS_FILE))?;
+        write_parquet(&mut self.trade_pairs_df(None)?, &dir.join(TRADE_PAIRS_FILE))?;
+        if let Some(capital) = &self.capital {
+            write_parquet(&mut capital.nav_df.0.clone(), &dir.join(CAPITAL_NAV_FILE))?;
+        }
+
+        let mut symbols: Vec<String> = self.symbol_results.keys().cloned().collect();
+        symbols.sort();
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            config: self.config.clone(),
+            symbols,
+            portfolio_metrics: self
+                .portfolio_metrics
+                .iter()
+                .map(|(k, v)| (k.clone(), JsonF64(*v)))
+                .collect(),
+            metrics: self.metrics.clone(),
+            warnings: self.warnings.clone(),
+        };
+        let file = File::create(dir.join(MANIFEST_FILE))?;
+        serde_json::to_writer_pretty(file, &manifest)?;
+        Ok(())
+    }
+
+    /// 从 `save` 写出的目录重建回测结果
+    pub fn load(dir: &Path) -> CzscResult<Self> {
+        let manifest: Manifest = serde_json::from_reader(File::open(dir.join(MANIFEST_FILE))?)?;
+        if manifest.version != MANIFEST_VERSION {
+            return Err(Validation(format!(
+                "Unsupported result version {}, expected {}",
+                manifest.version, MANIFEST_VERSION
+            )));
+        }
+        manifest.config.validate()?;
+
+        let daily_ew_return_df = read_parquet(&dir.join(DAILY_EW_RETURN_FILE))?;
+        let daily_metrics = daily_metrics_from_df(&read_parquet(&dir.join(DAILY_METRICS_FILE))?)?;
+        let trade_pairs = trade_pairs_from_df(&read_parquet(&dir.join(TRADE_PAIRS_FILE))?)?;
+
+        let mut symbol_results: HashMap<String, SymbolResult> = manifest
+            .symbols
+            .into_iter()
+            .map(|symbol| {
+                let sr = SymbolResult {
+                    daily_metrics: vec![],
+                    trade_pairs: vec![],
+                    warnings: vec![],
+                };
+                (symbol, sr)
+            })
+            .collect();
+        for metric in daily_metrics {
+            symbol_result_mut(&mut symbol_results, &metric.symbol)?.daily_metrics.push(metric);
+        }
+        for pair in trade_pairs {
+            symbol_result_mut(&mut symbol_results, &pair.symbol)?.trade_pairs.push(pair);
+        }
+        // 检查时被丢弃或没有剩余 K 线的品种不在清单中，其警告只保留在结果级 warnings
+        for warning in &manifest.warnings {
+            if let Some(sr) = symbol_results.get_mut(&warning.symbol) {
+                sr.warnings.push(warning.clone());
+            }
+        }
+
+        // 资金模拟的绩效指标由净值表重新计算
+        let capital = match &manifest.config.capital {
+            Some(capital) => {
+                let path = dir.join(CAPITAL_NAV_FILE);
+                if !path.exists() {
+                    return Err(Validation(format!(
+                        "{} is required by the capital config but missing",
+                        CAPITAL_NAV_FILE
+                    )));
+                }
+                Some(CapitalResult::from_nav_df(
+                    read_parquet(&path)?,
+                    capital.initial_capital,
+                    manifest.config.yearly_days,
+                    manifest.config.locale,
+                )?)
+            }
+            None => None,
+        };
+
+        Ok(BacktestResult {
+            symbol_results,
+            portfolio_metrics: manifest
+                .portfolio_metrics
+                .into_iter()
+                .map(|(k, v)| (k, v.0))
+                .collect(),
+            metrics: manifest.metrics,
+            warnings: manifest.warnings,
+            daily_ew_return_df: PyDataFrame(daily_ew_return_df),
+            capital,
+            config: manifest.config,
+        })
+    }
+}
+
+fn symbol_result_mut<'a>(
+    symbol_results: &'a mut HashMap<String, SymbolResult>,
+    symbol: &str,
+) -> CzscResult<&'a mut SymbolResult> {
+    symbol_results
+        .get_mut(symbol)
+        .ok_or_else(|| Validation(format!("Symbol {} is not listed in the manifest", symbol)))
+}
+
+fn write_parquet(df: &mut DataFrame, path: &Path) -> CzscResult<()> {
+    ParquetWriter::new(File::create(path)?).finish(df)?;
+    Ok(())
+}
+
+fn read_parquet(path: &Path) -> CzscResult<DataFrame> {
+    Ok(ParquetReader::new(File::open(path)?).finish()?)
+}
+
+fn daily_metrics_from_df(df: &DataFrame) -> CzscResult<Vec<DailyMetric>> {
+    let date = df.column("date")?.str()?;
+    let symbol = df.column("symbol")?.str()?;
+    let f64_col = |name: &str| -> CzscResult<Vec<f64>> {
+        Ok(df.column(name)?.f64()?.into_iter().map(|v| v.unwrap_or(f64::NAN)).collect())
+    };
+    let edge = f64_col("edge")?;
+    let return_val = f64_col("return")?;
+    let cost = f64_col("cost")?;
+    let n1b = f64_col("n1b")?;
+    let turnover = f64_col("turnover")?;
+    let long_edge = f64_col("long_edge")?;
+    let long_cost = f64_col("long_cost")?;
+    let long_return = f64_col("long_return")?;
+    let long_turnover = f64_col("long_turnover")?;
+    let short_edge = f64_col("short_edge")?;
+    let short_cost = f64_col("short_cost")?;
+    let short_return = f64_col("short_return")?;
+    let short_turnover = f64_col("short_turnover")?;
+    let weight = f64_col("weight")?;
+    let long_exposure = f64_col("long_exposure")?;
+    let short_exposure = f64_col("short_exposure")?;
+    let long_bars = f64_col("long_bars")?;
+    let short_bars = f64_col("short_bars")?;
+    let vol_scale = f64_col("vol_scale")?;
+
+    let mut metrics = Vec::with_capacity(df.height());
+    for i in 0..df.height() {
+        metrics.push(DailyMetric {
+            date          : date.get(i).unwrap_or_default().to_string(),
+            symbol        : symbol.get(i).unwrap_or_default().to_string(),
+            edge          : edge[i],
+            return_val    : return_val[i],
+            cost          : cost[i],
+            n1b           : n1b[i],
+            turnover      : turnover[i],
+            long_edge     : long_edge[i],
+            long_cost     : long_cost[i],
+            long_return   : long_return[i],
+            long_turnover : long_turnover[i],
+            short_edge    : short_edge[i],
+            short_cost    : short_cost[i],
+            short_return  : short_return[i],
+            short_turnover: short_turnover[i],
+            weight        : weight[i],
+            long_exposure : long_exposure[i],
+            short_exposure: short_exposure[i],
+            long_bars     : long_bars[i],
+            short_bars    : short_bars[i],
+            vol_scale     : vol_scale[i],
+        });
+    }
+    Ok(metrics)
+}
+
+fn trade_pairs_from_df(df: &DataFrame) -> CzscResult<Vec<TradePair>> {
+    let symbol = df.column("symbol")?.str()?;
+    let direction = df.column("direction")?.str()?;
+    let open_dt = df.column("open_dt")?.str()?;
+    let close_dt = df.column("close_dt")?.str()?;
+    let open_price = df.column("open_price")?.f64()?;
+    let close_price = df.column("close_price")?.f64()?;
+    let bar_count = df.column("bar_count")?.u64()?;
+    let event_sequence = df.column("event_sequence")?.str()?;
+    let holding_days = df.column("holding_days")?.i64()?;
+    let profit_ratio = df.column("profit_ratio")?.f64()?;
+
+    let mut pairs = Vec::with_capacity(df.height());
+    for i in 0..df.height() {
+        pairs.push(TradePair {
+            symbol        : symbol.get(i).unwrap_or_default().to_string(),
+            direction     : direction.get(i).unwrap_or_default().parse()?,
+            open_dt       : open_dt.get(i).unwrap_or_default().to_string(),
+            close_dt      : close_dt.get(i).unwrap_or_default().to_string(),
+            open_price    : open_price.get(i).unwrap_or(f64::NAN),
+            close_price   : close_price.get(i).unwrap_or(f64::NAN),
+            bar_count     : bar_count.get(i).unwrap_or_default() as usize,
+            event_sequence: event_sequence.get(i).unwrap_or_default().to_string(),
+            holding_days  : holding_days.get(i).unwrap_or_default(),
+            profit_ratio  : profit_ratio.get(i).unwrap_or(f64::NAN),
+        });
+    }
+    Ok(pairs)
+}
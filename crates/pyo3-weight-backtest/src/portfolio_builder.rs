@@ -1,9 +1,12 @@
 use crate::config::BacktestConfig;
 use crate::errors::CzscResult;
 use crate::stats::{daily_performance, evaluate_pairs};
-use crate::types::{Direction, SymbolResult, TradePair};
-use crate::utils::RoundTo;
+use crate::types::{
+    BacktestWarning, Direction, MetricKey, PortfolioMetrics, SymbolResult, TradePair, WarningKind,
+};
+use crate::utils::{standard_deviation, RoundTo};
 use chrono::{Days, NaiveDate};
+use log::debug;
 use polars::prelude::*;
 use std::collections::HashMap;
 
@@ -15,59 +18,110 @@ pub struct CorrelationStats {
     pub downside_correlation   : f64,
 }
 
+/// 持仓 K 线计数，用于计算多空占比
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PositionCounts {
+    pub total: usize,
+    pub long : usize,
+    pub short: usize,
+}
+
+impl PositionCounts {
+    /// 从含 weight 列的数据中统计多空 K 线数量
+    pub fn from_df(df: &DataFrame) -> CzscResult<Self> {
+        if df.is_empty() {
+            return Ok(Self::default());
+        }
+        Self::from_lazy(df.clone().lazy())
+    }
+
+    /// 以惰性方式统计多空 K 线数量，无需载入全部数据
+    pub fn from_lazy(lf: LazyFrame) -> CzscResult<Self> {
+        let counts = lf
+            .select([
+                // 计算总行数
+                len().alias("total_count"),
+                // 计算多头数量
+                col("weight").gt(0.0).sum().alias("long_count"),
+                // 计算空头数量
+                col("weight").lt(0.0).sum().alias("short_count"),
+            ])
+            .collect()?;
+
+        Ok(Self {
+            total: counts.column("total_count")?.get(0)?.try_extract::<u64>()? as usize,
+            long : counts.column("long_count")?.get(0)?.try_extract::<u64>()? as usize,
+            short: counts.column("short_count")?.get(0)?.try_extract::<u64>()? as usize,
+        })
+    }
+}
+
+impl std::ops::AddAssign for PositionCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.total += other.total;
+        self.long  += other.long;
+        self.short += other.short;
+    }
+}
+
 // 构建器结构
 pub struct PortfolioMetricsBuilder<'a> {
-    config            : &'a BacktestConfig,
-    symbol_results    : &'a HashMap<String, SymbolResult>,
-    df                : &'a DataFrame,
-    daily_df          : &'a DataFrame,
-    daily_ew_return_df: &'a DataFrame,
-    stats             : HashMap<String, f64>,
+    config         : &'a BacktestConfig,
+    symbol_results : &'a HashMap<String, SymbolResult>,
+    position_counts: PositionCounts,
+    /// 每日组合汇总，见 `PortfolioAnalyzer::gen_daily_summary_df`
+    daily_summary  : &'a DataFrame,
+    stats          : HashMap<MetricKey, f64>,
+    metrics        : PortfolioMetrics,
+    warnings       : Vec<BacktestWarning>,
 }
 
 impl<'a> PortfolioMetricsBuilder<'a> {
     /// 创建新的构建器实例
     pub fn new(
         config: &'a BacktestConfig,
-        df: &'a DataFrame,
-        daily_df: &'a DataFrame,
-        daily_ew_return_df: &'a DataFrame,
+        position_counts: PositionCounts,
+        daily_summary: &'a DataFrame,
         symbol_results: &'a HashMap<String, SymbolResult>,
     ) -> Self {
         PortfolioMetricsBuilder {
             config,
             symbol_results,
-            df,
-            daily_df,
-            daily_ew_return_df,
+            position_counts,
+            daily_summary,
             stats: HashMap::new(),
+            metrics: PortfolioMetrics::default(),
+            warnings: vec![],
         }
     }
 
     /// 添加基本指标
     pub fn add_basic_metrics(mut self) -> CzscResult<Self> {
         // 品种数量
-        self.stats.insert("品种数量".to_string(), self.symbol_results.len() as f64);
+        self.stats.insert(MetricKey::SymbolCount, self.symbol_results.len() as f64);
+        self.metrics.num_symbols = self.symbol_results.len();
 
         // 日期范围
         let base_date = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
         if let Some(min_days)
-            = self.daily_df.column("date")?.str()?.cast(&DataType::Date)?.date()?.min() {
+            = self.daily_summary.column("date")?.str()?.cast(&DataType::Date)?.date()?.min() {
             if let Some(min_date) = base_date
                 .checked_add_days(Days::new(min_days as u64)) {
+                self.metrics.start_date = min_date;
                 self.stats.insert(
-                    "最小日期".to_string(),
+                    MetricKey::StartDate,
                     min_date.format("%Y%m%d").to_string().parse::<f64>()?
                 );
             }
         }
 
         if let Some(max_days)
-            = self.daily_df.column("date")?.str()?.cast(&DataType::Date)?.date()?.max() {
+            = self.daily_summary.column("date")?.str()?.cast(&DataType::Date)?.date()?.max() {
             if let Some(max_date) = base_date
                 .checked_add_days(Days::new(max_days as u64)) {
+                self.metrics.end_date = max_date;
                 self.stats.insert(
-                    "最大日期".to_string(),
+                    MetricKey::EndDate,
                     max_date.format("%Y%m%d").to_string().parse::<f64>()?,
                 );
             }
@@ -78,7 +132,7 @@ impl<'a> PortfolioMetricsBuilder<'a> {
 
     /// 添加交易对统计指标
     pub fn add_trade_pair_metrics(mut self) -> CzscResult<Self> {
-        println!("process add_trade_pair_metrics");
+        debug!("computing trade pair metrics");
         let trade_pairs: Vec<TradePair> = self
             .symbol_results
             .values()
@@ -87,98 +141,222 @@ impl<'a> PortfolioMetricsBuilder<'a> {
 
         let stats = evaluate_pairs(&trade_pairs, Direction::LongShort)?;
 
-        self.stats.insert("单笔收益".to_string(), stats.avg_profit_per_trade);
-        self.stats.insert("持仓K线数".to_string(), stats.avg_bars_held);
-        self.stats.insert("交易胜率".to_string(), stats.win_rate);
-        self.stats.insert("持仓天数".to_string(), stats.avg_days_held);
+        self.stats.insert(MetricKey::TradeProfit, stats.avg_profit_per_trade);
+        self.stats.insert(MetricKey::HoldingBars, stats.avg_bars_held);
+        self.stats.insert(MetricKey::WinRate, stats.win_rate);
+        self.stats.insert(MetricKey::HoldingDays, stats.avg_days_held);
+
+        self.metrics.total_trades = stats.trade_count;
+        self.metrics.win_rate = stats.win_rate;
+        self.metrics.profit_factor = if stats.total_loss != 0.0 {
+            stats.total_win_profit / stats.total_loss.abs()
+        } else {
+            f64::NAN
+        };
+        self.metrics.avg_win_trade = stats.avg_win_profit;
+        self.metrics.avg_loss_trade = stats.avg_loss;
+        self.metrics.avg_holding_days = stats.avg_days_held;
+
+        let mut holding_days: Vec<f64> =
+            trade_pairs.iter().map(|p| p.holding_days as f64).collect();
+        holding_days.sort_by(|a, b| a.total_cmp(b));
+        self.metrics.median_holding_days = median(&holding_days);
+
+        self.metrics.long_win_rate = evaluate_pairs(&trade_pairs, Direction::Long)?.win_rate;
+        self.metrics.short_win_rate = evaluate_pairs(&trade_pairs, Direction::Short)?.win_rate;
 
         Ok(self)
     }
 
     /// 添加多空占比指标
     pub fn add_long_short_metrics(mut self) -> CzscResult<Self> {
-        println!("process add_long_short_metrics");
+        debug!("computing long/short metrics");
         let (long_rate, short_rate) = self.calculate_longshort_rates()?;
 
-        self.stats.insert("多头占比".to_string(), long_rate.round_to(4));
-        self.stats.insert("空头占比".to_string(), short_rate.round_to(4));
+        self.stats.insert(MetricKey::LongRatio, long_rate.round_to(4));
+        self.stats.insert(MetricKey::ShortRatio, short_rate.round_to(4));
+        self.metrics.long_ratio = long_rate;
+        self.metrics.short_ratio = short_rate;
 
         Ok(self)
     }
 
     /// 添加基准相关性指标
     pub fn add_benchmark_correlations(mut self) -> CzscResult<Self> {
-        println!("process add_benchmark_correlations");
+        debug!("computing benchmark correlations");
         let alpha_df = self.get_alpha_df()?;
         let corr_stats = self.calculate_correlations(&alpha_df)?;
 
         self.stats.insert(
-            "波动比".to_string(),
+            MetricKey::VolatilityRatio,
             corr_stats.volatility_ratio.round_to(4),
         );
         self.stats.insert(
-            "与基准波动相关性".to_string(),
+            MetricKey::VolatilityCorrelation,
             corr_stats.volatility_correlation,
         );
         self.stats.insert(
-            "与基准收益相关性".to_string(),
+            MetricKey::ReturnCorrelation,
             corr_stats.return_correlation,
         );
         self.stats.insert(
-            "与基准空头相关性".to_string(),
+            MetricKey::BearCorrelation,
             corr_stats.downside_correlation,
         );
 
+        self.add_benchmark_regression(&alpha_df)?;
+        Ok(self)
+    }
+
+    /// 添加换手率指标，日换手的汇总方式与组合收益一致
+    pub fn add_turnover_metrics(mut self) -> CzscResult<Self> {
+        if let Some(avg) = self.daily_summary.column("turnover")?.f64()?.mean() {
+            self.metrics.avg_daily_turnover = avg;
+            self.metrics.annual_turnover = avg * self.config.yearly_days as f64;
+        }
+        Ok(self)
+    }
+
+    /// 添加敞口指标，按每日组合敞口取平均
+    pub fn add_exposure_metrics(mut self) -> CzscResult<Self> {
+        let exposure = self.daily_summary;
+        if exposure.is_empty() {
+            return Ok(self);
+        }
+
+        let mean = |name: &str| -> CzscResult<f64> {
+            Ok(exposure.column(name)?.f64()?.mean().unwrap_or(0.0))
+        };
+        let m = &mut self.metrics;
+        m.avg_gross_exposure = mean("gross")?;
+        m.avg_net_exposure = mean("net")?;
+        m.avg_long_count = mean("long_count")?;
+        m.avg_short_count = mean("short_count")?;
         Ok(self)
     }
 
     /// 添加组合收益指标
     pub fn add_portfolio_return_metrics(mut self) -> CzscResult<Self> {
-        println!("process add_portfolio_return_metrics");
+        debug!("computing portfolio return metrics");
         let returns: Vec<f64> =
-            self.daily_ew_return_df.column("total")?.f64()?.into_iter().flatten().collect();
+            self.daily_summary.column("total")?.f64()?.into_iter().flatten().collect();
 
-        let perf_stats = daily_performance(&returns, Some(self.config.yearly_days as f64));
+        let (perf_stats, clipped) =
+            daily_performance(&returns, Some(self.config.yearly_days as f64));
 
         for (key, value) in perf_stats.iter() {
-            self.stats.insert(key.clone(), *value);
+            self.stats.insert(*key, *value);
+        }
+        for (key, raw) in clipped {
+            self.warnings.push(BacktestWarning::new(
+                "",
+                "",
+                WarningKind::ClippedMetric,
+                format!(
+                    "{} = {:.4} is out of range, reported as {}",
+                    key.label(self.config.locale),
+                    raw,
+                    self.stats[&key]
+                ),
+            ));
         }
+
+        self.add_return_statistics(&returns, &perf_stats);
         Ok(self)
     }
 
-    /// 完成构建并返回指标集合
-    pub fn build(self) -> HashMap<String, f64> {
-        self.stats
+    /// 完成构建并返回指标集合、结构化指标与组合级警告，指标键按配置的语言输出
+    pub fn build(self) -> (HashMap<String, f64>, PortfolioMetrics, Vec<BacktestWarning>) {
+        let locale = self.config.locale;
+        let stats = self
+            .stats
+            .into_iter()
+            .map(|(key, value)| (key.label(locale).to_string(), value))
+            .collect();
+        (stats, self.metrics, self.warnings)
+    }
+
+    /// 收益、风险及风险调整收益指标取自 `daily_performance` 的结果，与指标字典口径一致；
+    /// 只补充其中没有的复合增长率、回撤持续时间、索提诺比率与高阶矩
+    fn add_return_statistics(&mut self, returns: &[f64], perf: &HashMap<MetricKey, f64>) {
+        let m = &mut self.metrics;
+        if returns.is_empty() {
+            return;
+        }
+        let yearly_days = self.config.yearly_days as f64;
+        let n = returns.len() as f64;
+        let mean = returns.iter().sum::<f64>() / n;
+        let std = standard_deviation(returns);
+
+        m.total_return = perf[&MetricKey::AbsoluteReturn];
+        m.annualized_return = perf[&MetricKey::AnnualReturn];
+        m.volatility = perf[&MetricKey::AnnualVolatility];
+        m.max_drawdown = perf[&MetricKey::MaxDrawdown];
+        m.sharpe_ratio = perf[&MetricKey::SharpeRatio];
+        m.calmar_ratio = perf[&MetricKey::CalmarRatio];
+        // 新高间隔包含创新高的当天，回撤持续时间为其后未创新高的天数
+        m.max_drawdown_duration = (perf[&MetricKey::NewHighInterval] as usize).saturating_sub(1);
+        let downside = perf[&MetricKey::DownsideVolatility];
+        m.sortino_ratio = if downside > 0.0 { m.annualized_return / downside } else { 0.0 };
+
+        let growth = returns.iter().map(|r| 1.0 + r).product::<f64>();
+        m.cagr = if growth > 0.0 {
+            growth.powf(yearly_days / n) - 1.0
+        } else {
+            -1.0
+        };
+        if std > 0.0 {
+            m.skewness = returns.iter().map(|r| ((r - mean) / std).powi(3)).sum::<f64>() / n;
+            m.kurtosis = returns.iter().map(|r| ((r - mean) / std).powi(4)).sum::<f64>() / n - 3.0;
+        }
+    }
+
+    /// 以等权基准计算 alpha、beta、跟踪误差与信息比率
+    fn add_benchmark_regression(&mut self, alpha_df: &DataFrame) -> CzscResult<()> {
+        let strategy: Vec<f64> = alpha_df.column("策略")?.f64()?.into_iter().flatten().collect();
+        let benchmark: Vec<f64> = alpha_df.column("基准")?.f64()?.into_iter().flatten().collect();
+        if strategy.is_empty() || strategy.len() != benchmark.len() {
+            return Ok(());
+        }
+
+        let yearly_days = self.config.yearly_days as f64;
+        let n = strategy.len() as f64;
+        let mean_s = strategy.iter().sum::<f64>() / n;
+        let mean_b = benchmark.iter().sum::<f64>() / n;
+        let cov = strategy
+            .iter()
+            .zip(&benchmark)
+            .map(|(s, b)| (s - mean_s) * (b - mean_b))
+            .sum::<f64>()
+            / n;
+        let var_b = benchmark.iter().map(|b| (b - mean_b).powi(2)).sum::<f64>() / n;
+
+        let beta = if var_b > 0.0 { cov / var_b } else { f64::NAN };
+        let excess: Vec<f64> = strategy.iter().zip(&benchmark).map(|(s, b)| s - b).collect();
+        let tracking_error = standard_deviation(&excess) * yearly_days.sqrt();
+
+        self.metrics.beta = beta;
+        self.metrics.alpha = (mean_s - beta * mean_b) * yearly_days;
+        self.metrics.tracking_error = tracking_error;
+        self.metrics.information_ratio = if tracking_error > 0.0 {
+            (mean_s - mean_b) * yearly_days / tracking_error
+        } else {
+            f64::NAN
+        };
+        Ok(())
     }
 
     /// 计算多空占比
     fn calculate_longshort_rates(&self) -> CzscResult<(f64, f64)> {
-        if self.df.is_empty() {
+        let counts = self.position_counts;
+        if counts.total == 0 {
             return Ok((0.0, 0.0));
         }
 
-        // 使用表达式计算
-        let df = self
-            .df
-            .clone()
-            .lazy()
-            .select([
-                // 计算总行数
-                // 计算多头数量
-                col("weight").gt(0.0).sum().alias("long_count"),
-                // 计算空头数量
-                col("weight").lt(0.0).sum().alias("short_count"),
-            ])
-            .collect()?;
-
-        // 提取结果
-        let total = self.df.height() as f64;
-        let long_count = df.column("long_count")?.get(0)?.try_extract::<f64>()?;
-        let short_count = df.column("short_count")?.get(0)?.try_extract::<f64>()?;
-
         // 计算比例
-        let long_rate = long_count / total;
-        let short_rate = short_count / total;
+        let total = counts.total as f64;
+        let long_rate = counts.long as f64 / total;
+        let short_rate = counts.short as f64 / total;
 
         Ok((long_rate, short_rate))
     }
@@ -238,25 +416,27 @@ impl<'a> PortfolioMetricsBuilder<'a> {
     }
 
     fn get_alpha_df(&self) -> CzscResult<DataFrame> {
-        // 分组聚合计算平均值
-        let grouped = self
-            .daily_df
+        // 品种平均收益与平均基准收益，计算超额收益
+        let result = self
+            .daily_summary
             .clone()
             .lazy()
-            .group_by([col("date")])
-            .agg([
-                col("return").mean().alias("策略"),
-                col("n1b").mean().alias("基准"),
+            .select(&[
+                col("date"),
+                col("strategy").alias("策略"),
+                col("benchmark").alias("基准"),
+                (col("strategy") - col("benchmark")).alias("超额"),
             ])
             .collect()?;
 
-        // 计算超额收益
-        let result = grouped
-            .lazy()
-            .with_columns([(col("策略") - col("基准")).alias("超额")])
-            .select(&[col("date"), col("策略"), col("基准"), col("超额")])
-            .collect()?;
-
         Ok(result)
     }
 }
+
+fn median(sorted: &[f64]) -> f64 {
+    match sorted.len() {
+        0 => 0.0,
+        n if n % 2 == 1 => sorted[n / 2],
+        n => (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0,
+    }
+}
@@ -1,12 +1,24 @@
 use super::types::*;
 use crate::config::BacktestConfig;
-use crate::errors::CzscResult;
+use crate::errors::{check_cancelled, CzscResult};
 use crate::trade_position::TradePositionState;
+use crate::utils::timestamp_to_datetime;
 use crate::types::TradeAction::{CloseLong, CloseShort, OpenLong, OpenShort};
 use anyhow::anyhow;
-use chrono::NaiveDateTime;
+use log::warn;
 use polars::prelude::*;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::AtomicBool;
+
+/// 单个品种的持仓跟踪状态
+///
+/// 保存持仓状态机与未平仓的开仓队列，使交易对可以跨多批数据连续生成
+#[derive(Debug, Clone, Default)]
+pub struct PositionTracker {
+    state           : TradePositionState,
+    open_long_queue : VecDeque<TradeAction>,
+    open_short_queue: VecDeque<TradeAction>,
+}
 
 #[derive(Debug, Clone)]
 pub struct MetricProcessor {
@@ -22,6 +34,7 @@ impl MetricProcessor {
         &self,
         symbol: &str,
         symbol_df: &DataFrame,
+        cancel: &AtomicBool,
     ) -> CzscResult<Vec<DailyMetric>> {
         let fee_rate = self.config.fee_rate;
         // 实现核心指标计算逻辑
@@ -29,10 +42,8 @@ impl MetricProcessor {
         let df = symbol_df
             .clone()
             .lazy()
-            // 计算基准收益率：n1b = (下一期价格 / 当前价格) - 1
-            .with_column(
-                (col("price").shift(Expr::from(-1)) / col("price") - lit(1.0)).alias("n1b"),
-            )
+            // 计算基准收益率：n1b = (下一期价格 / 当前价格) - 1，多策略回测时已预先算好
+            .with_column(if symbol_df.column("n1b").is_ok() { col("n1b") } else { n1b_expr() })
             // 计算策略理论收益：edge = weight * n1b
             .with_column((col("weight") * col("n1b")).alias("edge"))
             // 计算换手率：|当期权重 - 上期权重|
@@ -86,9 +97,15 @@ impl MetricProcessor {
             .with_column((col("long_edge") - col("long_cost")).alias("long_return"))
             // 计算空头净收益
             .with_column((col("short_edge") - col("short_cost")).alias("short_return"))
-            // 提取日期部分（不含时间）
-            .with_column(col("dt").dt().strftime("%Y-%m-%d").alias("date"));
+            // 按交易日汇总
+            .with_column(col("trade_date").dt().strftime("%Y-%m-%d").alias("date"));
 
+        // 启用波动率目标时数据带有缩放系数列，否则缩放系数为 1
+        let df = if symbol_df.column("vol_scale").is_ok() {
+            df
+        } else {
+            df.with_column(lit(1.0).alias("vol_scale"))
+        };
         let aggregated_df = df
             .lazy()
             .group_by([col("date")])
@@ -106,6 +123,12 @@ impl MetricProcessor {
                 col("short_cost").sum().alias("short_cost"),
                 col("short_return").sum().alias("short_return"),
                 col("short_turnover").sum().alias("short_turnover"),
+                col("weight").last().alias("weight"),
+                col("long_weight").mean().alias("long_exposure"),
+                col("short_weight").mean().alias("short_exposure"),
+                col("weight").gt(0.0).cast(DataType::Float64).mean().alias("long_bars"),
+                col("weight").lt(0.0).cast(DataType::Float64).mean().alias("short_bars"),
+                col("vol_scale").last().alias("vol_scale"),
             ])
             .sort(["date"], SortMultipleOptions::default())
             .collect()?;
@@ -113,6 +136,7 @@ impl MetricProcessor {
         let mut daily_metrics = Vec::with_capacity(aggregated_df.height());
 
         for idx in 0..aggregated_df.height() {
+            check_cancelled(cancel)?;
             let date = aggregated_df.column("date")?.str()?.get(idx).unwrap_or_default();
             let edge = aggregated_df.column("edge")?.f64()?.get(idx).unwrap_or(0.0);
             let return_val = aggregated_df.column("return")?.f64()?.get(idx).unwrap_or(0.0);
@@ -129,6 +153,14 @@ impl MetricProcessor {
             let short_return = aggregated_df.column("short_return")?.f64()?.get(idx).unwrap_or(0.0);
             let short_turnover =
                 aggregated_df.column("short_turnover")?.f64()?.get(idx).unwrap_or(0.0);
+            let weight = aggregated_df.column("weight")?.f64()?.get(idx).unwrap_or(0.0);
+            let long_exposure =
+                aggregated_df.column("long_exposure")?.f64()?.get(idx).unwrap_or(0.0);
+            let short_exposure =
+                aggregated_df.column("short_exposure")?.f64()?.get(idx).unwrap_or(0.0);
+            let long_bars = aggregated_df.column("long_bars")?.f64()?.get(idx).unwrap_or(0.0);
+            let short_bars = aggregated_df.column("short_bars")?.f64()?.get(idx).unwrap_or(0.0);
+            let vol_scale = aggregated_df.column("vol_scale")?.f64()?.get(idx).unwrap_or(1.0);
 
             daily_metrics.push(DailyMetric {
                 date: date.parse().unwrap(),
@@ -146,47 +178,112 @@ impl MetricProcessor {
                 short_cost,
                 short_return,
                 short_turnover,
+                weight,
+                long_exposure,
+                short_exposure,
+                long_bars,
+                short_bars,
+                vol_scale,
             });
         }
 
         Ok(daily_metrics)
     }
 
+    /// 丢弃权重或价格为 NaN/无穷的行并记录警告；数据检查设为 warn 时这些行会保留到这里
+    pub fn clean_bars(
+        &self,
+        symbol: &str,
+        symbol_df: DataFrame,
+        warnings: &mut Vec<BacktestWarning>,
+    ) -> CzscResult<DataFrame> {
+        let checks = symbol_df
+            .clone()
+            .lazy()
+            .select([(col("weight").is_finite().and(col("price").is_finite())).alias("valid")])
+            .collect()?;
+        let valid = checks.column("valid")?.bool()?;
+
+        let dropped: Vec<usize> =
+            (0..valid.len()).filter(|&i| valid.get(i) != Some(true)).collect();
+        if let Some(&first) = dropped.first() {
+            warnings.push(BacktestWarning::new(
+                symbol,
+                bar_dt(&symbol_df, first)?,
+                WarningKind::DroppedRows,
+                format!("dropped {} rows with non-finite weight or price", dropped.len()),
+            ));
+        }
+
+        if dropped.is_empty() {
+            Ok(symbol_df)
+        } else {
+            // 丢弃行后下一期价格改变，预先算好的 n1b 不再适用
+            let symbol_df = symbol_df.filter(valid)?;
+            match symbol_df.column("n1b") {
+                Ok(_) => Ok(symbol_df.drop("n1b")?),
+                Err(_) => Ok(symbol_df),
+            }
+        }
+    }
+
     pub fn generate_trade_pairs(
         &self,
         symbol: &str,
         symbol_df: &DataFrame,
+        warnings: &mut Vec<BacktestWarning>,
+        cancel: &AtomicBool,
+    ) -> CzscResult<Vec<TradePair>> {
+        let mut tracker = PositionTracker::default();
+        self.track_trade_pairs(symbol, symbol_df, &mut tracker, warnings, cancel)
+    }
+
+    /// 在已有持仓状态上继续处理新的 K 线，返回本批次新完成的交易对
+    pub fn track_trade_pairs(
+        &self,
+        symbol: &str,
+        symbol_df: &DataFrame,
+        tracker: &mut PositionTracker,
+        warnings: &mut Vec<BacktestWarning>,
+        cancel: &AtomicBool,
     ) -> CzscResult<Vec<TradePair>> {
-        let mut state = TradePositionState::Flat;
         let mut all_actions = Vec::new();
 
         let dt_series = symbol_df.column("dt")?.datetime()?;
         let volume_series = symbol_df.column("volume")?.i32()?;
         let price_series = symbol_df.column("price")?.f64()?;
         let bar_id_series = symbol_df.column("bar_id")?.u32()?;
-        let weight_series = symbol_df.column("weight")?.f64()?;
+        // 出场规则强制平仓的 K 线及原因
+        let mut exits: HashMap<usize, String> = HashMap::new();
+        if let Ok(exit_series) = symbol_df.column("exit") {
+            for (bar_id, reason) in bar_id_series.into_iter().zip(exit_series.str()?) {
+                if let (Some(bar_id), Some(reason)) = (bar_id, reason) {
+                    exits.insert(bar_id as usize, reason.to_string());
+                }
+            }
+        }
 
         for i in 0..symbol_df.height() {
-            let (dt, volume, price, bar_id, weight) = match (
-                dt_series.get(i).and_then(|ts| NaiveDateTime::from_timestamp_nanos(ts)),
+            check_cancelled(cancel)?;
+            let (dt, volume, price, bar_id) = match (
+                dt_series.get(i).and_then(|ts| timestamp_to_datetime(ts, dt_series.time_unit())),
                 volume_series.get(i),
                 price_series.get(i),
                 bar_id_series.get(i),
-                weight_series.get(i),
             ) {
-                (Some(dt), Some(volume), Some(price), Some(bar_id), Some(weight)) => {
-                    (dt, volume, price, bar_id, weight)
-                }
+                (Some(dt), Some(volume), Some(price), Some(bar_id)) => (dt, volume, price, bar_id),
                 _ => {
                     return Err(
                         anyhow!("DataFrame contains null values in required columns").into(),
                     )
                 }
             };
-            let actions = state.handle_transition(volume, dt, price as f32, bar_id as usize);
+            let actions =
+                tracker.state.handle_transition(volume, dt, price as f32, bar_id as usize);
             all_actions.extend(actions);
         }
-        let trade_pairs = self.actions_to_trade_pairs(symbol, all_actions)?;
+        let trade_pairs =
+            self.actions_to_trade_pairs(symbol, all_actions, &exits, tracker, warnings)?;
 
         Ok(trade_pairs)
     }
@@ -195,10 +292,13 @@ impl MetricProcessor {
         &self,
         symbol: &str,
         actions: Vec<TradeAction>,
+        exits: &HashMap<usize, String>,
+        tracker: &mut PositionTracker,
+        warnings: &mut Vec<BacktestWarning>,
     ) -> CzscResult<Vec<TradePair>> {
         let mut trade_pairs: Vec<TradePair> = vec![];
-        let mut open_long_queue: VecDeque<TradeAction> = VecDeque::new();
-        let mut open_short_queue: VecDeque<TradeAction> = VecDeque::new();
+        let open_long_queue = &mut tracker.open_long_queue;
+        let open_short_queue = &mut tracker.open_short_queue;
 
         for action in actions {
             match action {
@@ -227,16 +327,20 @@ impl MetricProcessor {
                                 open_price: open_price as f64,
                                 close_price: price as f64,
                                 bar_count,
-                                event_sequence: "开多 -> 平多".to_string(), // Event sequence should be set appropriately
+                                event_sequence: event_sequence("开多", "平多", exits.get(&bar_id)),
                                 holding_days: holding_days as i64,
                                 profit_ratio: profit_ratio as f64,
                             });
                         }
                     } else {
-                        // Handle case where there is no open long position
-                        eprintln!(
-                            "Warning: Attempted to close a long position without an open position."
-                        );
+                        // 没有对应的多头开仓
+                        warn!("{}: close long at {} without an open long position", symbol, dt);
+                        warnings.push(BacktestWarning::new(
+                            symbol,
+                            dt,
+                            WarningKind::UnmatchedClose,
+                            "close long without an open long position".to_string(),
+                        ));
                     }
                 }
                 CloseShort { dt, price, bar_id } => {
@@ -258,14 +362,20 @@ impl MetricProcessor {
                                 open_price: open_price as f64,
                                 close_price: price as f64,
                                 bar_count,
-                                event_sequence: "开空 -> 平空".to_string(),
+                                event_sequence: event_sequence("开空", "平空", exits.get(&bar_id)),
                                 holding_days: holding_days as i64,
                                 profit_ratio: profit_ratio as f64,
                             });
                         }
                     } else {
-                        // Handle case where there is no open short position
-                        eprintln!("Warning: Attempted to close a short position without an open position.");
+                        // 没有对应的空头开仓
+                        warn!("{}: close short at {} without an open short position", symbol, dt);
+                        warnings.push(BacktestWarning::new(
+                            symbol,
+                            dt,
+                            WarningKind::UnmatchedClose,
+                            "close short without an open short position".to_string(),
+                        ));
                     }
                 }
             }
@@ -273,3 +383,61 @@ impl MetricProcessor {
         Ok(trade_pairs)
     }
 }
+
+/// 交易对的事件序列，被出场规则强制平仓时以原因代替平仓事件
+fn event_sequence(open: &str, close: &str, exit: Option<&String>) -> String {
+    format!("{} -> {}", open, exit.map(String::as_str).unwrap_or(close))
+}
+
+/// 将收益类字段中的 NaN 或无穷置为 0，并汇总为一条警告，dt 为第一个异常交易日
+///
+/// 价格为 0 时 n1b 为无穷，空仓的 0 × inf 得到 NaN，需要在计算任何指标前屏蔽
+pub fn mask_nan_returns(symbol: &str, metrics: &mut [DailyMetric]) -> Option<BacktestWarning> {
+    let mut first_date: Option<String> = None;
+    let mut count = 0;
+    for m in metrics.iter_mut() {
+        let mut masked = false;
+        for value in [
+            &mut m.edge,
+            &mut m.return_val,
+            &mut m.n1b,
+            &mut m.long_edge,
+            &mut m.long_return,
+            &mut m.short_edge,
+            &mut m.short_return,
+        ] {
+            if !value.is_finite() {
+                *value = 0.0;
+                masked = true;
+            }
+        }
+        if masked {
+            count += 1;
+            first_date.get_or_insert_with(|| m.date.clone());
+        }
+    }
+    first_date.map(|date| {
+        BacktestWarning::new(
+            symbol,
+            &date,
+            WarningKind::NanReturn,
+            format!("{} days have NaN or infinite return, set to 0", count),
+        )
+    })
+}
+
+/// 第 `idx` 根 K 线的时间
+fn bar_dt(df: &DataFrame, idx: usize) -> CzscResult<String> {
+    let dt = df.column("dt")?.datetime()?;
+    Ok(dt
+        .physical()
+        .get(idx)
+        .and_then(|ts| timestamp_to_datetime(ts, dt.time_unit()))
+        .map(|dt| dt.to_string())
+        .unwrap_or_default())
+}
+
+/// 基准收益率：n1b = (下一期价格 / 当前价格) - 1，要求数据已按时间排列
+pub(crate) fn n1b_expr() -> Expr {
+    (col("price").shift(Expr::from(-1)) / col("price") - lit(1.0)).alias("n1b")
+}
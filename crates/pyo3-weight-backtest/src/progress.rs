@@ -0,0 +1,81 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// 回测进度输出方式
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProgressMode {
+    /// 不输出进度
+    None,
+    /// 在 stderr 绘制进度条
+    #[default]
+    Bar,
+}
+
+impl FromStr for ProgressMode {
+    type Err = crate::errors::CzscError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(ProgressMode::None),
+            "bar" => Ok(ProgressMode::Bar),
+            _ => Err(anyhow::anyhow!("Invalid progress {:?}, must be 'none' or 'bar'", s).into()),
+        }
+    }
+}
+
+impl ProgressMode {
+    /// 创建对应的进度接收器
+    pub fn sink(self, total: usize) -> Box<dyn ProgressSink> {
+        match self {
+            ProgressMode::None => Box::new(NoProgress),
+            ProgressMode::Bar => Box::new(TerminalProgress::new(total)),
+        }
+    }
+}
+
+/// 回测进度接收器，每完成一个品种调用一次 `update`，并行回测时会在多个线程中调用
+pub trait ProgressSink: Send + Sync {
+    /// `done` 为已完成的品种数，`symbol` 为刚完成的品种
+    fn update(&self, done: usize, total: usize, symbol: &str);
+
+    /// 全部品种处理完成
+    fn finish(&self) {}
+}
+
+/// 不输出进度
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {
+    fn update(&self, _done: usize, _total: usize, _symbol: &str) {}
+}
+
+/// 终端进度条
+pub struct TerminalProgress {
+    bar: ProgressBar,
+}
+
+impl TerminalProgress {
+    pub fn new(total: usize) -> Self {
+        let bar = ProgressBar::new(total as u64);
+        bar.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len} ({eta}) {msg}")
+                .unwrap()
+                .progress_chars("##-"),
+        );
+        TerminalProgress { bar }
+    }
+}
+
+impl ProgressSink for TerminalProgress {
+    fn update(&self, done: usize, _total: usize, symbol: &str) {
+        self.bar.set_position(done as u64);
+        self.bar.set_message(symbol.to_string());
+    }
+
+    fn finish(&self) {
+        self.bar.finish_with_message("完成");
+    }
+}
@@ -0,0 +1,519 @@
+use crate::config::WeightType;
+use crate::engine::BacktestResult;
+use crate::errors::CzscResult;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+const WIDTH: f64 = 900.0;
+const HEIGHT: f64 = 260.0;
+const MARGIN_LEFT: f64 = 60.0;
+const MARGIN_RIGHT: f64 = 20.0;
+const MARGIN_TOP: f64 = 30.0;
+const MARGIN_BOTTOM: f64 = 30.0;
+
+/// 排名图中展示的品种数量（收益最高与最低各取一半）
+const RANKING_SIZE: usize = 30;
+/// 交易对收益分布的分箱数量
+const HISTOGRAM_BINS: usize = 40;
+
+const STYLE: &str = "body{font-family:-apple-system,'Segoe UI','PingFang SC','Microsoft YaHei',sans-serif;\
+margin:24px auto;max-width:940px;color:#222}h1{font-size:22px}h2{font-size:16px;margin-top:28px}\
+table{border-collapse:collapse;font-size:13px}td,th{border:1px solid #ddd;padding:4px 10px;text-align:right}\
+th{background:#f5f5f5}td:first-child{text-align:left}svg{display:block}\
+.axis{font-size:10px;fill:#666}.legend{font-size:11px}";
+
+/// 一条折线
+struct Line<'a> {
+    name  : &'a str,
+    color : &'a str,
+    values: Vec<f64>,
+}
+
+impl BacktestResult {
+    /// 生成不依赖外部资源的 HTML 报告，图表均为内联 SVG
+    pub fn to_html(&self) -> CzscResult<String> {
+        let df = &self.daily_ew_return_df.0;
+        let dates: Vec<String> = df
+            .column("date")?
+            .str()?
+            .into_iter()
+            .map(|d| d.unwrap_or_default().to_string())
+            .collect();
+        let returns: Vec<f64> =
+            df.column("total")?.f64()?.into_iter().map(|v| v.unwrap_or(0.0)).collect();
+
+        let equity = cumsum(&returns);
+        let mut peak = f64::MIN;
+        let drawdown: Vec<f64> = equity
+            .iter()
+            .map(|&v| {
+                peak = peak.max(v);
+                v - peak
+            })
+            .collect();
+
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>回测报告</title>");
+        let _ = write!(html, "<style>{}</style></head><body>", STYLE);
+        let _ = write!(
+            html,
+            "<h1>回测报告</h1><p>{} ~ {}，品种数量 {}，权重类型 {}，手续费率 {}</p>",
+            dates.first().map(String::as_str).unwrap_or("-"),
+            dates.last().map(String::as_str).unwrap_or("-"),
+            self.symbol_results.len(),
+            match self.config.weight_type {
+                WeightType::TimeSeries => "ts",
+                WeightType::CrossSection => "cs",
+            },
+            self.config.fee_rate,
+        );
+
+        html.push_str("<h2>绩效指标</h2>");
+        html.push_str(&self.metrics_table());
+
+        html.push_str("<h2>累计收益</h2>");
+        html.push_str(&line_chart(
+            &dates,
+            &[Line {
+                name: "组合",
+                color: "#c23531",
+                values: equity,
+            }],
+            false,
+        ));
+
+        html.push_str("<h2>回撤</h2>");
+        html.push_str(&line_chart(
+            &dates,
+            &[Line {
+                name: "回撤",
+                color: "#2f7ed8",
+                values: drawdown,
+            }],
+            true,
+        ));
+
+        html.push_str("<h2>月度收益</h2>");
+        html.push_str(&monthly_heatmap(&dates, &returns));
+
+        html.push_str("<h2>多空收益贡献</h2>");
+        let (ls_dates, long, short) = self.long_short_returns()?;
+        html.push_str(&line_chart(
+            &ls_dates,
+            &[
+                Line {
+                    name: "多头",
+                    color: "#c23531",
+                    values: cumsum(&long),
+                },
+                Line {
+                    name: "空头",
+                    color: "#3a9b5c",
+                    values: cumsum(&short),
+                },
+            ],
+            false,
+        ));
+
+        if let Some(vol_target) = &self.config.vol_target {
+            let _ = write!(
+                html,
+                "<h2>波动率目标缩放系数</h2><p>目标年化波动率 {}，回看 {} 个交易日，上限 {}</p>",
+                vol_target.target, vol_target.lookback, vol_target.max_leverage
+            );
+            let (scale_dates, scales) = self.vol_scales();
+            html.push_str(&line_chart(
+                &scale_dates,
+                &[Line {
+                    name: "缩放系数",
+                    color: "#8e44ad",
+                    values: scales,
+                }],
+                false,
+            ));
+        }
+
+        if let Some(capital) = &self.capital {
+            let nav_df = &capital.nav_df.0;
+            let column = |name: &str| -> CzscResult<Vec<f64>> {
+                Ok(nav_df.column(name)?.f64()?.into_iter().map(|v| v.unwrap_or(f64::NAN)).collect())
+            };
+            let nav_dates: Vec<String> = nav_df
+                .column("date")?
+                .str()?
+                .into_iter()
+                .map(|d| d.unwrap_or_default().to_string())
+                .collect();
+            html.push_str("<h2>资金净值</h2><p>按整手与现金记账的净值与权重口径净值对比</p>");
+            html.push_str(&line_chart(
+                &nav_dates,
+                &[
+                    Line {
+                        name: "资金净值",
+                        color: "#c23531",
+                        values: column("nav")?,
+                    },
+                    Line {
+                        name: "权重口径",
+                        color: "#2f7ed8",
+                        values: column("weight_nav")?,
+                    },
+                ],
+                false,
+            ));
+            html.push_str("<h2>整手取整与现金拖累</h2>");
+            html.push_str(&line_chart(
+                &nav_dates,
+                &[
+                    Line {
+                        name: "取整偏离",
+                        color: "#8e44ad",
+                        values: column("rounding_gap")?,
+                    },
+                    Line {
+                        name: "现金占比",
+                        color: "#3a9b5c",
+                        values: column("cash_ratio")?,
+                    },
+                ],
+                false,
+            ));
+        }
+
+        html.push_str("<h2>品种收益排名</h2>");
+        html.push_str(&self.symbol_ranking());
+
+        html.push_str("<h2>交易对收益分布 (BP)</h2>");
+        let profits: Vec<f64> = self
+            .symbol_results
+            .values()
+            .flat_map(|sr| sr.trade_pairs.iter().map(|p| p.profit_ratio))
+            .filter(|v| v.is_finite())
+            .collect();
+        html.push_str(&histogram(&profits, HISTOGRAM_BINS));
+
+        html.push_str("</body></html>");
+        Ok(html)
+    }
+
+    /// 将 HTML 报告写入文件
+    pub fn save_html(&self, path: &Path) -> CzscResult<()> {
+        std::fs::write(path, self.to_html()?)?;
+        Ok(())
+    }
+
+    fn metrics_table(&self) -> String {
+        let metrics: BTreeMap<&String, &f64> = self.portfolio_metrics.iter().collect();
+        let mut table = String::from("<table><tr><th>指标</th><th>数值</th></tr>");
+        for (key, value) in metrics {
+            let _ = write!(table, "<tr><td>{}</td><td>{}</td></tr>", escape(key), value);
+        }
+        table.push_str("</table>");
+        table
+    }
+
+    /// 按日汇总的多头、空头收益，与 `leg_returns_df` 一致
+    fn long_short_returns(&self) -> CzscResult<(Vec<String>, Vec<f64>, Vec<f64>)> {
+        let df = self.leg_returns_df()?;
+        let dates = df.column("date")?.str()?.into_no_null_iter().map(String::from).collect();
+        let long = df.column("long")?.f64()?.into_no_null_iter().collect();
+        let short = df.column("short")?.f64()?.into_no_null_iter().collect();
+        Ok((dates, long, short))
+    }
+
+    /// 各交易日缩放系数的品种均值，截面策略各品种的系数相同
+    fn vol_scales(&self) -> (Vec<String>, Vec<f64>) {
+        let mut by_date: BTreeMap<&str, (f64, usize)> = BTreeMap::new();
+        for sr in self.symbol_results.values() {
+            for m in &sr.daily_metrics {
+                let entry = by_date.entry(m.date.as_str()).or_default();
+                entry.0 += m.vol_scale;
+                entry.1 += 1;
+            }
+        }
+        let dates = by_date.keys().map(|d| d.to_string()).collect();
+        let scales = by_date.values().map(|(sum, n)| sum / *n as f64).collect();
+        (dates, scales)
+    }
+
+    fn symbol_ranking(&self) -> String {
+        let mut totals: Vec<(&str, f64)> = self
+            .symbol_results
+            .iter()
+            .map(|(symbol, sr)| {
+                (symbol.as_str(), sr.daily_metrics.iter().map(|m| m.return_val).sum())
+            })
+            .collect();
+        totals.sort_by(|a, b| b.1.total_cmp(&a.1));
+        if totals.len() > RANKING_SIZE {
+            let bottom = totals.split_off(totals.len() - RANKING_SIZE / 2);
+            totals.truncate(RANKING_SIZE / 2);
+            totals.extend(bottom);
+        }
+        bar_chart(&totals)
+    }
+}
+
+fn cumsum(values: &[f64]) -> Vec<f64> {
+    values
+        .iter()
+        .scan(0.0, |acc, v| {
+            *acc += v;
+            Some(*acc)
+        })
+        .collect()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn value_range(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    let (min, max) = values
+        .filter(|v| v.is_finite())
+        .fold((f64::MAX, f64::MIN), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    if min > max {
+        (0.0, 1.0)
+    } else if min == max {
+        (min - 1.0, max + 1.0)
+    } else {
+        (min, max)
+    }
+}
+
+fn svg_open(width: f64, height: f64) -> String {
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">",
+        w = width,
+        h = height
+    )
+}
+
+/// 绘制 y 轴刻度与网格线
+fn y_axis(svg: &mut String, lo: f64, hi: f64, digits: usize, y_of: impl Fn(f64) -> f64) {
+    for i in 0..=4 {
+        let v = lo + (hi - lo) * i as f64 / 4.0;
+        let y = y_of(v);
+        let _ = write!(
+            svg,
+            "<line x1=\"{x0}\" y1=\"{y:.1}\" x2=\"{x1}\" y2=\"{y:.1}\" stroke=\"#eee\"/>\
+             <text class=\"axis\" x=\"{tx}\" y=\"{ty:.1}\" text-anchor=\"end\">{v:.digits$}</text>",
+            x0 = MARGIN_LEFT,
+            x1 = WIDTH - MARGIN_RIGHT,
+            tx = MARGIN_LEFT - 4.0,
+            ty = y + 3.0,
+        );
+    }
+}
+
+fn line_chart(labels: &[String], lines: &[Line], fill: bool) -> String {
+    let n = lines.iter().map(|l| l.values.len()).max().unwrap_or(0);
+    let (mut lo, mut hi) = value_range(lines.iter().flat_map(|l| l.values.iter().copied()));
+    if fill {
+        hi = hi.max(0.0);
+        lo = lo.min(0.0);
+    }
+    let plot_w = WIDTH - MARGIN_LEFT - MARGIN_RIGHT;
+    let plot_h = HEIGHT - MARGIN_TOP - MARGIN_BOTTOM;
+    let x_of = |i: usize| MARGIN_LEFT + plot_w * i as f64 / (n.max(2) - 1) as f64;
+    let y_of = |v: f64| MARGIN_TOP + plot_h * (hi - v) / (hi - lo);
+
+    let mut svg = svg_open(WIDTH, HEIGHT);
+    y_axis(&mut svg, lo, hi, 4, y_of);
+    for i in (0..n).step_by((n / 6).max(1)) {
+        if let Some(label) = labels.get(i) {
+            let _ = write!(
+                svg,
+                "<text class=\"axis\" x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+                x_of(i),
+                HEIGHT - 10.0,
+                escape(label)
+            );
+        }
+    }
+
+    for (k, line) in lines.iter().enumerate() {
+        let points: Vec<String> = line
+            .values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("{:.1},{:.1}", x_of(i), y_of(*v)))
+            .collect();
+        if fill && !points.is_empty() {
+            let _ = write!(
+                svg,
+                "<polygon points=\"{:.1},{:.1} {} {:.1},{:.1}\" fill=\"{}\" fill-opacity=\"0.3\"/>",
+                x_of(0),
+                y_of(0.0),
+                points.join(" "),
+                x_of(line.values.len() - 1),
+                y_of(0.0),
+                line.color
+            );
+        }
+        let _ = write!(
+            svg,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.2\"/>\
+             <text class=\"legend\" x=\"{}\" y=\"{}\" fill=\"{}\">{}</text>",
+            points.join(" "),
+            line.color,
+            MARGIN_LEFT + 10.0 + 60.0 * k as f64,
+            MARGIN_TOP - 10.0,
+            line.color,
+            escape(line.name)
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+fn monthly_heatmap(dates: &[String], returns: &[f64]) -> String {
+    let mut months: BTreeMap<(i32, usize), f64> = BTreeMap::new();
+    for (date, ret) in dates.iter().zip(returns) {
+        let year = date.get(0..4).and_then(|y| y.parse().ok());
+        let month = date.get(5..7).and_then(|m| m.parse::<usize>().ok());
+        if let (Some(year), Some(month)) = (year, month) {
+            *months.entry((year, month)).or_default() += ret;
+        }
+    }
+    let years: Vec<i32> = {
+        let mut ys: Vec<i32> = months.keys().map(|(y, _)| *y).collect();
+        ys.dedup();
+        ys
+    };
+    let max_abs = months.values().fold(0.0_f64, |m, v| m.max(v.abs())).max(1e-12);
+
+    let cell_w = (WIDTH - MARGIN_LEFT - MARGIN_RIGHT) / 13.0;
+    let cell_h = 24.0;
+    let height = MARGIN_TOP + cell_h * years.len() as f64 + 10.0;
+    let mut svg = svg_open(WIDTH, height);
+    for (m, name) in (1..=12).map(|m| (m, format!("{}月", m))).chain([(13, "全年".to_string())]) {
+        let _ = write!(
+            svg,
+            "<text class=\"axis\" x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+            MARGIN_LEFT + cell_w * (m as f64 - 0.5),
+            MARGIN_TOP - 8.0,
+            name
+        );
+    }
+    for (row, year) in years.iter().enumerate() {
+        let y = MARGIN_TOP + cell_h * row as f64;
+        let _ = write!(
+            svg,
+            "<text class=\"axis\" x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>",
+            MARGIN_LEFT - 6.0,
+            y + cell_h / 2.0 + 3.0,
+            year
+        );
+        let year_total: f64 = (1..=12).filter_map(|m| months.get(&(*year, m))).sum();
+        for month in 1..=13 {
+            let value = if month == 13 {
+                Some(year_total)
+            } else {
+                months.get(&(*year, month)).copied()
+            };
+            let Some(value) = value else { continue };
+            // 红涨绿跌
+            let alpha = (value.abs() / max_abs).min(1.0) * 0.85 + 0.05;
+            let color = if value >= 0.0 { "#c23531" } else { "#3a9b5c" };
+            let x = MARGIN_LEFT + cell_w * (month as f64 - 1.0);
+            let _ = write!(
+                svg,
+                "<rect x=\"{x:.1}\" y=\"{y:.1}\" width=\"{w:.1}\" height=\"{h:.1}\" fill=\"{color}\" \
+                 fill-opacity=\"{alpha:.2}\" stroke=\"#fff\"/>\
+                 <text class=\"axis\" x=\"{tx:.1}\" y=\"{ty:.1}\" text-anchor=\"middle\" fill=\"#000\">{pct:.2}%</text>",
+                w = cell_w,
+                h = cell_h,
+                tx = x + cell_w / 2.0,
+                ty = y + cell_h / 2.0 + 3.0,
+                pct = value * 100.0,
+            );
+        }
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+fn bar_chart(items: &[(&str, f64)]) -> String {
+    let bar_h = 16.0;
+    let label_w = 120.0;
+    let height = MARGIN_TOP + bar_h * items.len() as f64 + 10.0;
+    let (lo, hi) = value_range(items.iter().map(|(_, v)| *v).chain([0.0]));
+    let plot_w = WIDTH - label_w - MARGIN_RIGHT - 60.0;
+    let x_of = |v: f64| label_w + plot_w * (v - lo) / (hi - lo);
+
+    let mut svg = svg_open(WIDTH, height);
+    for (i, (symbol, value)) in items.iter().enumerate() {
+        let y = MARGIN_TOP + bar_h * i as f64;
+        let (x0, x1) = (x_of(0.0).min(x_of(*value)), x_of(0.0).max(x_of(*value)));
+        let color = if *value >= 0.0 { "#c23531" } else { "#3a9b5c" };
+        let _ = write!(
+            svg,
+            "<text class=\"axis\" x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>\
+             <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"/>\
+             <text class=\"axis\" x=\"{:.1}\" y=\"{:.1}\">{:.4}</text>",
+            label_w - 6.0,
+            y + bar_h - 4.0,
+            escape(symbol),
+            x0,
+            y + 2.0,
+            (x1 - x0).max(0.5),
+            bar_h - 4.0,
+            color,
+            x1 + 4.0,
+            y + bar_h - 4.0,
+            value
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+fn histogram(values: &[f64], bins: usize) -> String {
+    if values.is_empty() {
+        return "<p>无交易</p>".to_string();
+    }
+    let (lo, hi) = value_range(values.iter().copied());
+    let width = (hi - lo) / bins as f64;
+    let mut counts = vec![0usize; bins];
+    for v in values {
+        let idx = (((v - lo) / width) as usize).min(bins - 1);
+        counts[idx] += 1;
+    }
+    let max_count = counts.iter().copied().max().unwrap_or(1).max(1) as f64;
+
+    let plot_w = WIDTH - MARGIN_LEFT - MARGIN_RIGHT;
+    let plot_h = HEIGHT - MARGIN_TOP - MARGIN_BOTTOM;
+    let bar_w = plot_w / bins as f64;
+    let mut svg = svg_open(WIDTH, HEIGHT);
+    y_axis(&mut svg, 0.0, max_count, 0, |c| MARGIN_TOP + plot_h * (1.0 - c / max_count));
+    for (i, count) in counts.iter().enumerate() {
+        let h = plot_h * *count as f64 / max_count;
+        let left = lo + width * i as f64;
+        let color = if left + width / 2.0 >= 0.0 { "#c23531" } else { "#3a9b5c" };
+        let _ = write!(
+            svg,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"><title>{:.1} ~ {:.1}: {}</title></rect>",
+            MARGIN_LEFT + bar_w * i as f64 + 0.5,
+            MARGIN_TOP + plot_h - h,
+            (bar_w - 1.0).max(0.5),
+            h,
+            color,
+            left,
+            left + width,
+            count
+        );
+    }
+    for i in (0..=bins).step_by((bins / 8).max(1)) {
+        let _ = write!(
+            svg,
+            "<text class=\"axis\" x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{:.0}</text>",
+            MARGIN_LEFT + bar_w * i as f64,
+            HEIGHT - 10.0,
+            lo + width * i as f64
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
@@ -0,0 +1,81 @@
+use crate::errors::CzscError::Validation;
+use crate::errors::CzscResult;
+use polars::io::HiveOptions;
+use polars::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// 回测数据文件格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileFormat {
+    Parquet,
+    Ipc,
+    Csv,
+}
+
+impl FileFormat {
+    /// 根据文件扩展名推断格式
+    pub fn from_path(path: &Path) -> CzscResult<Self> {
+        let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+        match extension.as_deref() {
+            Some("parquet") | Some("pq") => Ok(FileFormat::Parquet),
+            Some("feather") | Some("ipc") | Some("arrow") => Ok(FileFormat::Ipc),
+            Some("csv") => Ok(FileFormat::Csv),
+            _ => Err(Validation(format!(
+                "Unsupported file format: {}",
+                path.display()
+            ))),
+        }
+    }
+}
+
+/// 惰性扫描数据文件，路径支持通配符
+///
+/// `hive_partitioned` 为真时从目录名（如 `symbol=XXX/`）解析分区列，
+/// 按品种过滤时只会读取对应目录下的文件
+pub fn scan_files(paths: &[PathBuf], hive_partitioned: bool) -> CzscResult<LazyFrame> {
+    let first = paths
+        .first()
+        .ok_or_else(|| Validation("No data files given".to_string()))?;
+    let format = FileFormat::from_path(first)?;
+    for path in paths {
+        if FileFormat::from_path(path)? != format {
+            return Err(Validation(format!(
+                "All data files must share the same format, got {}",
+                path.display()
+            )));
+        }
+    }
+
+    let hive_options = if hive_partitioned {
+        HiveOptions::new_enabled()
+    } else {
+        HiveOptions::new_disabled()
+    };
+    let paths: Arc<[PathBuf]> = paths.into();
+
+    let lf = match format {
+        FileFormat::Parquet => LazyFrame::scan_parquet_files(
+            paths,
+            ScanArgsParquet {
+                hive_options,
+                ..Default::default()
+            },
+        )?,
+        FileFormat::Ipc => LazyFrame::scan_ipc_files(
+            paths,
+            ScanArgsIpc {
+                hive_options,
+                ..Default::default()
+            },
+        )?,
+        FileFormat::Csv => {
+            if hive_partitioned {
+                return Err(Validation(
+                    "Hive partitioning is not supported for CSV files".to_string(),
+                ));
+            }
+            LazyCsvReader::new_paths(paths).with_try_parse_dates(true).finish()?
+        }
+    };
+    Ok(lf)
+}
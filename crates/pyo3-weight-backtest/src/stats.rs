@@ -1,20 +1,20 @@
 use crate::errors::CzscResult;
-use crate::types::{Direction, TradeEvaluation, TradePair};
+use crate::types::{Direction, MetricKey, TradeEvaluation, TradePair};
 use crate::utils::RoundTo;
-use polars::prelude::*;
-use pyo3::impl_::wrap::SomeWrap;
 use std::collections::HashMap;
 
 // 计算盈亏平衡点的辅助函数
 fn cal_break_even_point(seq: &[f64]) -> f64 {
+    // 创建可修改的副本，非有限值不参与计算
+    let mut sorted_seq: Vec<f64> = seq.iter().copied().filter(|x| x.is_finite()).collect();
+
     // 处理空序列或总收益为负的情况
-    if seq.is_empty() || seq.iter().sum::<f64>() < 0.0 {
+    if sorted_seq.is_empty() || sorted_seq.iter().sum::<f64>() < 0.0 {
         return 1.0;
     }
 
-    // 创建可修改的副本并排序（升序）
-    let mut sorted_seq = seq.to_vec();
-    sorted_seq.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    // 排序（升序）
+    sorted_seq.sort_by(|a, b| a.total_cmp(b));
 
     let n = sorted_seq.len() as f64;
     let mut cumulative_sum = 0.0;
@@ -103,32 +103,47 @@ pub fn evaluate_pairs(pairs: &[TradePair], direction: Direction) -> CzscResult<T
     Ok(result)
 }
 
-// 采用单利计算日收益数据的各项指标
-pub fn daily_performance(daily_returns: &[f64], yearly_days: Option<f64>) -> HashMap<String, f64> {
+/// `daily_performance` 输出的指标，按展示顺序排列
+pub const DAILY_PERFORMANCE_KEYS: [MetricKey; 15] = [
+    MetricKey::AbsoluteReturn,
+    MetricKey::AnnualReturn,
+    MetricKey::SharpeRatio,
+    MetricKey::MaxDrawdown,
+    MetricKey::CalmarRatio,
+    MetricKey::DailyWinRate,
+    MetricKey::DailyProfitLossRatio,
+    MetricKey::DailyWinExpectation,
+    MetricKey::AnnualVolatility,
+    MetricKey::DownsideVolatility,
+    MetricKey::NonZeroCoverage,
+    MetricKey::BreakEvenPoint,
+    MetricKey::NewHighInterval,
+    MetricKey::NewHighRatio,
+    MetricKey::DrawdownRisk,
+];
+
+/// 采用单利计算日收益数据的各项指标
+///
+/// 同时返回被截断的指标及其原始值
+pub fn daily_performance(
+    daily_returns: &[f64],
+    yearly_days: Option<f64>,
+) -> (HashMap<MetricKey, f64>, Vec<(MetricKey, f64)>) {
     let yearly_days = yearly_days.unwrap_or(252.0);
 
     // 初始化结果
-    let mut metrics: HashMap<String, f64> = HashMap::new();
-    metrics.insert("绝对收益".to_string(), 0.0);
-    metrics.insert("年化".to_string(), 0.0);
-    metrics.insert("夏普".to_string(), 0.0);
-    metrics.insert("最大回撤".to_string(), 0.0);
-    metrics.insert("卡玛".to_string(), 0.0);
-    metrics.insert("日胜率".to_string(), 0.0);
-    metrics.insert("日盈亏比".to_string(), 0.0);
-    metrics.insert("日赢面".to_string(), 0.0);
-    metrics.insert("年化波动率".to_string(), 0.0);
-    metrics.insert("下行波动率".to_string(), 0.0);
-    metrics.insert("非零覆盖".to_string(), 0.0);
-    metrics.insert("盈亏平衡点".to_string(), 0.0);
-    metrics.insert("新高间隔".to_string(), 0.0);
-    metrics.insert("新高占比".to_string(), 0.0);
-    metrics.insert("回撤风险".to_string(), 0.0);
+    let mut metrics: HashMap<MetricKey, f64> =
+        DAILY_PERFORMANCE_KEYS.iter().map(|&key| (key, 0.0)).collect();
 
     if daily_returns.is_empty() {
-        return metrics;
+        return (metrics, vec![]);
     }
 
+    // NaN 或无穷收益按 0 处理，保持交易日数量不变
+    let daily_returns: Vec<f64> =
+        daily_returns.iter().map(|&x| if x.is_finite() { x } else { 0.0 }).collect();
+    let daily_returns = daily_returns.as_slice();
+
     // 计算基本统计量
     let n = daily_returns.len() as f64;
     let sum_return: f64 = daily_returns.iter().sum();
@@ -138,7 +153,7 @@ pub fn daily_performance(daily_returns: &[f64], yearly_days: Option<f64>) -> Has
 
     // 检查无效数据
     if std_dev == 0.0 || daily_returns.iter().all(|&x| x == 0.0) {
-        return metrics;
+        return (metrics, vec![]);
     }
 
     // 计算累计收益和回撤
@@ -254,58 +269,66 @@ pub fn daily_performance(daily_returns: &[f64], yearly_days: Option<f64>) -> Has
         (x_clamped * multiplier).round() / multiplier
     }
 
+    // 限制夏普与卡玛的范围，并记录被截断的原始值
+    let mut clipped = Vec::new();
+    let sharpe = (mean_return / std_dev) * yearly_days.sqrt();
+    for (key, value, min_val, max_val) in [
+        (MetricKey::SharpeRatio, sharpe, -5.0, 10.0),
+        (MetricKey::CalmarRatio, kama, -10.0, 20.0),
+    ] {
+        if value < min_val || value > max_val {
+            clipped.push((key, value));
+        }
+        metrics.insert(key, min_max(value, min_val, max_val, 2));
+    }
+
     // 填充结果
     metrics.insert(
-        "绝对收益".to_string(),
+        MetricKey::AbsoluteReturn,
         (sum_return * 10000.0).round() / 10000.0,
     );
     metrics.insert(
-        "年化".to_string(),
+        MetricKey::AnnualReturn,
         (mean_return * yearly_days * 10000.0).round() / 10000.0,
     );
     metrics.insert(
-        "夏普".to_string(),
-        min_max((mean_return / std_dev) * yearly_days.sqrt(), -5.0, 10.0, 2),
-    );
-    metrics.insert(
-        "最大回撤".to_string(),
+        MetricKey::MaxDrawdown,
         (max_drawdown * 10000.0).round() / 10000.0,
     );
-    metrics.insert("卡玛".to_string(), min_max(kama, -10.0, 20.0, 2));
-    metrics.insert("日胜率".to_string(), (win_pct * 10000.0).round() / 10000.0);
+    metrics.insert(MetricKey::DailyWinRate, (win_pct * 10000.0).round() / 10000.0);
     metrics.insert(
-        "日盈亏比".to_string(),
+        MetricKey::DailyProfitLossRatio,
         (daily_ykb * 10000.0).round() / 10000.0,
     );
     metrics.insert(
-        "日赢面".to_string(),
+        MetricKey::DailyWinExpectation,
         (win_expectation * 10000.0).round() / 10000.0,
     );
     metrics.insert(
-        "年化波动率".to_string(),
+        MetricKey::AnnualVolatility,
         (annual_volatility * 10000.0).round() / 10000.0,
     );
     metrics.insert(
-        "下行波动率".to_string(),
+        MetricKey::DownsideVolatility,
         (downside_volatility * 10000.0).round() / 10000.0,
     );
     metrics.insert(
-        "非零覆盖".to_string(),
+        MetricKey::NonZeroCoverage,
         (non_zero_cover * 10000.0).round() / 10000.0,
     );
     metrics.insert(
-        "盈亏平衡点".to_string(),
+        MetricKey::BreakEvenPoint,
         (cal_break_even_point(daily_returns) * 10000.0).round() / 10000.0,
     );
-    metrics.insert("新高间隔".to_string(), max_interval);
+    metrics.insert(MetricKey::NewHighInterval, max_interval);
     metrics.insert(
-        "新高占比".to_string(),
+        MetricKey::NewHighRatio,
         (high_pct * 10000.0).round() / 10000.0,
     );
     metrics.insert(
-        "回撤风险".to_string(),
+        MetricKey::DrawdownRisk,
         (max_drawdown / annual_volatility * 10000.0).round() / 10000.0,
     );
 
-    metrics
+    (metrics, clipped)
 }
@@ -1,9 +1,10 @@
 use crate::types::TradeAction;
 use chrono::NaiveDateTime;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum TradePositionState {
     /// 空仓状态
+    #[default]
     Flat,
     /// 持有多头仓位 (手数)
     Long(u32),
@@ -11,8 +12,6 @@ pub enum TradePositionState {
     Short(u32),
 }
 impl TradePositionState {
-    /// 获取当前持仓状态的手数
-
     fn gen_trade_actions(volume: u32, action: TradeAction) -> Vec<TradeAction> {
         (0..volume).map(|_| action.clone()).collect()
     }
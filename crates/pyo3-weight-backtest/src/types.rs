@@ -1,7 +1,11 @@
+use crate::config::Locale;
+use crate::errors::CzscError;
 use chrono::{NaiveDate, NaiveDateTime};
 use pyo3::prelude::*;
+use pyo3::types::PyDict;
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::str::FromStr;
 
 #[derive(Debug, Clone)]
 pub enum TradeAction {
@@ -46,6 +50,19 @@ impl fmt::Display for Direction {
     }
 }
 
+impl FromStr for Direction {
+    type Err = CzscError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Long" => Ok(Direction::Long),
+            "Short" => Ok(Direction::Short),
+            "LongShort" => Ok(Direction::LongShort),
+            _ => Err(CzscError::Validation(format!("Invalid direction: {}", s))),
+        }
+    }
+}
+
 #[pymethods]
 impl Direction {
     fn __repr__(&self) -> String {
@@ -93,6 +110,22 @@ pub struct TradeEvaluation {
     pub avg_bars_held          : f64,    // 平均持仓K线数
 }
 
+impl TradeEvaluation {
+    /// 汇总表使用的字段，列名取自对应的指标键
+    pub fn columns(&self) -> [(MetricKey, f64); 8] {
+        [
+            (MetricKey::TradeCount,           self.trade_count as f64),
+            (MetricKey::TotalTradeReturn,     self.total_profit),
+            (MetricKey::TradeProfit,          self.avg_profit_per_trade),
+            (MetricKey::WinRate,              self.win_rate),
+            (MetricKey::TotalProfitLossRatio, self.total_profit_loss_ratio),
+            (MetricKey::AvgProfitLossRatio,   self.avg_profit_loss_ratio),
+            (MetricKey::TradeBreakEvenPoint,  self.break_even_point),
+            (MetricKey::HoldingDays,          self.avg_days_held),
+        ]
+    }
+}
+
 // DailyMetrics 结构体定义
 #[pyclass]
 #[derive(Debug, Clone, Serialize)]
@@ -112,6 +145,18 @@ pub struct DailyMetric {
     #[pyo3(get)] pub short_cost    : f64,
     #[pyo3(get)] pub short_return  : f64,
     #[pyo3(get)] pub short_turnover: f64,
+    /// 交易日最后一根 K 线的持仓权重
+    #[pyo3(get)] pub weight        : f64,
+    /// 交易日内全部 K 线多头权重的平均值
+    #[pyo3(get)] pub long_exposure : f64,
+    /// 交易日内全部 K 线空头权重的平均值，为负数
+    #[pyo3(get)] pub short_exposure: f64,
+    /// 交易日内持有多头的 K 线占比
+    #[pyo3(get)] pub long_bars     : f64,
+    /// 交易日内持有空头的 K 线占比
+    #[pyo3(get)] pub short_bars    : f64,
+    /// 交易日最后一根 K 线的波动率目标缩放系数，未启用波动率目标时为 1
+    #[pyo3(get)] pub vol_scale     : f64,
 }
 
 #[pyclass]
@@ -119,107 +164,326 @@ pub struct DailyMetric {
 pub struct SymbolResult {
     #[pyo3(get)] pub daily_metrics: Vec<DailyMetric>,
     #[pyo3(get)] pub trade_pairs  : Vec<TradePair>,
+    #[pyo3(get)] pub warnings     : Vec<BacktestWarning>,
+}
+
+// 回测警告类型
+#[pyclass(eq, eq_int)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WarningKind {
+    UnmatchedClose,    // 平仓时没有对应的开仓
+    NonPositivePrice,  // 价格小于等于 0 或非有限值
+    NanReturn,         // 日收益为 NaN 或无穷
+    DroppedRows,       // 权重或价格非有限值的行被丢弃
+    ClippedMetric,     // 指标超出范围被截断
+    NullValues,        // 必需列存在空值
+    DtypeMismatch,     // 列类型不符
+    Unsorted,          // 品种内时间未升序排列
+    DuplicateRows,     // 重复的 (dt, symbol)
+    NonFiniteWeight,   // 权重为 NaN 或无穷
+    WeightOutOfBounds, // 权重超出配置的边界
+    SingleBar,         // 品种只有一根 K 线
+    PriceJump,         // 价格跳变超过阈值
+}
+
+/// 回测过程中发现的数据异常，不中断回测
+#[pyclass]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BacktestWarning {
+    /// 组合级警告的 symbol 为空
+    #[pyo3(get)] pub symbol : String,
+    #[pyo3(get)] pub dt     : String,
+    #[pyo3(get)] pub kind   : WarningKind,
+    #[pyo3(get)] pub message: String,
+}
+
+impl BacktestWarning {
+    pub fn new(symbol: &str, dt: impl ToString, kind: WarningKind, message: String) -> Self {
+        BacktestWarning {
+            symbol: symbol.to_string(),
+            dt: dt.to_string(),
+            kind,
+            message,
+        }
+    }
+}
+
+#[pymethods]
+impl BacktestWarning {
+    fn __repr__(&self) -> String {
+        format!("BacktestWarning({:?}, {}, {}: {})", self.kind, self.symbol, self.dt, self.message)
+    }
 }
 
 /// 组合级绩效指标
+#[pyclass]
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct PortfolioMetrics {
     // 基本信息
-    pub start_date           : NaiveDate,
-    pub end_date             : NaiveDate,
-    pub num_symbols          : usize,
-    pub total_trades         : usize,
+    #[pyo3(get)] pub start_date           : NaiveDate,
+    #[pyo3(get)] pub end_date             : NaiveDate,
+    #[pyo3(get)] pub num_symbols          : usize,
+    #[pyo3(get)] pub total_trades         : usize,
 
     // 收益指标
-    pub total_return         : f64,
-    pub annualized_return    : f64,
-    pub cagr                 : f64,       // 复合年增长率
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub total_return         : f64,
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub annualized_return    : f64,
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub cagr                 : f64,       // 复合年增长率
 
     // 风险指标
-    pub volatility           : f64,       // 年化波动率
-    pub max_drawdown         : f64,
-    pub max_drawdown_duration: usize,     // 最大回撤持续时间(天)
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub volatility           : f64,       // 年化波动率
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub max_drawdown         : f64,
+    #[pyo3(get)] pub max_drawdown_duration: usize,     // 最大回撤持续时间(天)
 
     // 风险调整收益
-    pub sharpe_ratio         : f64,
-    pub sortino_ratio        : f64,
-    pub calmar_ratio         : f64,
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub sharpe_ratio         : f64,
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub sortino_ratio        : f64,
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub calmar_ratio         : f64,
 
     // 胜率相关
-    pub win_rate             : f64,       // 胜率
-    pub profit_factor        : f64,       // 盈利因子 (总盈利/总亏损)
-    pub avg_win_trade        : f64,       // 平均盈利交易收益
-    pub avg_loss_trade       : f64,       // 平均亏损交易损失
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub win_rate             : f64,       // 胜率
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub profit_factor        : f64,       // 盈利因子 (总盈利/总亏损)
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub avg_win_trade        : f64,       // 平均盈利交易收益
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub avg_loss_trade       : f64,       // 平均亏损交易损失
 
     // 持仓时间
-    pub avg_holding_days     : f64,
-    pub median_holding_days  : f64,
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub avg_holding_days     : f64,
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub median_holding_days  : f64,
 
     // 多空指标
-    pub long_ratio           : f64,       // 多头交易占比
-    pub short_ratio          : f64,       // 空头交易占比
-    pub long_win_rate        : f64,
-    pub short_win_rate       : f64,
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub long_ratio           : f64,       // 多头持仓占比
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub short_ratio          : f64,       // 空头持仓占比
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub long_win_rate        : f64,
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub short_win_rate       : f64,
 
     // 换手率
-    pub avg_daily_turnover   : f64,
-    pub annual_turnover      : f64,
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub avg_daily_turnover   : f64,
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub annual_turnover      : f64,
+
+    // 敞口
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub avg_gross_exposure   : f64,       // 平均总敞口 (权重绝对值之和)
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub avg_net_exposure     : f64,       // 平均净敞口
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub avg_long_count       : f64,       // 平均多头持仓品种数
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub avg_short_count      : f64,       // 平均空头持仓品种数
 
     // 基准对比
-    pub alpha                : f64,       // 超额收益
-    pub beta                 : f64,       // 系统性风险
-    pub tracking_error       : f64,       // 跟踪误差
-    pub information_ratio    : f64,
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub alpha                : f64,       // 超额收益
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub beta                 : f64,       // 系统性风险
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub tracking_error       : f64,       // 跟踪误差
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub information_ratio    : f64,
 
     // 其他
-    pub skewness             : f64,       // 收益偏度
-    pub kurtosis             : f64,       // 收益峰度
-}
-
-// 指标键枚举
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum MetricKey {
-    TradeProfit,
-    HoldingBars,
-    WinRate,
-    HoldingDays,
-    LongRatio,
-    ShortRatio,
-    VolatilityRatio,
-    VolatilityCorrelation,
-    ReturnCorrelation,
-    BearCorrelation,
-    SymbolCount,
-    MaxDrawdown,
-    SharpeRatio,
-    StartDate,
-    EndDate,
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub skewness             : f64,       // 收益偏度
+    #[serde(with = "json_f64")]
+    #[pyo3(get)] pub kurtosis             : f64,       // 收益峰度
+}
+
+#[pymethods]
+impl PortfolioMetrics {
+    /// 转为 Python 字典，日期为 `datetime.date`
+    pub fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("start_date", self.start_date)?;
+        dict.set_item("end_date", self.end_date)?;
+        dict.set_item("num_symbols", self.num_symbols)?;
+        dict.set_item("total_trades", self.total_trades)?;
+        dict.set_item("total_return", self.total_return)?;
+        dict.set_item("annualized_return", self.annualized_return)?;
+        dict.set_item("cagr", self.cagr)?;
+        dict.set_item("volatility", self.volatility)?;
+        dict.set_item("max_drawdown", self.max_drawdown)?;
+        dict.set_item("max_drawdown_duration", self.max_drawdown_duration)?;
+        dict.set_item("sharpe_ratio", self.sharpe_ratio)?;
+        dict.set_item("sortino_ratio", self.sortino_ratio)?;
+        dict.set_item("calmar_ratio", self.calmar_ratio)?;
+        dict.set_item("win_rate", self.win_rate)?;
+        dict.set_item("profit_factor", self.profit_factor)?;
+        dict.set_item("avg_win_trade", self.avg_win_trade)?;
+        dict.set_item("avg_loss_trade", self.avg_loss_trade)?;
+        dict.set_item("avg_holding_days", self.avg_holding_days)?;
+        dict.set_item("median_holding_days", self.median_holding_days)?;
+        dict.set_item("long_ratio", self.long_ratio)?;
+        dict.set_item("short_ratio", self.short_ratio)?;
+        dict.set_item("long_win_rate", self.long_win_rate)?;
+        dict.set_item("short_win_rate", self.short_win_rate)?;
+        dict.set_item("avg_daily_turnover", self.avg_daily_turnover)?;
+        dict.set_item("annual_turnover", self.annual_turnover)?;
+        dict.set_item("avg_gross_exposure", self.avg_gross_exposure)?;
+        dict.set_item("avg_net_exposure", self.avg_net_exposure)?;
+        dict.set_item("avg_long_count", self.avg_long_count)?;
+        dict.set_item("avg_short_count", self.avg_short_count)?;
+        dict.set_item("alpha", self.alpha)?;
+        dict.set_item("beta", self.beta)?;
+        dict.set_item("tracking_error", self.tracking_error)?;
+        dict.set_item("information_ratio", self.information_ratio)?;
+        dict.set_item("skewness", self.skewness)?;
+        dict.set_item("kurtosis", self.kurtosis)?;
+        Ok(dict)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+/// JSON 不支持 NaN/inf：NaN 序列化为 null，正负无穷序列化为字符串 "inf"/"-inf"，读取时无损还原
+pub(crate) mod json_f64 {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Number(f64),
+        Text(String),
+    }
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_finite() {
+            serializer.serialize_f64(*value)
+        } else if value.is_nan() {
+            serializer.serialize_none()
+        } else if *value > 0.0 {
+            serializer.serialize_str("inf")
+        } else {
+            serializer.serialize_str("-inf")
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        match Option::<Repr>::deserialize(deserializer)? {
+            Some(Repr::Number(value)) => Ok(value),
+            Some(Repr::Text(text)) => match text.as_str() {
+                "inf" => Ok(f64::INFINITY),
+                "-inf" => Ok(f64::NEG_INFINITY),
+                _ => Err(D::Error::custom(format!("invalid number {:?}", text))),
+            },
+            None => Ok(f64::NAN),
+        }
+    }
+}
+
+/// 按 `json_f64` 规则序列化的浮点数，用于字典等无法逐字段标注的位置
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct JsonF64(#[serde(with = "json_f64")] pub f64);
+
+/// 由一张表生成指标键枚举、`ALL` 列表与中英文键名，新增指标只需在表中加一行
+macro_rules! metric_keys {
+    ($($key:ident => ($zh:literal, $en:literal),)*) => {
+        // 指标键枚举，组合指标字典的键统一在此登记
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum MetricKey {
+            $($key,)*
+        }
+
+        impl MetricKey {
+            pub const ALL: [MetricKey; [$(stringify!($key)),*].len()] = [$(Self::$key),*];
+
+            /// 中文与英文键名，中文键与 czsc 保持一致
+            fn labels(&self) -> (&'static str, &'static str) {
+                match self {
+                    $(Self::$key => ($zh, $en),)*
+                }
+            }
+        }
+    };
+}
+
+metric_keys! {
+    TradeProfit           => ("单笔收益",         "avg_trade_return"),
+    HoldingBars           => ("持仓K线数",        "avg_holding_bars"),
+    WinRate               => ("交易胜率",         "trade_win_rate"),
+    HoldingDays           => ("持仓天数",         "avg_holding_days"),
+    LongRatio             => ("多头占比",         "long_ratio"),
+    ShortRatio            => ("空头占比",         "short_ratio"),
+    VolatilityRatio       => ("波动比",           "volatility_ratio"),
+    VolatilityCorrelation => ("与基准波动相关性", "benchmark_volatility_corr"),
+    ReturnCorrelation     => ("与基准收益相关性", "benchmark_return_corr"),
+    BearCorrelation       => ("与基准空头相关性", "benchmark_bear_corr"),
+    SymbolCount           => ("品种数量",         "symbol_count"),
+    StartDate             => ("最小日期",         "start_date"),
+    EndDate               => ("最大日期",         "end_date"),
+    AbsoluteReturn        => ("绝对收益",         "absolute_return"),
+    AnnualReturn          => ("年化",             "annual_return"),
+    SharpeRatio           => ("夏普",             "sharpe_ratio"),
+    MaxDrawdown           => ("最大回撤",         "max_drawdown"),
+    CalmarRatio           => ("卡玛",             "calmar_ratio"),
+    DailyWinRate          => ("日胜率",           "daily_win_rate"),
+    DailyProfitLossRatio  => ("日盈亏比",         "daily_profit_loss_ratio"),
+    DailyWinExpectation   => ("日赢面",           "daily_win_expectation"),
+    AnnualVolatility      => ("年化波动率",       "annual_volatility"),
+    DownsideVolatility    => ("下行波动率",       "downside_volatility"),
+    NonZeroCoverage       => ("非零覆盖",         "non_zero_coverage"),
+    BreakEvenPoint        => ("盈亏平衡点",       "break_even_point"),
+    NewHighInterval       => ("新高间隔",         "new_high_interval"),
+    NewHighRatio          => ("新高占比",         "new_high_ratio"),
+    DrawdownRisk          => ("回撤风险",         "drawdown_risk"),
+    // 以下只用于交易统计表
+    TradeCount            => ("交易次数",         "trade_count"),
+    TotalTradeReturn      => ("累计收益",         "total_trade_return"),
+    TotalProfitLossRatio  => ("累计盈亏比",       "total_profit_loss_ratio"),
+    AvgProfitLossRatio    => ("单笔盈亏比",       "avg_profit_loss_ratio"),
+    TradeBreakEvenPoint   => ("交易盈亏平衡点",   "trade_break_even_point"),
 }
 
 impl MetricKey {
-    fn as_str(&self) -> &'static str {
-        match self {
-            Self::TradeProfit           => "单笔收益",
-            Self::HoldingBars           => "持仓K线数",
-            Self::WinRate               => "交易胜率",
-            Self::HoldingDays           => "持仓天数",
-            Self::LongRatio             => "多头占比",
-            Self::ShortRatio            => "空头占比",
-            Self::VolatilityRatio       => "波动比",
-            Self::VolatilityCorrelation => "与基准波动相关性",
-            Self::ReturnCorrelation     => "与基准收益相关性",
-            Self::BearCorrelation       => "与基准空头相关性",
-            Self::SymbolCount           => "品种数量",
-            Self::MaxDrawdown           => "最大回撤",
-            Self::SharpeRatio           => "夏普比率",
-            Self::StartDate             => "起始日期",
-            Self::EndDate               => "结束日期",
+    /// 中文键名
+    pub fn as_str(&self) -> &'static str {
+        self.labels().0
+    }
+
+    /// 英文 snake_case 键名
+    pub fn as_en_str(&self) -> &'static str {
+        self.labels().1
+    }
+
+    /// 按语言取键名
+    pub fn label(&self, locale: Locale) -> &'static str {
+        match locale {
+            Locale::Zh => self.as_str(),
+            Locale::En => self.as_en_str(),
         }
     }
+
+    /// 由任一语言的键名反查指标
+    pub fn from_label(label: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|key| key.as_str() == label || key.as_en_str() == label)
+    }
 }
 
-impl ToString for MetricKey {
-    fn to_string(&self) -> String {
-        self.as_str().to_string()
+impl fmt::Display for MetricKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
     }
 }
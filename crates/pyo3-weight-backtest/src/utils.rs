@@ -1,11 +1,14 @@
+use crate::config::TradingDay;
 use crate::errors::CzscError::Validation;
 use crate::errors::CzscResult;
+use chrono::{DateTime, NaiveDateTime};
 use polars::prelude::*;
 
+/// 检查必需列与非空，空值等行级问题由 `validation` 模块处理
 pub fn validate_dataframe(df: &DataFrame) -> CzscResult<()> {
     let required_columns = ["dt", "symbol", "weight", "price"];
     for &col in &required_columns {
-        if !df.column(col).is_ok() {
+        if df.column(col).is_err() {
             return Err(Validation(format!(
                 "DataFrame is missing required column: {}",
                 col
@@ -16,14 +19,183 @@ pub fn validate_dataframe(df: &DataFrame) -> CzscResult<()> {
         return Err(Validation("DataFrame is empty".to_string()));
     }
 
-    let total_nulls = df.get_columns().iter().map(|s| s.null_count()).sum::<usize>();
-    if total_nulls > 0 {
-        return Err(Validation("DataFrame contains null values".to_string()));
+    Ok(())
+}
+
+/// 对惰性数据执行与 `validate_dataframe` 相同的检查，只载入行数
+pub fn validate_lazyframe(lf: &LazyFrame) -> CzscResult<()> {
+    let schema = lf.clone().collect_schema()?;
+    let required_columns = ["dt", "symbol", "weight", "price"];
+    for &col in &required_columns {
+        if !schema.contains(col) {
+            return Err(Validation(format!(
+                "DataFrame is missing required column: {}",
+                col
+            )));
+        }
+    }
+
+    let height = lf.clone().select([len()]).collect()?;
+    if height.get_columns()[0].get(0)?.try_extract::<u64>()? == 0 {
+        return Err(Validation("DataFrame is empty".to_string()));
     }
 
     Ok(())
 }
 
+/// 引擎内部统一使用的时间类型：交易所本地时间，纳秒精度，不带时区
+pub fn dt_dtype() -> DataType {
+    DataType::Datetime(TimeUnit::Nanoseconds, None)
+}
+
+/// 将 dt 列统一为 `dt_dtype()` 的表达式，已是目标类型时返回 None
+///
+/// 支持 Date、任意精度的 Datetime、带时区的 Datetime（转换到交易所时区 `timezone`）
+/// 以及 ISO 8601 字符串（不带偏移量的按交易所本地时间解释）
+pub fn normalize_dt_expr(schema: &Schema, timezone: &str) -> CzscResult<Option<Expr>> {
+    let dtype = schema
+        .get("dt")
+        .ok_or_else(|| Validation("DataFrame is missing required column: dt".to_string()))?;
+    let exchange_tz = TimeZone::opt_try_new(Some(timezone))?
+        .ok_or_else(|| Validation(format!("Invalid timezone: {:?}", timezone)))?;
+    let to_local = |expr: Expr| {
+        expr.dt()
+            .convert_time_zone(exchange_tz.clone())
+            .dt()
+            .replace_time_zone(None, lit("raise"), NonExistent::Raise)
+    };
+
+    let expr = match dtype {
+        DataType::Datetime(TimeUnit::Nanoseconds, None) => return Ok(None),
+        DataType::Datetime(_, None) | DataType::Date => col("dt").cast(dt_dtype()),
+        DataType::Datetime(_, Some(tz)) => {
+            to_local(col("dt").cast(DataType::Datetime(TimeUnit::Nanoseconds, Some(tz.clone()))))
+        }
+        DataType::String => to_local(col("dt").str().to_datetime(
+            Some(TimeUnit::Nanoseconds),
+            Some(exchange_tz.clone()),
+            StrptimeOptions { strict: true, ..Default::default() },
+            lit("raise"),
+        )),
+        other => {
+            return Err(Validation(format!(
+                "column dt has unsupported dtype {}, expected Date, Datetime or ISO 8601 string",
+                other
+            )))
+        }
+    };
+    Ok(Some(expr.alias("dt")))
+}
+
+/// 统一 dt 列的类型，字符串无法解析时报告第一个出错的值
+pub fn normalize_dt(df: DataFrame, timezone: &str) -> CzscResult<DataFrame> {
+    let Some(expr) = normalize_dt_expr(df.schema(), timezone)? else {
+        return Ok(df);
+    };
+    match df.clone().lazy().with_column(expr).collect() {
+        Ok(df) => Ok(df),
+        Err(err) => {
+            check_dt_strings(df.clone().lazy(), df.schema())?;
+            Err(Validation(format!("column dt: {}", err)))
+        }
+    }
+}
+
+/// 惰性数据的 dt 统一为 `dt_dtype()`；字符串 dt 在此先扫描一遍，报告第一个无法解析的值
+pub fn normalize_dt_lazy(lf: LazyFrame, schema: &Schema, timezone: &str) -> CzscResult<LazyFrame> {
+    let Some(expr) = normalize_dt_expr(schema, timezone)? else {
+        return Ok(lf);
+    };
+    check_dt_strings(lf.clone(), schema)?;
+    Ok(lf.with_column(expr))
+}
+
+/// 字符串 dt 列中存在无法解析为时间的值时报错，错误信息给出第一个出错的值
+fn check_dt_strings(lf: LazyFrame, schema: &Schema) -> CzscResult<()> {
+    if schema.get("dt") != Some(&DataType::String) {
+        return Ok(());
+    }
+    let parsed = col("dt").str().to_datetime(
+        Some(TimeUnit::Nanoseconds),
+        None,
+        StrptimeOptions { strict: false, ..Default::default() },
+        lit("null"),
+    );
+    let bad = lf
+        .select([col("dt")])
+        .filter(col("dt").is_not_null().and(parsed.is_null()))
+        .first()
+        .collect()?;
+    if bad.height() == 0 {
+        return Ok(());
+    }
+    Err(Validation(format!(
+        "column dt: cannot parse {:?} as a datetime",
+        bad.column("dt")?.str()?.get(0).unwrap_or_default()
+    )))
+}
+
+/// 按交易日规则由 dt 计算交易日 (Date)
+pub fn trade_date_expr(rule: &TradingDay) -> Expr {
+    let date = col("dt").dt().date();
+    // Date 的物理类型为自 1970-01-01 起的天数
+    let add_days = |date: Expr, days: i32| (date.cast(DataType::Int32) + lit(days)).cast(DataType::Date);
+    match rule {
+        TradingDay::Calendar => date,
+        TradingDay::Cutoff { hour } => {
+            let date = when(col("dt").dt().hour().gt_eq(lit(*hour)))
+                .then(add_days(date.clone(), 1))
+                .otherwise(date);
+            // 周五夜盘及其跨过零点的部分计入下周一
+            let weekday = date.clone().dt().weekday();
+            when(weekday.clone().eq(lit(6)))
+                .then(add_days(date.clone(), 2))
+                .when(weekday.eq(lit(7)))
+                .then(add_days(date.clone(), 1))
+                .otherwise(date)
+        }
+        TradingDay::Column { name } => col(name.as_str()).cast(DataType::Date),
+    }
+}
+
+/// 交易日规则为 `Column` 时检查数据中存在该列，且每一行都能解析为日期，错误信息给出第一个出错的行号
+pub fn validate_trading_day(lf: LazyFrame, schema: &Schema, rule: &TradingDay) -> CzscResult<()> {
+    let TradingDay::Column { name } = rule else {
+        return Ok(());
+    };
+    if !schema.contains(name) {
+        return Err(Validation(format!("DataFrame is missing trading day column: {}", name)));
+    }
+    let bad = lf
+        .select([
+            col(name.as_str()).cast(DataType::String).alias("value"),
+            trade_date_expr(rule).alias("date"),
+        ])
+        .with_row_index("row", None)
+        .filter(col("date").is_null())
+        .first()
+        .collect()?;
+    if bad.height() == 0 {
+        return Ok(());
+    }
+    Err(Validation(format!(
+        "trading day column {}: row {} has null or unparseable value {:?}",
+        name,
+        bad.column("row")?.idx()?.get(0).unwrap_or_default(),
+        bad.column("value")?.str()?.get(0)
+    )))
+}
+
+/// 按时间精度将时间戳转换为 `NaiveDateTime`
+pub fn timestamp_to_datetime(ts: i64, unit: TimeUnit) -> Option<NaiveDateTime> {
+    let dt = match unit {
+        TimeUnit::Nanoseconds => Some(DateTime::from_timestamp_nanos(ts)),
+        TimeUnit::Microseconds => DateTime::from_timestamp_micros(ts),
+        TimeUnit::Milliseconds => DateTime::from_timestamp_millis(ts),
+    };
+    dt.map(|dt| dt.naive_utc())
+}
+
 pub trait RoundTo {
     fn round_to(&self, decimals: u32) -> f64;
 }
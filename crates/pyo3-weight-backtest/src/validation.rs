@@ -0,0 +1,456 @@
+use crate::errors::CzscError::Validation;
+use crate::errors::CzscResult;
+use crate::types::{BacktestWarning, WarningKind};
+use crate::utils::{timestamp_to_datetime, validate_dataframe};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// 数据检查发现问题时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckAction {
+    /// 拒绝数据
+    Error,
+    /// 记录警告，数据保持不变
+    Warn,
+    /// 自动修复（丢弃、前值填充或截断）并记录警告
+    Fix,
+}
+
+impl FromStr for CheckAction {
+    type Err = crate::errors::CzscError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "error" => Ok(CheckAction::Error),
+            "warn" => Ok(CheckAction::Warn),
+            "fix" => Ok(CheckAction::Fix),
+            _ => Err(anyhow::anyhow!("Invalid check action {:?}, must be 'error', 'warn' or 'fix'", s).into()),
+        }
+    }
+}
+
+/// 输入数据检查配置，每项检查可单独设为 error / warn / fix
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ValidationConfig {
+    /// 必需列中的空值，fix: 丢弃该行
+    pub null_values       : CheckAction,
+    /// 列类型不符，fix: 转换为 symbol: String、weight/price: Float64
+    pub dtypes            : CheckAction,
+    /// 同一品种内 dt 未升序排列，fix: 按品种、时间排序
+    pub unsorted          : CheckAction,
+    /// 重复的 (dt, symbol)，fix: 保留最后一行
+    pub duplicates        : CheckAction,
+    /// 权重为 NaN/无穷，fix: 丢弃该行
+    pub non_finite_weight : CheckAction,
+    /// 价格小于等于 0 或非有限值，fix: 用前一个有效价格填充
+    pub non_positive_price: CheckAction,
+    /// 单根 K 线相对上一根的涨跌幅超过 `max_price_jump`，fix: 用前一个价格填充
+    pub price_jump        : CheckAction,
+    pub max_price_jump    : f64,
+    /// 权重绝对值超过 `max_abs_weight`，fix: 截断到边界；未设置边界时不检查
+    pub weight_bound      : CheckAction,
+    pub max_abs_weight    : Option<f64>,
+    /// 只有一根 K 线的品种，fix: 丢弃该品种
+    pub single_bar        : CheckAction,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        ValidationConfig {
+            null_values       : CheckAction::Error,
+            dtypes            : CheckAction::Fix,
+            unsorted          : CheckAction::Fix,
+            duplicates        : CheckAction::Warn,
+            non_finite_weight : CheckAction::Fix,
+            non_positive_price: CheckAction::Fix,
+            price_jump        : CheckAction::Warn,
+            max_price_jump    : 0.5,
+            weight_bound      : CheckAction::Warn,
+            max_abs_weight    : None,
+            single_bar        : CheckAction::Warn,
+        }
+    }
+}
+
+impl ValidationConfig {
+    /// 检查阈值参数
+    pub fn validate(&self) -> CzscResult<()> {
+        if !self.max_price_jump.is_finite() || self.max_price_jump <= 0.0 {
+            return Err(Validation(format!(
+                "max_price_jump must be a positive number, got {}",
+                self.max_price_jump
+            )));
+        }
+        if let Some(bound) = self.max_abs_weight {
+            if !bound.is_finite() || bound <= 0.0 {
+                return Err(Validation(format!(
+                    "max_abs_weight must be a positive number, got {}",
+                    bound
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 检查完整数据：列类型与全部行级检查
+pub fn validate_bars(
+    df: DataFrame,
+    config: &ValidationConfig,
+) -> CzscResult<(DataFrame, Vec<BacktestWarning>)> {
+    validate_frame(df, config, true)
+}
+
+/// 检查增量更新的一批数据，批次内品种只有一根 K 线属于正常情况，不做单 K 线检查
+pub fn validate_batch(
+    df: DataFrame,
+    config: &ValidationConfig,
+) -> CzscResult<(DataFrame, Vec<BacktestWarning>)> {
+    validate_frame(df, config, false)
+}
+
+fn validate_frame(
+    df: DataFrame,
+    config: &ValidationConfig,
+    single_bar: bool,
+) -> CzscResult<(DataFrame, Vec<BacktestWarning>)> {
+    validate_dataframe(&df)?;
+    let (casts, mut warnings) = validate_schema(df.schema(), config)?;
+    let df = if casts.is_empty() { df } else { df.lazy().with_columns(casts).collect()? };
+    let (df, row_warnings) = Validator::new(config, single_bar).run(df)?;
+    warnings.extend(row_warnings);
+    Ok((df, warnings))
+}
+
+/// 只对单个品种的数据做行级检查，供惰性数据逐品种载入时使用
+pub fn validate_symbol_bars(
+    df: DataFrame,
+    config: &ValidationConfig,
+) -> CzscResult<(DataFrame, Vec<BacktestWarning>)> {
+    Validator::new(config, true).run(df)
+}
+
+/// 只检查权重列（空值、非有限值、越界），供多策略回测在共享价格数据上逐策略检查
+pub fn validate_weights(
+    df: DataFrame,
+    config: &ValidationConfig,
+) -> CzscResult<(DataFrame, Vec<BacktestWarning>)> {
+    let mut validator = Validator::new(config, false);
+    let mut df = df;
+
+    let null_weight = col("weight").is_null();
+    if validator.check(
+        &df,
+        WarningKind::NullValues,
+        config.null_values,
+        null_weight.clone(),
+        "rows with null weight",
+        "dropped",
+    )? {
+        df = df.lazy().filter(null_weight.not()).collect()?;
+    }
+    let df = validator.non_finite_weights(df)?;
+    let df = validator.weight_bounds(df)?;
+
+    if !validator.errors.is_empty() {
+        return Err(Validation(validator.errors.join("; ")));
+    }
+    Ok((df, validator.warnings))
+}
+
+/// 检查列类型，返回 fix 时需要执行的类型转换；类型问题属于数据整体，警告的 symbol 为空
+pub fn validate_schema(
+    schema: &Schema,
+    config: &ValidationConfig,
+) -> CzscResult<(Vec<Expr>, Vec<BacktestWarning>)> {
+    // dt 列已由 `normalize_dt` 统一类型
+    let expected = [
+        ("symbol", DataType::String),
+        ("weight", DataType::Float64),
+        ("price", DataType::Float64),
+    ];
+
+    let mut casts = Vec::new();
+    let mut warnings = Vec::new();
+    let mut errors = Vec::new();
+    for (name, target) in expected {
+        let dtype = schema
+            .get(name)
+            .ok_or_else(|| Validation(format!("DataFrame is missing required column: {}", name)))?;
+        if *dtype == target {
+            continue;
+        }
+
+        let message = format!("column {} has dtype {}, expected {}", name, dtype, target);
+        match config.dtypes {
+            CheckAction::Error => errors.push(message),
+            CheckAction::Warn => {
+                warnings.push(BacktestWarning::new("", "", WarningKind::DtypeMismatch, message))
+            }
+            CheckAction::Fix => {
+                casts.push(col(name).strict_cast(target.clone()));
+                warnings.push(BacktestWarning::new(
+                    "",
+                    "",
+                    WarningKind::DtypeMismatch,
+                    format!("{}, cast to {}", message, target),
+                ));
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(Validation(errors.join("; ")));
+    }
+    Ok((casts, warnings))
+}
+
+/// 行级检查，逐项统计各品种出问题的行数与首个出问题的时间
+struct Validator<'a> {
+    config    : &'a ValidationConfig,
+    single_bar: bool,
+    warnings  : Vec<BacktestWarning>,
+    errors    : Vec<String>,
+}
+
+impl<'a> Validator<'a> {
+    fn new(config: &'a ValidationConfig, single_bar: bool) -> Self {
+        Validator { config, single_bar, warnings: Vec::new(), errors: Vec::new() }
+    }
+
+    fn run(mut self, df: DataFrame) -> CzscResult<(DataFrame, Vec<BacktestWarning>)> {
+        let config = self.config;
+        let mut df = df;
+
+        let has_null = col("dt")
+            .is_null()
+            .or(col("symbol").is_null())
+            .or(col("weight").is_null())
+            .or(col("price").is_null());
+        if self.check(
+            &df,
+            WarningKind::NullValues,
+            config.null_values,
+            has_null.clone(),
+            "rows with null dt, symbol, weight or price",
+            "dropped",
+        )? {
+            df = df.lazy().filter(has_null.not()).collect()?;
+        }
+
+        df = self.non_finite_weights(df)?;
+
+        // 后续检查依赖品种内的时间顺序；回测本身按时间重排，排序不影响结果
+        let unsorted = col("dt")
+            .lt(col("dt").shift(lit(1)).over([col("symbol")]))
+            .fill_null(lit(false));
+        self.check(
+            &df,
+            WarningKind::Unsorted,
+            config.unsorted,
+            unsorted,
+            "rows earlier than the previous bar",
+            "sorted by symbol and dt",
+        )?;
+        df = df.sort(
+            ["symbol", "dt"],
+            SortMultipleOptions::default().with_maintain_order(true),
+        )?;
+
+        let duplicated = len().over([col("symbol"), col("dt")]).gt(lit(1));
+        if self.check(
+            &df,
+            WarningKind::DuplicateRows,
+            config.duplicates,
+            duplicated,
+            "rows share the same (dt, symbol)",
+            "kept the last row",
+        )? {
+            df = df
+                .lazy()
+                .unique_stable(Some(vec!["symbol".into(), "dt".into()]), UniqueKeepStrategy::Last)
+                .collect()?;
+        }
+
+        let bad_price = col("price")
+            .is_finite()
+            .not()
+            .or(col("price").lt_eq(lit(0.0)))
+            .fill_null(lit(false));
+        if self.check(
+            &df,
+            WarningKind::NonPositivePrice,
+            config.non_positive_price,
+            bad_price.clone(),
+            "bars have zero, negative or non-finite price",
+            "filled with the previous price",
+        )? {
+            df = fill_prev_price(df, bad_price)?;
+        }
+
+        let prev_price = col("price").shift(lit(1)).over([col("symbol")]);
+        let jumped = prev_price
+            .clone()
+            .gt(lit(0.0))
+            .and(col("price").gt(lit(0.0)))
+            .and((col("price") / prev_price - lit(1.0)).abs().gt(lit(config.max_price_jump)))
+            .fill_null(lit(false));
+        let what = format!("bars move more than {:.0}% from the previous bar", config.max_price_jump * 100.0);
+        if self.check(
+            &df,
+            WarningKind::PriceJump,
+            config.price_jump,
+            jumped.clone(),
+            &what,
+            "filled with the previous price",
+        )? {
+            df = fill_prev_price(df, jumped)?;
+        }
+
+        df = self.weight_bounds(df)?;
+
+        if self.single_bar {
+            let single = len().over([col("symbol")]).eq(lit(1));
+            if self.check(
+                &df,
+                WarningKind::SingleBar,
+                config.single_bar,
+                single.clone(),
+                "bar is the only bar of the symbol",
+                "symbol dropped",
+            )? {
+                df = df.lazy().filter(single.not()).collect()?;
+            }
+        }
+
+        if !self.errors.is_empty() {
+            return Err(Validation(self.errors.join("; ")));
+        }
+        Ok((df, self.warnings))
+    }
+
+    /// 权重为 NaN/无穷的行
+    fn non_finite_weights(&mut self, df: DataFrame) -> CzscResult<DataFrame> {
+        let bad_weight = col("weight").is_finite().not().fill_null(lit(false));
+        if self.check(
+            &df,
+            WarningKind::NonFiniteWeight,
+            self.config.non_finite_weight,
+            bad_weight.clone(),
+            "rows with NaN or infinite weight",
+            "dropped",
+        )? {
+            return Ok(df.lazy().filter(bad_weight.not()).collect()?);
+        }
+        Ok(df)
+    }
+
+    /// 权重绝对值超过配置的边界，未设置边界时不检查
+    fn weight_bounds(&mut self, df: DataFrame) -> CzscResult<DataFrame> {
+        let Some(bound) = self.config.max_abs_weight else {
+            return Ok(df);
+        };
+        let out_of_bound = col("weight").abs().gt(lit(bound)).fill_null(lit(false));
+        let what = format!("rows have |weight| above {}", bound);
+        if self.check(
+            &df,
+            WarningKind::WeightOutOfBounds,
+            self.config.weight_bound,
+            out_of_bound,
+            &what,
+            "clipped to the bound",
+        )? {
+            return Ok(df
+                .lazy()
+                .with_column(col("weight").clip(lit(-bound), lit(bound)))
+                .collect()?);
+        }
+        Ok(df)
+    }
+
+    /// 统计 `mask` 命中的行；action 为 error 时记录错误，否则记录警告。
+    /// 返回是否需要修复
+    fn check(
+        &mut self,
+        df: &DataFrame,
+        kind: WarningKind,
+        action: CheckAction,
+        mask: Expr,
+        what: &str,
+        fix: &str,
+    ) -> CzscResult<bool> {
+        let hits = df
+            .clone()
+            .lazy()
+            .with_column(mask.alias("_hit"))
+            .filter(col("_hit"))
+            .group_by_stable([col("symbol").cast(DataType::String)])
+            .agg([len().alias("rows"), col("dt").first()])
+            .collect()?;
+        if hits.height() == 0 {
+            return Ok(false);
+        }
+
+        let symbols = hits.column("symbol")?.str()?;
+        let rows = hits.column("rows")?.idx()?;
+        let dts = hits.column("dt")?;
+        for i in 0..hits.height() {
+            let symbol = symbols.get(i).unwrap_or_default();
+            let dt = dt_string(dts, i)?;
+            let count = rows.get(i).unwrap_or_default();
+            match action {
+                CheckAction::Error => {
+                    self.errors.push(format!("{} {}: {} {}", symbol, dt, count, what))
+                }
+                CheckAction::Warn => self.warnings.push(BacktestWarning::new(
+                    symbol,
+                    dt,
+                    kind,
+                    format!("{} {}", count, what),
+                )),
+                CheckAction::Fix => self.warnings.push(BacktestWarning::new(
+                    symbol,
+                    dt,
+                    kind,
+                    format!("{} {}, {}", count, what, fix),
+                )),
+            }
+        }
+        Ok(action == CheckAction::Fix)
+    }
+}
+
+/// 将命中 `mask` 的价格替换为同品种前一个有效价格，品种开头无前值的行被丢弃
+fn fill_prev_price(df: DataFrame, mask: Expr) -> CzscResult<DataFrame> {
+    Ok(df
+        .lazy()
+        .with_column(mask.alias("_hit"))
+        .with_column(
+            when(col("_hit"))
+                .then(lit(NULL).cast(DataType::Float64))
+                .otherwise(col("price"))
+                .fill_null_with_strategy(FillNullStrategy::Forward(None))
+                .over([col("symbol")])
+                .alias("price"),
+        )
+        .filter(col("_hit").and(col("price").is_null()).not())
+        .drop(["_hit"])
+        .collect()?)
+}
+
+/// 第 `idx` 行的时间，非 Datetime 列按原值显示
+fn dt_string(column: &Column, idx: usize) -> CzscResult<String> {
+    match column.dtype() {
+        DataType::Datetime(unit, _) => Ok(column
+            .datetime()?
+            .physical()
+            .get(idx)
+            .and_then(|ts| timestamp_to_datetime(ts, *unit))
+            .map(|dt| dt.to_string())
+            .unwrap_or_default()),
+        _ => Ok(column.get(idx)?.to_string()),
+    }
+}
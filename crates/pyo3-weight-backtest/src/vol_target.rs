@@ -0,0 +1,148 @@
+use crate::config::{TradingDay, WeightType};
+use crate::errors::CzscError::Validation;
+use crate::errors::CzscResult;
+use crate::utils::trade_date_expr;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// 波动率目标：按事前已实现波动率缩放权重，使年化波动率接近 `target`
+///
+/// 时序策略逐品种按价格收益的波动率缩放，截面策略按组合收益的波动率整体缩放；
+/// 第 d 个交易日的缩放系数只使用 d 之前 `lookback` 个交易日的收盘价，
+/// 回看窗口不足时缩放系数为 1，系数不超过 `max_leverage`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VolTarget {
+    /// 年化目标波动率，如 0.15
+    pub target      : f64,
+    /// 计算已实现波动率的交易日数
+    pub lookback    : usize,
+    /// 缩放系数上限
+    pub max_leverage: f64,
+}
+
+impl Default for VolTarget {
+    fn default() -> Self {
+        VolTarget {
+            target      : 0.15,
+            lookback    : 20,
+            max_leverage: 2.0,
+        }
+    }
+}
+
+impl VolTarget {
+    /// 检查各参数的取值范围
+    pub fn validate(&self) -> CzscResult<()> {
+        if !self.target.is_finite() || self.target <= 0.0 {
+            return Err(Validation(format!(
+                "vol_target.target must be a positive number, got {}",
+                self.target
+            )));
+        }
+        if self.lookback < 2 {
+            return Err(Validation(format!(
+                "vol_target.lookback must be at least 2, got {}",
+                self.lookback
+            )));
+        }
+        if !self.max_leverage.is_finite() || self.max_leverage <= 0.0 {
+            return Err(Validation(format!(
+                "vol_target.max_leverage must be a positive number, got {}",
+                self.max_leverage
+            )));
+        }
+        Ok(())
+    }
+
+    /// 由年化波动率计算缩放系数，波动率未知时为 1，为 0 时取上限
+    fn scale_expr(&self, vol: Expr, yearly_days: usize) -> Expr {
+        let annual = vol * lit((yearly_days as f64).sqrt());
+        (lit(self.target) / annual)
+            .clip(lit(0.0), lit(self.max_leverage))
+            .fill_nan(lit(1.0))
+            .fill_null(lit(1.0))
+            .alias("vol_scale")
+    }
+
+    fn rolling_std(&self, expr: Expr) -> Expr {
+        expr.rolling_std(RollingOptionsFixedWindow {
+            window_size: self.lookback,
+            min_periods: self.lookback,
+            ..Default::default()
+        })
+    }
+}
+
+/// 按波动率目标缩放 (dt, symbol, weight, price) 数据的权重，并添加每行的缩放系数列 vol_scale；
+/// 在权重取整之前调用
+pub fn apply_vol_target(
+    df: DataFrame,
+    vol_target: &VolTarget,
+    weight_type: WeightType,
+    trading_day: &TradingDay,
+    yearly_days: usize,
+) -> CzscResult<DataFrame> {
+    let df = df
+        .lazy()
+        .with_column(trade_date_expr(trading_day).alias("vol_date"))
+        .collect()?;
+
+    // 每个品种每个交易日的收盘价、收盘权重与日收益
+    let daily = df
+        .clone()
+        .lazy()
+        .sort(["symbol", "dt"], SortMultipleOptions::default())
+        .group_by_stable([col("symbol"), col("vol_date")])
+        .agg([
+            col("price").last().alias("close"),
+            col("weight").last().alias("close_weight"),
+        ])
+        .sort(["symbol", "vol_date"], SortMultipleOptions::default())
+        .with_column(
+            (col("close") / col("close").shift(lit(1)) - lit(1.0))
+                .over([col("symbol")])
+                .alias("ret"),
+        );
+
+    // 第 d 天的波动率取截至 d - 1 的窗口，不使用当天及之后的价格
+    let (scales, keys) = match weight_type {
+        WeightType::TimeSeries => {
+            let vol = vol_target.rolling_std(col("ret")).shift(lit(1)).over([col("symbol")]);
+            let scales = daily.select([
+                col("symbol"),
+                col("vol_date"),
+                vol_target.scale_expr(vol, yearly_days),
+            ]);
+            (scales, vec![col("symbol"), col("vol_date")])
+        }
+        WeightType::CrossSection => {
+            // 组合日收益：前一交易日的收盘权重乘以当日收益
+            let contribution =
+                col("close_weight").shift(lit(1)).over([col("symbol")]) * col("ret");
+            let vol = vol_target.rolling_std(col("portfolio_ret")).shift(lit(1));
+            let scales = daily
+                .with_column(contribution.alias("contribution"))
+                .group_by([col("vol_date")])
+                .agg([col("contribution").sum().alias("portfolio_ret")])
+                .sort(["vol_date"], SortMultipleOptions::default())
+                .select([col("vol_date"), vol_target.scale_expr(vol, yearly_days)]);
+            (scales, vec![col("vol_date")])
+        }
+    };
+
+    Ok(df
+        .lazy()
+        .join(
+            scales,
+            keys.clone(),
+            keys,
+            JoinArgs {
+                maintain_order: MaintainOrderJoin::Left,
+                ..JoinArgs::new(JoinType::Left)
+            },
+        )
+        .with_column((col("weight").cast(DataType::Float64) * col("vol_scale")).alias("weight"))
+        .drop(["vol_date"])
+        .collect()?)
+}
@@ -1,6 +1,23 @@
 use polars::io::ipc::IpcReader;
 use polars::prelude::*;
 use pyo3_polars::PyDataFrame;
+use std::collections::HashSet;
+use std::sync::atomic::AtomicBool;
+use std::sync::Mutex;
+use weight_backtest_pyo3::attribution::Period;
+use weight_backtest_pyo3::blend::{blend_returns, BlendMethod, BlendResult};
+use weight_backtest_pyo3::capital::CapitalConfig;
+use weight_backtest_pyo3::config::{BacktestConfig, Locale, TradingDay, WeightType};
+use weight_backtest_pyo3::engine::{BacktestEngine, BacktestResult};
+use weight_backtest_pyo3::errors::CzscError;
+use weight_backtest_pyo3::exits::{apply_exit_rules, ExitRules};
+use weight_backtest_pyo3::factor::{evaluate_factor, FactorConfig};
+use weight_backtest_pyo3::incremental::IncrementalEngine;
+use weight_backtest_pyo3::multi::MultiBacktest;
+use weight_backtest_pyo3::progress::{ProgressMode, ProgressSink};
+use weight_backtest_pyo3::types::{Direction, MetricKey, TradePair, WarningKind};
+use weight_backtest_pyo3::validation::CheckAction;
+use weight_backtest_pyo3::vol_target::VolTarget;
 use weight_backtest_pyo3::WeightBacktest;
 
 // 生成基本测试数据
@@ -32,16 +49,1191 @@ fn read_feather_sync(path: &str) -> DataFrame {
 
 #[test]
 fn test_backtest_engine_creation() {
-    // let df = create_test_df();
-    let df = read_feather_sync("/Users/i7xh/Downloads/weight_example.feather");
+    // 本地样例数据不存在时使用构造数据
+    let path = "/Users/i7xh/Downloads/weight_example.feather";
+    let df = if std::path::Path::new(path).exists() {
+        read_feather_sync(path)
+    } else {
+        create_test_df()
+    };
 
     let col_names = df.get_column_names();
     println!("方法1 - 所有列名: {:?}", col_names);
 
     let py_df = PyDataFrame(df);
-    let engine = WeightBacktest::new(py_df, 2, &*"ts".to_string(), 0.0002, 252, 1);
+    let engine = WeightBacktest::new(py_df, 2, "ts", 0.0002, 252, 1);
 
     assert!(engine.is_ok());
     // let engine = engine.unwrap();
     // assert_eq!(engine.symbols, vec!["AAPL"]);
 }
+
+// 生成多品种日线测试数据
+fn create_daily_df(days: i64) -> DataFrame {
+    let start = chrono::NaiveDate::from_ymd_opt(2023, 1, 2)
+        .unwrap()
+        .and_hms_opt(15, 0, 0)
+        .unwrap();
+    let mut dts = Vec::new();
+    let mut symbols = Vec::new();
+    let mut weights = Vec::new();
+    let mut prices = Vec::new();
+    for (k, symbol) in ["AAA", "BBB"].iter().enumerate() {
+        for i in 0..days {
+            dts.push(start + chrono::Duration::days(i));
+            symbols.push(*symbol);
+            weights.push(((i as f64 + k as f64) * 0.7).sin().round_to_one());
+            prices.push(100.0 + ((i * (k as i64 + 1)) as f64 * 0.3).cos() * 5.0 + i as f64 * 0.1);
+        }
+    }
+    df![
+        "dt" => dts,
+        "symbol" => symbols,
+        "weight" => weights,
+        "price" => prices,
+    ]
+    .unwrap()
+}
+
+trait RoundToOne {
+    fn round_to_one(self) -> f64;
+}
+
+impl RoundToOne for f64 {
+    fn round_to_one(self) -> f64 {
+        (self * 10.0).round() / 10.0
+    }
+}
+
+#[test]
+fn test_incremental_matches_full_backtest() {
+    // 检查设为 warn 时非有限权重留到处理器丢弃，多空占比须按丢弃后的 K 线统计
+    let mut df = create_daily_df(40);
+    let mut weights: Vec<f64> = df.column("weight").unwrap().f64().unwrap().into_no_null_iter().collect();
+    weights[5] = f64::NAN;
+    weights[50] = f64::INFINITY;
+    df.with_column(Column::new("weight".into(), weights)).unwrap();
+    let mut config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    config.validation.non_finite_weight = CheckAction::Warn;
+
+    let full = BacktestEngine::new(df.clone(), config.clone())
+        .unwrap()
+        .run_backtest()
+        .unwrap();
+
+    let mut unsupported = config.clone();
+    unsupported.vol_target = Some(VolTarget::default());
+    assert!(matches!(IncrementalEngine::new(unsupported), Err(CzscError::Validation(_))));
+
+    let mut engine = IncrementalEngine::new(config).unwrap();
+    let split = chrono::NaiveDate::from_ymd_opt(2023, 1, 25)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    let head = df.clone().lazy().filter(col("dt").lt(lit(split))).collect().unwrap();
+    let tail = df.lazy().filter(col("dt").gt_eq(lit(split))).collect().unwrap();
+    engine.update(head).unwrap();
+    let update = engine.update(tail).unwrap();
+
+    for (key, value) in &full.portfolio_metrics {
+        let incremental = update.portfolio_metrics[key];
+        assert!(
+            (value - incremental).abs() < 1e-9 || (value.is_nan() && incremental.is_nan()),
+            "{key}: {value} != {incremental}"
+        );
+    }
+    let totals = |df: &DataFrame| -> Vec<f64> {
+        df.column("total").unwrap().f64().unwrap().into_no_null_iter().collect()
+    };
+    assert_eq!(totals(&full.daily_ew_return_df.0), totals(&update.daily_return_df.0));
+
+    let result = engine.result().unwrap();
+    assert_eq!(full.metrics.long_ratio, result.metrics.long_ratio);
+    assert_eq!(full.metrics.short_ratio, result.metrics.short_ratio);
+    for (symbol, sr) in &full.symbol_results {
+        let incremental = &result.symbol_results[symbol];
+        assert_eq!(sr.trade_pairs.len(), incremental.trade_pairs.len());
+        assert_eq!(sr.daily_metrics.len(), incremental.daily_metrics.len());
+        for (a, b) in sr.daily_metrics.iter().zip(&incremental.daily_metrics) {
+            assert_eq!(a.date, b.date);
+            assert!((a.return_val - b.return_val).abs() < 1e-12);
+            assert!((a.turnover - b.turnover).abs() < 1e-12);
+            assert!((a.n1b - b.n1b).abs() < 1e-12);
+        }
+    }
+}
+
+#[test]
+fn test_lazy_files_match_in_memory_backtest() {
+    // 未取整的权重：如 0.037 取整后为空仓，多空占比须按取整后的权重统计
+    let mut df = create_daily_df(30);
+    let weights: Vec<f64> =
+        df.column("weight").unwrap().f64().unwrap().into_no_null_iter().map(|w| w * 0.37).collect();
+    df.with_column(Column::new("weight".into(), weights)).unwrap();
+    // 各时间点的品种不完全相同，K 线编号须与全量数据按 (dt, symbol) 排序后的行号一致
+    let df = df
+        .lazy()
+        .with_row_index("row", None)
+        .filter(col("symbol").eq(lit("AAA")).or((col("row") % lit(7)).neq(lit(3))))
+        .drop(["row"])
+        .collect()
+        .unwrap();
+    let config = BacktestConfig::new(1, 0.0002, "cs".to_string(), 252, 2).unwrap();
+    let expected = BacktestEngine::new(df.clone(), config.clone())
+        .unwrap()
+        .run_backtest()
+        .unwrap();
+
+    // 按 symbol 分区写入 parquet
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    for part in df.partition_by_stable(["symbol"], true).unwrap() {
+        let symbol = part.column("symbol").unwrap().str().unwrap().get(0).unwrap().to_string();
+        let dir = root.join(format!("symbol={symbol}"));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut part = part.drop("symbol").unwrap();
+        let file = std::fs::File::create(dir.join("part.parquet")).unwrap();
+        ParquetWriter::new(file).finish(&mut part).unwrap();
+    }
+
+    let paths = vec![root.join("**/*.parquet")];
+    let result = BacktestEngine::from_files(&paths, true, config)
+        .unwrap()
+        .run_backtest()
+        .unwrap();
+
+    for (key, value) in &expected.portfolio_metrics {
+        let lazy = result.portfolio_metrics[key];
+        assert!(
+            (value - lazy).abs() < 1e-9 || (value.is_nan() && lazy.is_nan()),
+            "{key}: {value} != {lazy}"
+        );
+    }
+    assert_eq!(expected.metrics.long_ratio, result.metrics.long_ratio);
+    assert_eq!(expected.metrics.short_ratio, result.metrics.short_ratio);
+    assert_eq!(expected.symbol_results.len(), result.symbol_results.len());
+    for (symbol, sr) in &expected.symbol_results {
+        let bar_counts = |pairs: &[TradePair]| pairs.iter().map(|p| p.bar_count).collect::<Vec<_>>();
+        assert_eq!(bar_counts(&sr.trade_pairs), bar_counts(&result.symbol_results[symbol].trade_pairs));
+    }
+}
+
+#[test]
+fn test_config_file_round_trip_and_validation() {
+    let config = BacktestConfig::from_toml_str("weight_type = \"cs\"\nfee_rate = 0.0005\n").unwrap();
+    assert_eq!(config.weight_type, WeightType::CrossSection);
+    assert_eq!(config.digits, BacktestConfig::default().digits);
+
+    let reloaded = BacktestConfig::from_toml_str(&config.to_toml_string().unwrap()).unwrap();
+    assert_eq!(reloaded.fee_rate, config.fee_rate);
+    let reloaded = BacktestConfig::from_json_str(&config.to_json_string().unwrap()).unwrap();
+    assert_eq!(reloaded.weight_type, config.weight_type);
+
+    assert!(matches!(
+        BacktestConfig::from_toml_str("fee_rate = -0.1"),
+        Err(CzscError::Validation(_))
+    ));
+    assert!(matches!(
+        BacktestConfig::from_json_str("{\"digits\": 12}"),
+        Err(CzscError::Validation(_))
+    ));
+    assert!(matches!(
+        BacktestConfig::from_toml_str("fee = 0.1"),
+        Err(CzscError::Serialization(_))
+    ));
+    // 截断后的权重上界乘以 10^digits 超出 i32
+    let bounded = "digits = 9\n[validation]\nweight_bound = \"fix\"\nmax_abs_weight = ";
+    assert!(BacktestConfig::from_toml_str(&format!("{bounded}2.0")).is_ok());
+    assert!(matches!(
+        BacktestConfig::from_toml_str(&format!("{bounded}3.0")),
+        Err(CzscError::Validation(_))
+    ));
+}
+
+#[test]
+fn test_volume_overflow_is_an_error() {
+    let config = BacktestConfig::new(9, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    let mut df = create_daily_df(30);
+    df.with_column(df.column("weight").unwrap() * 3.0).unwrap();
+    let result = BacktestEngine::new(df, config).and_then(|engine| engine.run_backtest());
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_metric_keys_follow_locale() {
+    let zh_config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    let en_config = BacktestConfig::from_toml_str("digits = 1\nlocale = \"en\"\n").unwrap();
+    assert_eq!(en_config.locale, Locale::En);
+
+    let zh = BacktestEngine::new(create_daily_df(30), zh_config).unwrap().run_backtest().unwrap();
+    let en = BacktestEngine::new(create_daily_df(30), en_config).unwrap().run_backtest().unwrap();
+
+    assert!(zh.portfolio_metrics.contains_key("夏普"));
+    assert!(en.portfolio_metrics.contains_key("sharpe_ratio"));
+    assert_eq!(zh.portfolio_metrics.len(), en.portfolio_metrics.len());
+    assert!(en.portfolio_metrics.keys().all(|k| k.is_ascii()));
+
+    let converted = zh.localized_metrics(Locale::En);
+    for (key, value) in &en.portfolio_metrics {
+        let other = converted[key];
+        assert!(value == &other || (value.is_nan() && other.is_nan()), "{key}");
+    }
+    assert_eq!(en.localized_metrics(Locale::Zh).keys().len(), zh.portfolio_metrics.len());
+
+    // 两种语言的键名互不重复，均可反查回原指标
+    let labels: HashSet<&str> =
+        MetricKey::ALL.iter().flat_map(|key| [key.as_str(), key.as_en_str()]).collect();
+    assert_eq!(labels.len(), MetricKey::ALL.len() * 2);
+    for key in MetricKey::ALL {
+        assert_eq!(MetricKey::from_label(key.as_str()), Some(key));
+        assert_eq!(MetricKey::from_label(key.as_en_str()), Some(key));
+    }
+}
+
+#[test]
+fn test_result_dataframes() {
+    let config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    let result = BacktestEngine::new(create_daily_df(30), config)
+        .unwrap()
+        .run_backtest()
+        .unwrap();
+
+    let daily = result.daily_metrics_df(None).unwrap();
+    let pairs = result.trade_pairs_df(None).unwrap();
+    let total_days: usize = result.symbol_results.values().map(|sr| sr.daily_metrics.len()).sum();
+    let total_pairs: usize = result.symbol_results.values().map(|sr| sr.trade_pairs.len()).sum();
+    assert_eq!(daily.height(), total_days);
+    assert_eq!(pairs.height(), total_pairs);
+    assert_eq!(daily.column("symbol").unwrap().str().unwrap().get(0), Some("AAA"));
+
+    let aaa = result.daily_metrics_df(Some("AAA")).unwrap();
+    assert_eq!(aaa.height(), result.symbol_results["AAA"].daily_metrics.len());
+    let aaa_pairs = result.trade_pairs_df(Some("AAA")).unwrap();
+    assert_eq!(aaa_pairs.height(), result.symbol_results["AAA"].trade_pairs.len());
+    assert!(matches!(result.trade_pairs_df(Some("ZZZ")), Err(CzscError::Validation(_))));
+}
+
+#[test]
+fn test_typed_portfolio_metrics() {
+    let config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    let result = BacktestEngine::new(create_daily_df(60), config)
+        .unwrap()
+        .run_backtest()
+        .unwrap();
+
+    let metrics = &result.metrics;
+    let stats = &result.portfolio_metrics;
+    assert_eq!(metrics.num_symbols, 2);
+    assert_eq!(metrics.num_symbols as f64, stats["品种数量"]);
+    assert_eq!(metrics.win_rate, stats["交易胜率"]);
+    assert!(metrics.start_date < metrics.end_date);
+    assert!(metrics.total_trades > 0);
+
+    // 收益与风险指标和指标字典同一口径
+    assert_eq!(metrics.total_return, stats["绝对收益"]);
+    assert_eq!(metrics.annualized_return, stats["年化"]);
+    assert_eq!(metrics.volatility, stats["年化波动率"]);
+    assert_eq!(metrics.max_drawdown, stats["最大回撤"]);
+    assert_eq!(metrics.sharpe_ratio, stats["夏普"]);
+    assert_eq!(metrics.calmar_ratio, stats["卡玛"]);
+    assert_eq!(metrics.max_drawdown_duration as f64, stats["新高间隔"] - 1.0);
+    assert!((metrics.sortino_ratio - stats["年化"] / stats["下行波动率"]).abs() < 1e-12);
+}
+
+#[test]
+fn test_symbol_performance_table() {
+    let config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    let result = BacktestEngine::new(create_daily_df(30), config)
+        .unwrap()
+        .run_backtest()
+        .unwrap();
+
+    let table = result.symbol_performance_df().unwrap();
+    assert_eq!(table.height(), 2);
+    let symbols: Vec<&str> = table.column("symbol").unwrap().str().unwrap().into_no_null_iter().collect();
+    assert_eq!(symbols, ["AAA", "BBB"]);
+
+    let sr = &result.symbol_results["BBB"];
+    let pairs = |direction: &str| sr.trade_pairs.iter().filter(|p| p.direction.to_string() == direction).count();
+    let value = |name: &str| table.column(name).unwrap().f64().unwrap().get(1).unwrap();
+    assert_eq!(value("交易次数"), sr.trade_pairs.len() as f64);
+    assert_eq!(value("多头交易次数"), pairs("Long") as f64);
+    assert_eq!(value("空头交易次数"), pairs("Short") as f64);
+    let total: f64 = sr.daily_metrics.iter().map(|m| m.return_val).sum();
+    assert!((value("绝对收益") - total).abs() < 1e-4);
+    assert!(table.column("夏普").is_ok() && table.column("最大回撤").is_ok());
+
+    let mut en = result;
+    en.config.locale = Locale::En;
+    let table = en.symbol_performance_df().unwrap();
+    assert!(table.column("sharpe_ratio").is_ok() && table.column("short_trade_win_rate").is_ok());
+}
+
+#[test]
+fn test_long_short_leg_performance() {
+    let config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    let result = BacktestEngine::new(create_daily_df(30), config)
+        .unwrap()
+        .run_backtest()
+        .unwrap();
+
+    let legs = result.leg_returns_df().unwrap();
+    let total: Vec<f64> = legs.column("total").unwrap().f64().unwrap().into_no_null_iter().collect();
+    let long: Vec<f64> = legs.column("long").unwrap().f64().unwrap().into_no_null_iter().collect();
+    let short: Vec<f64> = legs.column("short").unwrap().f64().unwrap().into_no_null_iter().collect();
+    let ew = result.daily_ew_return_df.0.column("total").unwrap().f64().unwrap().clone();
+    assert_eq!(total, ew.into_no_null_iter().collect::<Vec<_>>());
+    for i in 0..total.len() {
+        assert!((long[i] + short[i] - total[i]).abs() < 2e-4);
+    }
+
+    let table = result.leg_performance_df().unwrap();
+    let legs: Vec<&str> = table.column("leg").unwrap().str().unwrap().into_no_null_iter().collect();
+    assert_eq!(legs, ["多空", "多头", "空头"]);
+    let value = |name: &str, row: usize| table.column(name).unwrap().f64().unwrap().get(row).unwrap();
+    assert!((value("绝对收益", 1) - long.iter().sum::<f64>()).abs() < 1e-6);
+    assert!((value("绝对收益", 2) - short.iter().sum::<f64>()).abs() < 1e-6);
+
+    let pairs: Vec<_> = result.symbol_results.values().flat_map(|sr| &sr.trade_pairs).collect();
+    let count = |d: Direction| pairs.iter().filter(|p| p.direction == d).count() as f64;
+    assert_eq!(value("交易次数", 0), pairs.len() as f64);
+    assert_eq!(value("交易次数", 1), count(Direction::Long));
+    assert_eq!(value("交易次数", 2), count(Direction::Short));
+
+    // 持仓 K 线价格为 0 时，另一条腿的 0 × inf 不能让绩效计算失败
+    let mut df = create_daily_df(30);
+    let mut weights: Vec<f64> = df.column("weight").unwrap().f64().unwrap().into_no_null_iter().collect();
+    let mut prices: Vec<f64> = df.column("price").unwrap().f64().unwrap().into_no_null_iter().collect();
+    weights[3] = 0.5;
+    prices[3] = 0.0;
+    df.with_column(Column::new("weight".into(), weights)).unwrap();
+    df.with_column(Column::new("price".into(), prices)).unwrap();
+    let mut config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    config.validation.non_positive_price = CheckAction::Warn;
+    let result = BacktestEngine::new(df, config).unwrap().run_backtest().unwrap();
+    let nan_return = result.warnings.iter().find(|w| w.kind == WarningKind::NanReturn).unwrap();
+    assert_eq!((nan_return.symbol.as_str(), nan_return.dt.as_str()), ("AAA", "2023-01-05"));
+    let table = result.leg_performance_df().unwrap();
+    for column in table.get_columns().iter().filter(|c| c.dtype() == &DataType::Float64) {
+        assert!(column.f64().unwrap().into_no_null_iter().all(f64::is_finite));
+    }
+}
+
+#[test]
+fn test_return_attribution() {
+    let config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    let result = BacktestEngine::new(create_daily_df(60), config)
+        .unwrap()
+        .run_backtest()
+        .unwrap();
+    let total: f64 = result.symbol_results.values()
+        .flat_map(|sr| &sr.daily_metrics)
+        .map(|m| m.return_val)
+        .sum::<f64>() / 2.0;
+
+    let summary = result.attribution_summary().unwrap();
+    let value = |column: &str, name: &str| {
+        let idx = summary.column("component").unwrap().str().unwrap()
+            .into_no_null_iter().position(|c| c == name).unwrap();
+        summary.column(column).unwrap().f64().unwrap().get(idx).unwrap()
+    };
+    let contribution = |name: &str| value("contribution", name);
+    assert!((contribution("return") - total).abs() < 1e-9);
+    // 毛收益与成本恰好拆分净收益，两者的占比之和为 1
+    assert!(contribution("cost") < 0.0);
+    assert!((contribution("edge") + contribution("cost") - total).abs() < 1e-9);
+    assert!((contribution("long_cost") + contribution("short_cost") - contribution("cost")).abs() < 1e-9);
+    assert!((value("share", "edge") + value("share", "cost") - 1.0).abs() < 1e-9);
+    assert!((contribution("long_return") + contribution("short_return") - total).abs() < 1e-9);
+
+    let symbols = result.symbol_attribution_df(None).unwrap();
+    let returns: Vec<f64> = symbols.column("return").unwrap().f64().unwrap().into_no_null_iter().collect();
+    assert_eq!(returns.len(), 2);
+    assert!(returns[0] >= returns[1]);
+    assert!((returns.iter().sum::<f64>() - total).abs() < 1e-9);
+    let shares: f64 = symbols.column("share").unwrap().f64().unwrap().sum().unwrap();
+    assert!((shares - 1.0).abs() < 1e-9);
+
+    let months = result.period_attribution_df(Period::Month).unwrap();
+    let periods: Vec<&str> = months.column("period").unwrap().str().unwrap().into_no_null_iter().collect();
+    assert_eq!(periods, ["2023-01", "2023-02", "2023-03"]);
+    let monthly: f64 = months.column("return").unwrap().f64().unwrap().sum().unwrap();
+    assert!((monthly - total).abs() < 1e-9);
+    assert_eq!(result.period_attribution_df(Period::Year).unwrap().height(), 1);
+    assert!("week".parse::<Period>().is_err());
+}
+
+#[test]
+fn test_exposure_series() {
+    let config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    let result = BacktestEngine::new(create_daily_df(30), config)
+        .unwrap()
+        .run_backtest()
+        .unwrap();
+
+    let exposure = result.exposure_df().unwrap();
+    assert_eq!(exposure.height(), 30);
+    let f = |name: &str| -> Vec<f64> {
+        exposure.column(name).unwrap().cast(&DataType::Float64).unwrap()
+            .f64().unwrap().into_no_null_iter().collect()
+    };
+    let (gross, net, long, short) = (f("gross"), f("net"), f("long"), f("short"));
+    let (long_count, short_count) = (f("long_count"), f("short_count"));
+
+    // 第一天两个品种的权重为 sin(0)=0 与 sin(0.7)≈0.6
+    assert!((gross[0] - 0.3).abs() < 1e-9);
+    for i in 0..gross.len() {
+        assert!((long[i] + short[i] - net[i]).abs() < 1e-9);
+        assert!((long[i] - short[i] - gross[i]).abs() < 1e-9);
+        assert!(short[i] <= 0.0 && long_count[i] + short_count[i] <= 2.0);
+    }
+
+    let mean = |v: &[f64]| v.iter().sum::<f64>() / v.len() as f64;
+    let m = &result.metrics;
+    assert!((m.avg_gross_exposure - mean(&gross)).abs() < 1e-12);
+    assert!((m.avg_net_exposure - mean(&net)).abs() < 1e-12);
+    assert!((m.avg_long_count - mean(&long_count)).abs() < 1e-12);
+    assert!((m.annual_turnover - mean(&f("turnover")) * 252.0).abs() < 1e-9);
+    assert!(m.annual_turnover > 0.0);
+
+    // 分钟数据的敞口取交易日内全部 K 线的平均值，而不是收盘时的持仓
+    let dt: Vec<chrono::NaiveDateTime> = ["2023-01-03", "2023-01-04"]
+        .iter()
+        .flat_map(|day| {
+            ["09:30:00", "10:30:00", "13:30:00", "14:30:00"].map(|time| {
+                chrono::NaiveDateTime::parse_from_str(&format!("{} {}", day, time), "%Y-%m-%d %H:%M:%S")
+                    .unwrap()
+            })
+        })
+        .collect();
+    let df = df![
+        "dt" => dt,
+        "symbol" => ["AAA"; 8],
+        "weight" => [1.0, 1.0, -1.0, 0.0, 0.5, 0.5, 0.5, 0.5],
+        "price" => [100.0, 101.0, 102.0, 101.0, 100.0, 99.0, 100.0, 101.0],
+    ]
+    .unwrap();
+    let config = BacktestConfig::new(1, 0.0, "ts".to_string(), 252, 1).unwrap();
+    let result = BacktestEngine::new(df, config).unwrap().run_backtest().unwrap();
+    let exposure = result.exposure_df().unwrap();
+    let row = |name: &str| -> Vec<f64> {
+        exposure.column(name).unwrap().f64().unwrap().into_no_null_iter().collect()
+    };
+    assert_eq!(row("gross"), [0.75, 0.5]);
+    assert_eq!(row("net"), [0.25, 0.5]);
+    assert_eq!(row("long"), [0.5, 0.5]);
+    assert_eq!(row("short"), [-0.25, 0.0]);
+    assert_eq!(row("long_count"), [0.5, 1.0]);
+    assert_eq!(row("short_count"), [0.25, 0.0]);
+}
+
+#[test]
+fn test_factor_evaluation() {
+    // 因子取下一根 K 线的收益，IC 与秩 IC 应为 1，分组收益随因子单调
+    let start = chrono::NaiveDate::from_ymd_opt(2023, 1, 2).unwrap().and_hms_opt(15, 0, 0).unwrap();
+    let (mut dts, mut symbols, mut factors, mut prices) = (vec![], vec![], vec![], vec![]);
+    for s in 0..6 {
+        let ret = |t: i64| 0.01 * (s as f64 * 1.3 + t as f64 * 0.7).sin();
+        let mut price = 100.0;
+        for t in 0..40 {
+            price *= 1.0 + ret(t);
+            dts.push(start + chrono::Duration::days(t));
+            symbols.push(format!("S{}", s));
+            factors.push(ret(t + 1));
+            prices.push(price);
+        }
+    }
+    let df = df!["dt" => &dts, "symbol" => &symbols, "factor" => &factors, "price" => &prices].unwrap();
+
+    let config = FactorConfig::new(3, vec![1, 5], FactorConfig::default().backtest).unwrap();
+    let result = evaluate_factor(df, &config).unwrap();
+
+    let ic = &result.ic_df.0;
+    assert_eq!(ic.height(), 39);
+    assert!(ic.column("ic").unwrap().f64().unwrap().min().unwrap() > 0.999);
+    assert!(ic.column("rank_ic").unwrap().f64().unwrap().min().unwrap() > 0.999);
+
+    let decay = &result.ic_decay_df.0;
+    let horizons: Vec<u32> = decay.column("horizon").unwrap().u32().unwrap().into_no_null_iter().collect();
+    assert_eq!(horizons, [1, 5]);
+    let ic_mean: Vec<f64> = decay.column("ic_mean").unwrap().f64().unwrap().into_no_null_iter().collect();
+    assert!(ic_mean[0] > ic_mean[1]);
+
+    let returns = &result.quantile_returns_df.0;
+    let names: Vec<&str> = returns.get_column_names().iter().map(|c| c.as_str()).collect();
+    assert_eq!(names, ["date", "q1", "q2", "q3", "long_short"]);
+    let total = |name: &str| returns.column(name).unwrap().f64().unwrap().sum().unwrap();
+    assert!(total("q1") < total("q2") && total("q2") < total("q3"));
+    assert!(total("long_short") > 0.0);
+
+    let performance = &result.quantile_performance_df.0;
+    assert_eq!(performance.height(), 4);
+    assert!(performance.column("夏普").is_ok());
+    // 只有各组合自身的指标截断警告，以组合名区分
+    assert!(result.warnings.iter().all(|w| w.kind == WarningKind::ClippedMetric));
+    let portfolio_named = |message: &str| {
+        ["q1", "q2", "q3", "long_short"].iter().any(|n| message.starts_with(&format!("{}: ", n)))
+    };
+    assert!(result.warnings.iter().all(|w| portfolio_named(&w.message)));
+
+    // 价格异常在各分组组合回测中只报告一次
+    prices[10] = 0.0;
+    let bad = df!["dt" => dts, "symbol" => symbols, "factor" => factors, "price" => prices].unwrap();
+    let result = evaluate_factor(bad, &config).unwrap();
+    let non_positive: Vec<_> =
+        result.warnings.iter().filter(|w| w.kind == WarningKind::NonPositivePrice).collect();
+    assert_eq!(non_positive.len(), 1);
+    assert_eq!(non_positive[0].symbol, "S0");
+
+    assert!(FactorConfig::new(1, vec![1], BacktestConfig::default()).is_err());
+}
+
+#[test]
+fn test_multiple_weight_columns_match_single_runs() {
+    // 未取整的权重，取整后部分 K 线变为空仓
+    let mut base = create_daily_df(30);
+    let weights: Vec<f64> =
+        base.column("weight").unwrap().f64().unwrap().into_no_null_iter().map(|w| w * 0.37).collect();
+    base.with_column(Column::new("weight".into(), weights)).unwrap();
+    let panel = base
+        .clone()
+        .lazy()
+        .with_columns([
+            col("weight").alias("trend"),
+            (-col("weight")).alias("reverse"),
+        ])
+        .drop(["weight"])
+        .collect()
+        .unwrap();
+    let config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    let strategies = ["trend".to_string(), "reverse".to_string()];
+    let multi = MultiBacktest::new(panel, &strategies, config.clone()).unwrap();
+    assert_eq!(multi.strategies(), ["trend", "reverse"]);
+    let result = multi.run_backtest().unwrap();
+
+    for (name, sign) in [("trend", 1.0), ("reverse", -1.0)] {
+        let df = base.clone().lazy().with_column(col("weight") * lit(sign)).collect().unwrap();
+        let single = BacktestEngine::new(df, config.clone()).unwrap().run_backtest().unwrap();
+        let multi = result.get(name).unwrap();
+        assert_eq!(
+            single.daily_metrics_df(None).unwrap(),
+            multi.daily_metrics_df(None).unwrap()
+        );
+        assert_eq!(single.trade_pairs_df(None).unwrap(), multi.trade_pairs_df(None).unwrap());
+        assert_eq!(single.portfolio_metrics.len(), multi.portfolio_metrics.len());
+        for (key, value) in &single.portfolio_metrics {
+            let other = multi.portfolio_metrics[key];
+            assert!(value == &other || (value.is_nan() && other.is_nan()), "{name} {key}");
+        }
+    }
+
+    let table = result.comparison_df().unwrap();
+    assert_eq!(table.height(), 2);
+    let sharpe = table.column("夏普").unwrap().f64().unwrap();
+    assert_eq!(sharpe.get(0), result.get("trend").unwrap().portfolio_metrics.get("夏普").copied());
+
+    let df = create_daily_df(5);
+    let duplicate = ["weight".to_string()];
+    assert!(MultiBacktest::new(df, &duplicate, config).is_err());
+}
+
+#[test]
+fn test_strategy_blend() {
+    let panel = create_daily_df(40)
+        .lazy()
+        .with_columns([
+            col("weight").alias("trend"),
+            (col("weight") * lit(0.5)).alias("half"),
+            (-col("weight")).alias("reverse"),
+        ])
+        .drop(["weight"])
+        .collect()
+        .unwrap();
+    let config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    let strategies = ["trend".to_string(), "half".to_string(), "reverse".to_string()];
+    let result = MultiBacktest::new(panel, &strategies, config).unwrap().run_backtest().unwrap();
+
+    // 固定权重的组合收益为各策略日收益的加权和
+    let fixed = result.blend(&BlendMethod::Fixed(vec![0.5, 0.3, 0.2])).unwrap();
+    let returns = &fixed.returns_df.0;
+    let column = |name: &str| returns.column(name).unwrap().f64().unwrap().clone();
+    let (trend, half, reverse, blend) =
+        (column("trend"), column("half"), column("reverse"), column("blend"));
+    for i in 0..returns.height() {
+        let expected = 0.5 * trend.get(i).unwrap()
+            + 0.3 * half.get(i).unwrap()
+            + 0.2 * reverse.get(i).unwrap();
+        assert!((blend.get(i).unwrap() - expected).abs() < 1e-12);
+    }
+    assert!(fixed.metrics.contains_key("夏普"));
+
+    let corr = &fixed.correlation_df.0;
+    assert_eq!(corr.shape(), (3, 4));
+    let trend_corr = corr.column("trend").unwrap().f64().unwrap();
+    assert!((trend_corr.get(0).unwrap() - 1.0).abs() < 1e-9);
+
+    // 滚动方式每日权重和为 1，窗口填满前等权
+    for method in [
+        BlendMethod::InverseVolatility { window: 10 },
+        BlendMethod::EqualRisk { window: 10 },
+        BlendMethod::MaxSharpe { window: 10 },
+    ] {
+        let blended = result.blend(&method).unwrap();
+        let weights = &blended.weights_df.0;
+        for i in 0..weights.height() {
+            let total: f64 = strategies
+                .iter()
+                .map(|s| weights.column(s).unwrap().f64().unwrap().get(i).unwrap())
+                .sum();
+            assert!((total - 1.0).abs() < 1e-9, "{:?}", method);
+        }
+        let first = weights.column("trend").unwrap().f64().unwrap().get(0).unwrap();
+        assert!((first - 1.0 / 3.0).abs() < 1e-12);
+    }
+
+    assert!(result.blend(&BlendMethod::Fixed(vec![1.0])).is_err());
+    assert!(BlendMethod::from_parts("fixed", None, 60).is_err());
+
+    // 低波动策略在波动率倒数与等风险方式下权重更高；只有共同日期参与组合
+    let days = 30;
+    let dates: Vec<String> = (1..=days + 1).map(|d| format!("2023-01-{:02}", d)).collect();
+    let high: Vec<f64> = (0..days).map(|t| ((t * 7 % 5) as f64 - 2.0) * 0.01).collect();
+    let low: Vec<f64> = (0..=days).map(|t| ((t * 3 % 4) as f64 - 1.5) * 0.002).collect();
+    let series = |high: &[f64]| {
+        vec![
+            ("high".to_string(), df!["date" => &dates[..days], "total" => high].unwrap()),
+            ("low".to_string(), df!["date" => &dates, "total" => &low].unwrap()),
+        ]
+    };
+    let weight = |blended: &BlendResult, name: &str, t: usize| {
+        blended.weights_df.0.column(name).unwrap().f64().unwrap().get(t).unwrap()
+    };
+    let window = 10;
+    for method in [
+        BlendMethod::InverseVolatility { window },
+        BlendMethod::EqualRisk { window },
+    ] {
+        let blended = blend_returns(&series(&high), &method, 252, Locale::Zh).unwrap();
+        assert_eq!(blended.returns_df.0.height(), days);
+        for t in window..days {
+            assert!(weight(&blended, "low", t) > weight(&blended, "high", t), "{:?}", method);
+        }
+
+        // 第 t 天的权重只使用此前的收益：改动第 t 天及之后的收益不影响当天权重
+        let t = 20;
+        let mut shocked = high.clone();
+        for r in &mut shocked[t..] {
+            *r *= 10.0;
+        }
+        let shocked_blend = blend_returns(&series(&shocked), &method, 252, Locale::Zh).unwrap();
+        for day in 0..=t {
+            assert_eq!(weight(&shocked_blend, "high", day), weight(&blended, "high", day));
+        }
+        assert_ne!(weight(&shocked_blend, "high", t + 1), weight(&blended, "high", t + 1));
+    }
+}
+
+#[test]
+fn test_vol_target_overlay() {
+    let lookback = 5;
+    let mut config = BacktestConfig::new(4, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    config.vol_target = Some(VolTarget { target: 0.1, lookback, max_leverage: 3.0 });
+    let result = BacktestEngine::new(create_daily_df(30), config.clone())
+        .unwrap()
+        .run_backtest()
+        .unwrap();
+
+    let raw = create_daily_df(30);
+    for symbol in ["AAA", "BBB"] {
+        let daily = &result.symbol_results[symbol].daily_metrics;
+        let raw_weights: Vec<f64> = raw
+            .clone()
+            .lazy()
+            .filter(col("symbol").eq(lit(symbol)))
+            .collect()
+            .unwrap()
+            .column("weight")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        for (i, m) in daily.iter().enumerate() {
+            // 前 lookback 天只有不足一个窗口的日收益
+            if i <= lookback {
+                assert_eq!(m.vol_scale, 1.0);
+            } else {
+                assert!(m.vol_scale > 0.0 && m.vol_scale <= 3.0);
+            }
+            let expected = (raw_weights[i] * m.vol_scale * 1e4).round() / 1e4;
+            assert!((m.weight - expected).abs() < 1e-9);
+        }
+    }
+
+    // 缩放系数只依赖此前的价格：改动最后 5 天的价格不影响之前的系数
+    let split = chrono::NaiveDate::from_ymd_opt(2023, 1, 27)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    let changed = create_daily_df(30)
+        .lazy()
+        .with_column(
+            when(col("dt").gt_eq(lit(split)))
+                .then(col("price") * lit(1.5))
+                .otherwise(col("price")),
+        )
+        .collect()
+        .unwrap();
+    let changed = BacktestEngine::new(changed, config).unwrap().run_backtest().unwrap();
+    let scales = |r: &BacktestResult| -> Vec<f64> {
+        r.symbol_results["AAA"].daily_metrics.iter().map(|m| m.vol_scale).collect()
+    };
+    assert_eq!(scales(&result)[..26], scales(&changed)[..26]);
+    assert_ne!(scales(&result)[26..], scales(&changed)[26..]);
+
+    // 截面策略按组合收益的波动率整体缩放，同一交易日各品种的系数相同
+    let mut cs = BacktestConfig::new(2, 0.0002, "cs".to_string(), 252, 1).unwrap();
+    cs.vol_target = Some(VolTarget { target: 0.1, lookback, max_leverage: 3.0 });
+    let result = BacktestEngine::new(create_daily_df(20), cs.clone()).unwrap().run_backtest().unwrap();
+    let aaa = &result.symbol_results["AAA"].daily_metrics;
+    let bbb = &result.symbol_results["BBB"].daily_metrics;
+    assert!(aaa.iter().zip(bbb).all(|(a, b)| a.vol_scale == b.vol_scale));
+    assert!(aaa.iter().any(|m| m.vol_scale != 1.0));
+    let df = create_daily_df(5).lazy().rename(["weight"], ["w"], true).collect().unwrap();
+    assert!(MultiBacktest::new(df, &["w".to_string()], cs).is_err());
+}
+
+#[test]
+fn test_exit_rules() {
+    let backtest = |weights: &[f64], prices: &[f64], rules: ExitRules| {
+        let start = chrono::NaiveDate::from_ymd_opt(2023, 1, 2).unwrap().and_hms_opt(15, 0, 0).unwrap();
+        let df = df![
+            "dt" => (0..weights.len()).map(|i| start + chrono::Duration::days(i as i64)).collect::<Vec<_>>(),
+            "symbol" => vec!["AAA"; weights.len()],
+            "weight" => weights,
+            "price" => prices,
+        ]
+        .unwrap();
+        let mut config = BacktestConfig::new(0, 0.0, "ts".to_string(), 252, 1).unwrap();
+        config.exit_rules = Some(rules);
+        BacktestEngine::new(df, config).unwrap().run_backtest().unwrap()
+    };
+    let events = |r: &BacktestResult| -> Vec<(String, f64)> {
+        r.symbol_results["AAA"]
+            .trade_pairs
+            .iter()
+            .map(|p| (p.event_sequence.clone(), p.close_price))
+            .collect()
+    };
+    let daily_weights = |r: &BacktestResult| -> Vec<f64> {
+        r.symbol_results["AAA"].daily_metrics.iter().map(|m| m.weight).collect()
+    };
+
+    // 多头止损后保持空仓直到权重归零，空头止盈后保持空仓
+    let weights = [1.0, 1.0, 1.0, 1.0, 1.0, 0.0, -1.0, -1.0, -1.0, -1.0];
+    let prices = [100.0, 101.0, 95.0, 96.0, 97.0, 98.0, 100.0, 97.0, 94.0, 95.0];
+    let rules = ExitRules { stop_loss: Some(300.0), take_profit: Some(500.0), ..Default::default() };
+    let result = backtest(&weights, &prices, rules);
+    assert_eq!(
+        events(&result),
+        [("开多 -> 止损".to_string(), 95.0), ("开空 -> 止盈".to_string(), 94.0)]
+    );
+    assert_eq!(daily_weights(&result), [1.0, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0, -1.0, 0.0, 0.0]);
+    // 多空占比按出场规则生效后的权重统计
+    assert_eq!((result.metrics.long_ratio, result.metrics.short_ratio), (0.2, 0.2));
+    let total: f64 = result.symbol_results["AAA"].daily_metrics.iter().map(|m| m.return_val).sum();
+    let expected = (101.0 / 100.0 - 1.0) + (95.0 / 101.0 - 1.0) - (97.0 / 100.0 - 1.0) - (94.0 / 97.0 - 1.0);
+    assert!((total - expected).abs() < 1e-9);
+
+    // 移动止损以持仓期间的最高价为基准，最大持仓 K 线数从开仓后计
+    let weights = [1.0; 6];
+    let prices = [100.0, 105.0, 103.0, 102.5, 104.0, 106.0];
+    let trailing = backtest(&weights, &prices, ExitRules { trailing_stop: Some(200.0), ..Default::default() });
+    assert_eq!(events(&trailing), [("开多 -> 移动止损".to_string(), 102.5)]);
+    let holding = backtest(&weights, &prices, ExitRules { max_holding_bars: Some(2), ..Default::default() });
+    assert_eq!(events(&holding), [("开多 -> 超时平仓".to_string(), 103.0)]);
+    assert_eq!(daily_weights(&holding), [1.0, 1.0, 0.0, 0.0, 0.0, 0.0]);
+
+    let mut config = BacktestConfig::new(0, 0.0, "ts".to_string(), 252, 1).unwrap();
+    config.exit_rules = Some(ExitRules { stop_loss: Some(-1.0), ..Default::default() });
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_capital_simulation() {
+    // 资金充足、每手 1 股且无手续费时，资金净值与权重口径的复利净值一致
+    let mut config = BacktestConfig::new(1, 0.0, "ts".to_string(), 252, 1).unwrap();
+    config.capital = Some(CapitalConfig { initial_capital: 1e10, ..Default::default() });
+    let result = BacktestEngine::new(create_daily_df(20), config).unwrap().run_backtest().unwrap();
+    let capital = result.capital.as_ref().unwrap();
+    let nav_df = &capital.nav_df.0;
+    assert_eq!(nav_df.height(), result.daily_ew_return_df.0.height());
+    let tracking = nav_df.column("tracking").unwrap().f64().unwrap();
+    // 组合日收益保留 4 位小数，偏离只来自该取整的累积
+    assert!(tracking.into_no_null_iter().all(|t| t.abs() < 1e-3));
+    assert!(capital.metrics.contains_key("夏普"));
+
+    // 小资金按 100 股一手取整，产生现金拖累与取整偏离，手续费按成交金额计
+    let mut config = BacktestConfig::new(1, 0.001, "ts".to_string(), 252, 1).unwrap();
+    let lot_sizes = [("AAA".to_string(), 100)].into_iter().collect();
+    config.capital = Some(CapitalConfig { initial_capital: 50_000.0, lot_size: 10, lot_sizes });
+    let result = BacktestEngine::new(create_daily_df(20), config).unwrap().run_backtest().unwrap();
+    let nav_df = &result.capital.as_ref().unwrap().nav_df.0;
+    let sum = |name: &str| nav_df.column(name).unwrap().f64().unwrap().sum().unwrap();
+    assert!(sum("rounding_gap") > 0.0);
+    assert!(sum("fees") > 0.0);
+    let last = nav_df.height() - 1;
+    let value = |name: &str| nav_df.column(name).unwrap().f64().unwrap().get(last).unwrap();
+    assert!((value("nav") - value("cash") - value("market_value")).abs() < 1e-6);
+    assert!((value("cum_fees") - sum("fees")).abs() < 1e-9);
+
+    // 保存后重新读取；配置了资金模拟但缺少净值表时读取失败
+    let dir = tempfile::tempdir().unwrap();
+    result.save(dir.path()).unwrap();
+    let loaded = BacktestResult::load(dir.path()).unwrap();
+    assert_eq!(loaded.capital.unwrap().nav_df.0, *nav_df);
+    std::fs::remove_file(dir.path().join("capital_nav.parquet")).unwrap();
+    assert!(matches!(BacktestResult::load(dir.path()), Err(CzscError::Validation(_))));
+
+    let mut config = BacktestConfig::new(1, 0.0, "ts".to_string(), 252, 1).unwrap();
+    config.capital = Some(CapitalConfig { lot_size: 0, ..Default::default() });
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_cancelled_backtest_is_interrupted() {
+    let cancel = AtomicBool::new(true);
+    for n_jobs in [1, 2] {
+        let config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, n_jobs).unwrap();
+        let engine = BacktestEngine::new(create_daily_df(10), config).unwrap();
+        assert!(matches!(engine.run_backtest_cancellable(&cancel), Err(CzscError::Interrupted)));
+    }
+
+    // 品种内部的逐 K 线循环同样检查取消标志
+    let bars = df![
+        "weight" => [1.0, 1.0, 0.0],
+        "volume" => [10i32, 10, 0],
+        "price" => [100.0, 90.0, 95.0],
+    ]
+    .unwrap();
+    let rules = ExitRules { stop_loss: Some(300.0), ..Default::default() };
+    assert!(matches!(
+        apply_exit_rules(bars.clone(), &rules, &cancel),
+        Err(CzscError::Interrupted)
+    ));
+    assert!(apply_exit_rules(bars, &rules, &AtomicBool::new(false)).is_ok());
+}
+
+#[derive(Default)]
+struct RecordingProgress {
+    updates: Mutex<Vec<(usize, usize, String)>>,
+}
+
+impl ProgressSink for RecordingProgress {
+    fn update(&self, done: usize, total: usize, symbol: &str) {
+        self.updates.lock().unwrap().push((done, total, symbol.to_string()));
+    }
+}
+
+#[test]
+fn test_progress_sink_receives_every_symbol() {
+    for n_jobs in [1, 2] {
+        let mut config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, n_jobs).unwrap();
+        config.progress = ProgressMode::None;
+        let engine = BacktestEngine::new(create_daily_df(10), config).unwrap();
+
+        let progress = RecordingProgress::default();
+        engine.run_backtest_with(&progress, &AtomicBool::new(false)).unwrap();
+
+        let mut updates = progress.updates.into_inner().unwrap();
+        updates.sort();
+        assert_eq!(
+            updates.iter().map(|u| u.0).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert!(updates.iter().all(|u| u.1 == 2));
+        let mut symbols: Vec<&str> = updates.iter().map(|u| u.2.as_str()).collect();
+        symbols.sort();
+        assert_eq!(symbols, ["AAA", "BBB"]);
+    }
+}
+
+#[test]
+fn test_data_anomalies_become_warnings() {
+    let mut config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    // 价格为 0 的 K 线保留原值，检查下游收益计算的容错
+    config.validation.non_positive_price = CheckAction::Warn;
+    let clean = BacktestEngine::new(create_daily_df(30), config.clone())
+        .unwrap()
+        .run_backtest()
+        .unwrap();
+    assert!(clean.warnings.iter().all(|w| w.kind == WarningKind::ClippedMetric));
+
+    let mut df = create_daily_df(30);
+    let mut weights: Vec<f64> = df.column("weight").unwrap().f64().unwrap().into_no_null_iter().collect();
+    let mut prices: Vec<f64> = df.column("price").unwrap().f64().unwrap().into_no_null_iter().collect();
+    weights[5] = f64::NAN;
+    prices[30 + 7] = 0.0;
+    // 空仓 K 线价格为 0：n1b 为无穷，0 × inf 得到 NaN
+    weights[15] = 0.0;
+    prices[15] = 0.0;
+    df.with_column(Column::new("weight".into(), weights)).unwrap();
+    df.with_column(Column::new("price".into(), prices)).unwrap();
+
+    let result = BacktestEngine::new(df, config).unwrap().run_backtest().unwrap();
+    let find = |kind: WarningKind, symbol: &str| {
+        result.warnings.iter().find(|w| w.kind == kind && w.symbol == symbol).cloned()
+    };
+
+    let dropped = find(WarningKind::NonFiniteWeight, "AAA").unwrap();
+    assert_eq!(dropped.dt, "2023-01-07 15:00:00");
+    let price = find(WarningKind::NonPositivePrice, "BBB").unwrap();
+    assert_eq!(price.dt, "2023-01-09 15:00:00");
+    assert!(find(WarningKind::NanReturn, "BBB").is_some());
+    let nan_return = find(WarningKind::NanReturn, "AAA").unwrap();
+    assert_eq!(nan_return.dt, "2023-01-17");
+    assert_eq!(result.symbol_results["AAA"].daily_metrics.len(), 29);
+    assert_eq!(result.symbol_results["BBB"].warnings.len(), 2);
+    for sr in result.symbol_results.values() {
+        assert!(sr.daily_metrics.iter().all(|m| m.return_val.is_finite() && m.short_return.is_finite()));
+    }
+    assert!(result.portfolio_metrics.values().all(|v| v.is_finite()));
+}
+
+#[test]
+fn test_validation_actions() {
+    // AAA 第 10 根 K 线价格跳变、第 3 根 K 线重复；CCC 只有一根 K 线
+    let mut df = create_daily_df(20);
+    let mut prices: Vec<f64> = df.column("price").unwrap().f64().unwrap().into_no_null_iter().collect();
+    prices[10] *= 3.0;
+    df.with_column(Column::new("price".into(), prices)).unwrap();
+    let mut single = df.slice(0, 1);
+    single.with_column(Column::new("symbol".into(), ["CCC"])).unwrap();
+    let df = df.vstack(&df.slice(3, 1)).unwrap().vstack(&single).unwrap();
+
+    let mut config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    config.progress = ProgressMode::None;
+    let engine = BacktestEngine::new(df.clone(), config.clone()).unwrap();
+    let kinds: Vec<(WarningKind, &str)> = engine
+        .validation_warnings()
+        .iter()
+        .map(|w| (w.kind, w.symbol.as_str()))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (WarningKind::Unsorted, "AAA"),
+            (WarningKind::DuplicateRows, "AAA"),
+            (WarningKind::PriceJump, "AAA"),
+            (WarningKind::SingleBar, "CCC"),
+        ]
+    );
+
+    let mut strict = config.clone();
+    strict.validation.duplicates = CheckAction::Error;
+    let err = BacktestEngine::new(df.clone(), strict).unwrap_err().to_string();
+    assert!(err.contains("AAA 2023-01-05 15:00:00: 2 rows share the same (dt, symbol)"), "{err}");
+
+    let mut fix = config;
+    fix.validation.duplicates = CheckAction::Fix;
+    fix.validation.price_jump = CheckAction::Fix;
+    fix.validation.single_bar = CheckAction::Fix;
+    fix.validation.weight_bound = CheckAction::Fix;
+    fix.validation.max_abs_weight = Some(0.5);
+    let result = BacktestEngine::new(df, fix).unwrap().run_backtest().unwrap();
+    assert!(!result.symbol_results.contains_key("CCC"));
+    assert_eq!(result.symbol_results["AAA"].daily_metrics.len(), 20);
+    let bound = result.warnings.iter().find(|w| w.kind == WarningKind::WeightOutOfBounds).unwrap();
+    assert!(bound.message.ends_with("clipped to the bound"));
+    assert!(result.warnings.iter().any(|w| w.kind == WarningKind::SingleBar && w.symbol == "CCC"));
+
+    // 被丢弃品种的警告只在结果级保存，读回后结果不变
+    let dir = tempfile::tempdir().unwrap();
+    result.save(dir.path()).unwrap();
+    let loaded = BacktestResult::load(dir.path()).unwrap();
+    assert_eq!(result.warnings, loaded.warnings);
+    assert!(!loaded.symbol_results.contains_key("CCC"));
+    for (symbol, sr) in &result.symbol_results {
+        let reloaded = &loaded.symbol_results[symbol];
+        assert_eq!(sr.warnings, reloaded.warnings);
+        assert_eq!(format!("{:?}", sr.daily_metrics), format!("{:?}", reloaded.daily_metrics));
+    }
+}
+
+#[test]
+fn test_dt_dtypes_are_normalised() {
+    let mut config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    config.progress = ProgressMode::None;
+    let run = |df: DataFrame| BacktestEngine::new(df, config.clone()).unwrap().run_backtest().unwrap();
+    let df = create_daily_df(30);
+    let expected = run(df.clone());
+    let expected_pairs = expected.trade_pairs_df(None).unwrap();
+
+    let with_dt = |expr: Expr| df.clone().lazy().with_column(expr.alias("dt")).collect().unwrap();
+    let shanghai = || col("dt").dt().replace_time_zone(
+        Some(TimeZone::opt_try_new(Some("Asia/Shanghai")).unwrap().unwrap()),
+        lit("raise"),
+        NonExistent::Raise,
+    );
+    let variants = [
+        with_dt(col("dt").cast(DataType::Datetime(TimeUnit::Milliseconds, None))),
+        with_dt(shanghai().dt().convert_time_zone(TimeZone::UTC)),
+        with_dt(col("dt").dt().strftime("%Y-%m-%dT%H:%M:%S")),
+        with_dt(shanghai().dt().strftime("%Y-%m-%dT%H:%M:%S%:z")),
+    ];
+    for variant in variants {
+        let result = run(variant.clone());
+        assert!(expected_pairs.equals_missing(&result.trade_pairs_df(None).unwrap()));
+        assert_eq!(expected.metrics.total_trades, result.metrics.total_trades);
+        // 惰性数据走同样的转换
+        let lazy = BacktestEngine::from_lazy(variant.lazy(), config.clone())
+            .unwrap()
+            .run_backtest()
+            .unwrap();
+        assert!(expected_pairs.equals_missing(&lazy.trade_pairs_df(None).unwrap()));
+    }
+
+    // 纯日期与日线数据的日度结果一致
+    let dates = run(with_dt(col("dt").cast(DataType::Date)));
+    assert_eq!(dates.metrics.start_date, expected.metrics.start_date);
+    assert!(expected.daily_metrics_df(None).unwrap().equals_missing(&dates.daily_metrics_df(None).unwrap()));
+
+    let mut bad = with_dt(col("dt").dt().strftime("%Y-%m-%d %H:%M:%S"));
+    let mut raw: Vec<String> =
+        bad.column("dt").unwrap().str().unwrap().into_no_null_iter().map(String::from).collect();
+    raw[4] = "2023-13-40 25:00:00".to_string();
+    bad.with_column(Column::new("dt".into(), raw)).unwrap();
+    let err = BacktestEngine::new(bad.clone(), config.clone()).unwrap_err().to_string();
+    assert!(err.contains("column dt: cannot parse \"2023-13-40 25:00:00\""), "{err}");
+    // 惰性数据在创建引擎时同样报告第一个出错的值
+    let err = BacktestEngine::from_lazy(bad.lazy(), config.clone()).unwrap_err().to_string();
+    assert!(err.contains("column dt: cannot parse \"2023-13-40 25:00:00\""), "{err}");
+}
+
+#[test]
+fn test_night_session_trading_day() {
+    // 周四日盘、夜盘至周一日盘、夜盘，再到周二日盘
+    let bars = [
+        ("2023-01-05 09:00:00", "2023-01-05"),
+        ("2023-01-05 14:00:00", "2023-01-05"),
+        ("2023-01-05 21:00:00", "2023-01-06"),
+        ("2023-01-05 23:00:00", "2023-01-06"),
+        ("2023-01-06 01:00:00", "2023-01-06"),
+        ("2023-01-06 09:00:00", "2023-01-06"),
+        ("2023-01-06 14:00:00", "2023-01-06"),
+        ("2023-01-06 21:00:00", "2023-01-09"),
+        ("2023-01-06 23:00:00", "2023-01-09"),
+        ("2023-01-07 01:00:00", "2023-01-09"),
+        ("2023-01-09 09:00:00", "2023-01-09"),
+        ("2023-01-09 14:00:00", "2023-01-09"),
+        ("2023-01-09 21:00:00", "2023-01-10"),
+        ("2023-01-10 09:00:00", "2023-01-10"),
+    ];
+    let n = bars.len();
+    let df = df![
+        "dt" => bars.iter().map(|b| b.0).collect::<Vec<_>>(),
+        "symbol" => vec!["RB"; n],
+        "weight" => (0..n).map(|i| [0.0, 0.5, 1.0, -0.5][i % 4]).collect::<Vec<_>>(),
+        "price" => (0..n).map(|i| 3800.0 + i as f64 * 5.0).collect::<Vec<_>>(),
+        "trading_day" => bars.iter().map(|b| b.1).collect::<Vec<_>>(),
+    ]
+    .unwrap();
+
+    let mut config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    config.progress = ProgressMode::None;
+    let dates = |rule: TradingDay| {
+        let mut config = config.clone();
+        config.trading_day = rule;
+        let result = BacktestEngine::new(df.clone(), config).unwrap().run_backtest().unwrap();
+        let dates: Vec<String> = result.symbol_results["RB"]
+            .daily_metrics
+            .iter()
+            .map(|m| m.date.to_string())
+            .collect();
+        (dates, result)
+    };
+
+    let (calendar, _) = dates(TradingDay::Calendar);
+    assert_eq!(calendar, ["2023-01-05", "2023-01-06", "2023-01-07", "2023-01-09", "2023-01-10"]);
+    let (cutoff, by_cutoff) = dates(TradingDay::Cutoff { hour: 18 });
+    assert_eq!(cutoff, ["2023-01-05", "2023-01-06", "2023-01-09", "2023-01-10"]);
+    let (column, by_column) = dates(TradingDay::Column { name: "trading_day".to_string() });
+    assert_eq!(column, cutoff);
+    assert!(by_cutoff.daily_ew_return_df.0.equals_missing(&by_column.daily_ew_return_df.0));
+
+    // 交易日列中的空值或无法解析的值报错，并给出出错的行号
+    let mut column_config = config.clone();
+    column_config.trading_day = TradingDay::Column { name: "trading_day".to_string() };
+    for (value, row) in [(None, 3usize), (Some("not a date"), 5)] {
+        let mut days: Vec<Option<&str>> = bars.iter().map(|b| Some(b.1)).collect();
+        days[row] = value;
+        let mut bad = df.clone();
+        bad.with_column(Column::new("trading_day".into(), days)).unwrap();
+        let message = match BacktestEngine::new(bad.clone(), column_config.clone()) {
+            Err(CzscError::Validation(message)) => message,
+            other => panic!("expected a validation error, got {:?}", other.map(|_| ())),
+        };
+        assert!(message.contains(&format!("row {}", row)), "{}", message);
+        assert!(matches!(
+            BacktestEngine::from_lazy(bad.lazy(), column_config.clone()),
+            Err(CzscError::Validation(_))
+        ));
+    }
+
+    let config = BacktestConfig::from_toml_str("trading_day = { rule = \"cutoff\", hour = 18 }").unwrap();
+    assert_eq!(config.trading_day, TradingDay::Cutoff { hour: 18 });
+    assert!(BacktestConfig::from_toml_str("trading_day = { rule = \"cutoff\", hour = 24 }").is_err());
+}
+
+#[test]
+fn test_result_save_and_load() {
+    let config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    let mut result = BacktestEngine::new(create_daily_df(30), config)
+        .unwrap()
+        .run_backtest()
+        .unwrap();
+    // 零波动时比率类指标为无穷，保存后须原样读回
+    let mut keys: Vec<String> = result.portfolio_metrics.keys().cloned().collect();
+    keys.sort();
+    result.portfolio_metrics.insert(keys[0].clone(), f64::INFINITY);
+    result.portfolio_metrics.insert(keys[1].clone(), f64::NEG_INFINITY);
+    result.portfolio_metrics.insert(keys[2].clone(), f64::NAN);
+    result.metrics.sharpe_ratio = f64::INFINITY;
+    result.metrics.calmar_ratio = f64::NEG_INFINITY;
+
+    let dir = tempfile::tempdir().unwrap();
+    result.save(dir.path()).unwrap();
+    let loaded = BacktestResult::load(dir.path()).unwrap();
+
+    assert!(result.daily_ew_return_df.0.equals_missing(&loaded.daily_ew_return_df.0));
+    assert_eq!(result.config.weight_type, loaded.config.weight_type);
+    assert_eq!(result.portfolio_metrics.len(), loaded.portfolio_metrics.len());
+    for (key, value) in &result.portfolio_metrics {
+        let reloaded = loaded.portfolio_metrics[key];
+        assert!(value == &reloaded || (value.is_nan() && reloaded.is_nan()), "{key}");
+    }
+    assert_eq!(format!("{:?}", result.metrics), format!("{:?}", loaded.metrics));
+    assert_eq!(result.warnings, loaded.warnings);
+
+    assert_eq!(result.symbol_results.len(), loaded.symbol_results.len());
+    for (symbol, sr) in &result.symbol_results {
+        let reloaded = &loaded.symbol_results[symbol];
+        assert_eq!(format!("{:?}", sr.daily_metrics), format!("{:?}", reloaded.daily_metrics));
+        assert_eq!(format!("{:?}", sr.trade_pairs), format!("{:?}", reloaded.trade_pairs));
+    }
+}
+
+#[test]
+fn test_html_report_is_self_contained() {
+    let config = BacktestConfig::new(1, 0.0002, "ts".to_string(), 252, 1).unwrap();
+    let result = BacktestEngine::new(create_daily_df(40), config)
+        .unwrap()
+        .run_backtest()
+        .unwrap();
+
+    let html = result.to_html().unwrap();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert_eq!(html.matches("<svg").count(), 6);
+    assert!(!html.contains("<script"));
+    assert!(!html.contains("src=\"http"));
+}
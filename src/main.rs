@@ -0,0 +1,314 @@
+use anyhow::{bail, Context, Result};
+use clap::{Parser, ValueEnum};
+use polars::prelude::*;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use weight_backtest_pyo3::attribution::Period;
+use weight_backtest_pyo3::capital::CapitalConfig;
+use weight_backtest_pyo3::blend::{BlendMethod, BlendResult};
+use weight_backtest_pyo3::config::BacktestConfig;
+use weight_backtest_pyo3::exits::ExitRules;
+use weight_backtest_pyo3::engine::{BacktestEngine, BacktestResult};
+use weight_backtest_pyo3::multi::MultiBacktest;
+use weight_backtest_pyo3::progress::ProgressMode;
+use weight_backtest_pyo3::source::scan_files;
+use weight_backtest_pyo3::vol_target::VolTarget;
+
+/// 权重回测命令行工具
+#[derive(Parser, Debug)]
+#[command(name = "czsc_rs", version, about = "Run a weight backtest from a data file")]
+struct Cli {
+    /// 权重数据文件 (feather/ipc/parquet/csv)，需包含 dt, symbol, weight, price 列
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// 结果输出目录
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// 回测配置文件 (TOML/JSON)，命令行参数优先
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// 权重小数位数
+    #[arg(long)]
+    digits: Option<usize>,
+
+    /// 单边交易费率
+    #[arg(long)]
+    fee_rate: Option<f32>,
+
+    /// 权重类型：ts 或 cs
+    #[arg(long)]
+    weight_type: Option<String>,
+
+    /// 年化交易天数
+    #[arg(long)]
+    yearly_days: Option<usize>,
+
+    /// 并行线程数
+    #[arg(long)]
+    n_jobs: Option<usize>,
+
+    /// 指标键语言：zh 或 en
+    #[arg(long)]
+    locale: Option<String>,
+
+    /// 多个权重列（策略）名，逗号分隔；每个策略的结果写入输出目录下的同名子目录，
+    /// 并在输出目录写出各策略指标的对比表
+    #[arg(long, value_delimiter = ',')]
+    weight_columns: Vec<String>,
+
+    /// 年化目标波动率，设置后按波动率目标缩放权重
+    #[arg(long)]
+    vol_target: Option<f64>,
+
+    /// 波动率目标的回看交易日数
+    #[arg(long, requires = "vol_target")]
+    vol_lookback: Option<usize>,
+
+    /// 波动率目标的缩放系数上限
+    #[arg(long, requires = "vol_target")]
+    max_leverage: Option<f64>,
+
+    /// 止损 BP 数
+    #[arg(long)]
+    stop_loss: Option<f64>,
+
+    /// 止盈 BP 数
+    #[arg(long)]
+    take_profit: Option<f64>,
+
+    /// 移动止损 BP 数
+    #[arg(long)]
+    trailing_stop: Option<f64>,
+
+    /// 最大持仓 K 线数
+    #[arg(long)]
+    max_holding_bars: Option<usize>,
+
+    /// 资金模拟的初始资金，设置后按整手与现金额外记账
+    #[arg(long)]
+    initial_capital: Option<f64>,
+
+    /// 资金模拟的默认每手股数
+    #[arg(long)]
+    lot_size: Option<u64>,
+
+    /// 多策略组合方式：inverse_vol、equal_risk 或 max_sharpe，需配合 --weight-columns 使用
+    #[arg(long)]
+    blend: Option<String>,
+
+    /// 多策略组合的滚动窗口（交易日数）
+    #[arg(long, default_value_t = 60)]
+    blend_window: usize,
+
+    /// 输入为按 symbol 分区的 hive 目录
+    #[arg(long)]
+    hive_partitioned: bool,
+
+    /// 不显示进度条
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// 表格输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Csv)]
+    format: OutputFormat,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Csv,
+    Parquet,
+}
+
+impl OutputFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Parquet => "parquet",
+        }
+    }
+}
+
+impl Cli {
+    fn backtest_config(&self) -> Result<BacktestConfig> {
+        let mut config = match &self.config {
+            Some(path) => BacktestConfig::from_file(path)
+                .with_context(|| format!("loading config file {}", path.display()))?,
+            None => BacktestConfig::default(),
+        };
+
+        if let Some(digits) = self.digits {
+            config.digits = digits;
+        }
+        if let Some(fee_rate) = self.fee_rate {
+            config.fee_rate = fee_rate;
+        }
+        if let Some(weight_type) = &self.weight_type {
+            config.weight_type = weight_type.parse()?;
+        }
+        if let Some(yearly_days) = self.yearly_days {
+            config.yearly_days = yearly_days;
+        }
+        if let Some(n_jobs) = self.n_jobs {
+            config.n_jobs = n_jobs;
+        }
+        if let Some(locale) = &self.locale {
+            config.locale = locale.parse()?;
+        }
+        if let Some(target) = self.vol_target {
+            config.vol_target.get_or_insert_with(VolTarget::default).target = target;
+        }
+        if let Some(vol_target) = config.vol_target.as_mut() {
+            if let Some(lookback) = self.vol_lookback {
+                vol_target.lookback = lookback;
+            }
+            if let Some(max_leverage) = self.max_leverage {
+                vol_target.max_leverage = max_leverage;
+            }
+        }
+        if self.stop_loss.is_some()
+            || self.take_profit.is_some()
+            || self.trailing_stop.is_some()
+            || self.max_holding_bars.is_some()
+        {
+            let rules = config.exit_rules.get_or_insert_with(ExitRules::default);
+            rules.stop_loss = self.stop_loss.or(rules.stop_loss);
+            rules.take_profit = self.take_profit.or(rules.take_profit);
+            rules.trailing_stop = self.trailing_stop.or(rules.trailing_stop);
+            rules.max_holding_bars = self.max_holding_bars.or(rules.max_holding_bars);
+        }
+        if self.initial_capital.is_some() || self.lot_size.is_some() {
+            let capital = config.capital.get_or_insert_with(CapitalConfig::default);
+            if let Some(initial_capital) = self.initial_capital {
+                capital.initial_capital = initial_capital;
+            }
+            if let Some(lot_size) = self.lot_size {
+                capital.lot_size = lot_size;
+            }
+        }
+        if self.quiet {
+            config.progress = ProgressMode::None;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+fn write_table(df: &mut DataFrame, dir: &Path, name: &str, format: OutputFormat) -> Result<()> {
+    let path = dir.join(format!("{}.{}", name, format.extension()));
+    let file = File::create(&path).with_context(|| format!("creating {}", path.display()))?;
+    match format {
+        OutputFormat::Csv => CsvWriter::new(file).finish(df)?,
+        OutputFormat::Parquet => {
+            ParquetWriter::new(file).finish(df)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_result(result: &BacktestResult, dir: &Path, format: OutputFormat) -> Result<()> {
+    std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+
+    let metrics_path = dir.join("metrics.json");
+    let metrics_file = File::create(&metrics_path)
+        .with_context(|| format!("creating {}", metrics_path.display()))?;
+    serde_json::to_writer_pretty(metrics_file, &result.portfolio_metrics)?;
+
+    let warnings_path = dir.join("warnings.json");
+    let warnings_file = File::create(&warnings_path)
+        .with_context(|| format!("creating {}", warnings_path.display()))?;
+    serde_json::to_writer_pretty(warnings_file, &result.warnings)?;
+
+    let config_path = dir.join("config.toml");
+    std::fs::write(&config_path, result.config.to_toml_string()?)
+        .with_context(|| format!("writing {}", config_path.display()))?;
+
+    result.save_html(&dir.join("report.html"))?;
+
+    let mut daily_returns = result.daily_ew_return_df.0.clone();
+    write_table(&mut daily_returns, dir, "daily_returns", format)?;
+    write_table(&mut result.daily_metrics_df(None)?, dir, "daily_metrics", format)?;
+    write_table(&mut result.trade_pairs_df(None)?, dir, "trade_pairs", format)?;
+    write_table(&mut result.symbol_performance_df()?, dir, "symbol_performance", format)?;
+    write_table(&mut result.leg_returns_df()?, dir, "leg_returns", format)?;
+    write_table(&mut result.leg_performance_df()?, dir, "leg_performance", format)?;
+    write_table(&mut result.exposure_df()?, dir, "exposure", format)?;
+    write_table(&mut result.attribution_summary()?, dir, "attribution", format)?;
+    write_table(&mut result.symbol_attribution_df(None)?, dir, "symbol_attribution", format)?;
+    write_table(&mut result.period_attribution_df(Period::Month)?, dir, "monthly_attribution", format)?;
+
+    if let Some(capital) = &result.capital {
+        let capital_path = dir.join("capital_metrics.json");
+        let capital_file = File::create(&capital_path)
+            .with_context(|| format!("creating {}", capital_path.display()))?;
+        serde_json::to_writer_pretty(capital_file, &capital.metrics)?;
+        write_table(&mut capital.nav_df.0.clone(), dir, "capital_nav", format)?;
+    }
+    Ok(())
+}
+
+fn write_blend(blend: &BlendResult, dir: &Path, format: OutputFormat) -> Result<()> {
+    std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+
+    let metrics_path = dir.join("metrics.json");
+    let metrics_file = File::create(&metrics_path)
+        .with_context(|| format!("creating {}", metrics_path.display()))?;
+    serde_json::to_writer_pretty(metrics_file, &blend.metrics)?;
+
+    write_table(&mut blend.returns_df.0.clone(), dir, "returns", format)?;
+    write_table(&mut blend.weights_df.0.clone(), dir, "weights", format)?;
+    write_table(&mut blend.correlation_df.0.clone(), dir, "correlation", format)?;
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    // 通过 RUST_LOG=debug 查看回测过程中的调试日志
+    env_logger::init();
+
+    let cli = Cli::parse();
+    // 通配符路径交给 Polars 展开，只检查普通文件是否存在
+    let is_glob = cli.input.to_string_lossy().contains(['*', '?', '[']);
+    if !cli.hive_partitioned && !is_glob && !cli.input.exists() {
+        bail!("input file {} does not exist", cli.input.display());
+    }
+
+    if cli.blend.is_some() && cli.weight_columns.is_empty() {
+        bail!("--blend requires --weight-columns");
+    }
+    if cli.blend.is_some() && cli.weight_columns.iter().any(|c| c == "blend") {
+        bail!("--blend writes to the \"blend\" directory, rename the weight column \"blend\"");
+    }
+
+    let config = cli.backtest_config()?;
+    let inputs = std::slice::from_ref(&cli.input);
+    if !cli.weight_columns.is_empty() {
+        let df = scan_files(inputs, cli.hive_partitioned)?.collect()?;
+        let result = MultiBacktest::new(df, &cli.weight_columns, config)?.run_backtest()?;
+        for (name, strategy) in &result.results {
+            write_result(strategy, &cli.output.join(name), cli.format)?;
+        }
+        write_table(&mut result.comparison_df()?, &cli.output, "comparison", cli.format)?;
+        if let Some(method) = &cli.blend {
+            let blend = result.blend(&BlendMethod::from_parts(method, None, cli.blend_window)?)?;
+            write_blend(&blend, &cli.output.join("blend"), cli.format)?;
+        }
+        println!(
+            "Backtest results for {} strategies written to {}",
+            result.results.len(),
+            cli.output.display()
+        );
+        return Ok(());
+    }
+
+    let engine = BacktestEngine::from_files(inputs, cli.hive_partitioned, config)?;
+    let result = engine.run_backtest()?;
+    write_result(&result, &cli.output, cli.format)?;
+
+    if !result.warnings.is_empty() {
+        eprintln!("{} warnings, see warnings.json", result.warnings.len());
+    }
+    println!("Backtest results written to {}", cli.output.display());
+    Ok(())
+}
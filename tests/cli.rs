@@ -0,0 +1,58 @@
+use polars::prelude::*;
+use std::fs::File;
+use std::path::Path;
+use std::process::Command;
+
+// 按品种写出多个 parquet 文件
+fn write_parts(dir: &Path, days: usize) {
+    for (k, symbol) in ["AAA", "BBB"].iter().enumerate() {
+        let mut df = df![
+            "dt" => (0..days).map(|i| format!("2023-01-{:02} 15:00:00", i + 1)).collect::<Vec<_>>(),
+            "symbol" => vec![*symbol; days],
+            "weight" => (0..days).map(|i| [0.0, 0.5, 1.0, -0.5][(i + k) % 4]).collect::<Vec<_>>(),
+            "price" => (0..days).map(|i| 100.0 + (i * (k + 1)) as f64).collect::<Vec<_>>(),
+        ]
+        .unwrap();
+        let file = File::create(dir.join(format!("{symbol}.parquet"))).unwrap();
+        ParquetWriter::new(file).finish(&mut df).unwrap();
+    }
+}
+
+fn czsc_rs(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_czsc_rs")).args(args).output().unwrap()
+}
+
+#[test]
+fn test_cli_writes_results_for_glob_input() {
+    let dir = tempfile::tempdir().unwrap();
+    write_parts(dir.path(), 20);
+    let input = dir.path().join("*.parquet");
+    let output = dir.path().join("out");
+
+    let run = czsc_rs(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--output",
+        output.to_str().unwrap(),
+        "--quiet",
+    ]);
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+
+    for name in ["metrics.json", "config.toml", "report.html", "daily_returns.csv", "trade_pairs.csv"] {
+        assert!(output.join(name).exists(), "{name}");
+    }
+    let metrics: serde_json::Value =
+        serde_json::from_reader(File::open(output.join("metrics.json")).unwrap()).unwrap();
+    assert_eq!(metrics["品种数量"], 2.0);
+}
+
+#[test]
+fn test_cli_rejects_missing_input() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("missing.parquet");
+    let output = dir.path().join("out");
+
+    let run = czsc_rs(&["--input", input.to_str().unwrap(), "--output", output.to_str().unwrap()]);
+    assert!(!run.status.success());
+    assert!(String::from_utf8_lossy(&run.stderr).contains("does not exist"));
+}